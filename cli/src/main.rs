@@ -0,0 +1,381 @@
+//! Runs a ROM without a browser and dumps the screen it ends up on, for smoke
+//! testing ROMs and emulator changes.
+mod screen;
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::process;
+
+use chip8_core::games::read_rom_or_game;
+use chip8_core::{
+    disassemble, Chip8, Engine, FileSink, FlickerFilter, Movie, QuirkProfile, Syntax, TimingMode,
+};
+use screen::{Format, Screen};
+
+const USAGE: &str = "\
+Usage: chip8 [OPTIONS] <ROM>
+
+Runs <ROM>, a ROM file, an Octo source file ending in .8o or the title of a
+bundled game, and prints the screen it ends up on.
+
+Options:
+  --frames <N>          Frames to run for at 60 frames per second [default: 600,
+                        or the length of the movie]
+  --press <KEY@FRAME>   Press KEY (0-F) at FRAME, for 5 frames or for N with
+                        KEY@FRAME+N. Can be repeated
+  --movie <FILE>        Play back the movie in FILE, recorded with the same ROM,
+                        instead of pressing keys
+  --record <FILE>       Record the keys pressed into a movie in FILE
+  --trace <FILE>        Write what every instruction does to FILE, one event per
+                        line
+  --format <FORMAT>     ascii, pbm or png [default: ascii]
+  --output <FILE>       Write the screen to FILE instead of stdout
+  --quirks <PROFILE>    cosmac-vip, chip48, schip-legacy, schip-modern or xo-chip
+  --speed <N>           Instructions per second [default: 600]
+  --vip-timing          Time instructions like the COSMAC VIP instead
+  --recompile           Run the ROM with the recompiler, which is faster at high
+                        speeds and ends up on the same screen
+  --seed <N>            Seed of the random number generator [default: 1]
+  --disassemble <SYNTAX>
+                        Print the disassembly of <ROM> in octo or cowgod syntax
+                        instead of running it
+  -h, --help            Print this message
+
+Stops early when the ROM exits. Exits with status 1 if it halts on a fault.";
+
+// How long a key is held down when a press doesn't say.
+const DEFAULT_HOLD_FRAMES: u32 = 5;
+
+/// A key held down from `frame` for `hold` frames.
+#[derive(Debug, PartialEq, Eq)]
+struct Press {
+    key: usize,
+    frame: u32,
+    hold: u32,
+}
+
+impl Press {
+    /// Parses `KEY@FRAME` or `KEY@FRAME+HOLD`, with KEY a hex digit.
+    fn parse(arg: &str) -> Result<Press, String> {
+        let invalid = || format!("invalid key press '{}', expected KEY@FRAME", arg);
+        let (key, time) = split_once(arg, '@').ok_or_else(invalid)?;
+        let (frame, hold) = match split_once(time, '+') {
+            Some((frame, hold)) => (frame, hold.parse().map_err(|_| invalid())?),
+            None => (time, DEFAULT_HOLD_FRAMES),
+        };
+        let key = usize::from_str_radix(key, 16).map_err(|_| invalid())?;
+        if key > 0xF {
+            return Err(invalid());
+        }
+        Ok(Press {
+            key,
+            frame: frame.parse().map_err(|_| invalid())?,
+            hold,
+        })
+    }
+}
+
+fn split_once(s: &str, separator: char) -> Option<(&str, &str)> {
+    let index = s.find(separator)?;
+    Some((&s[..index], &s[index + 1..]))
+}
+
+#[derive(Debug)]
+struct Options {
+    rom: String,
+    frames: Option<u32>,
+    presses: Vec<Press>,
+    movie: Option<String>,
+    record: Option<String>,
+    trace: Option<String>,
+    format: Format,
+    output: Option<String>,
+    profile: Option<QuirkProfile>,
+    speed: Option<u32>,
+    vip_timing: bool,
+    recompile: bool,
+    seed: u32,
+    disassemble: Option<Syntax>,
+}
+
+impl Options {
+    /// Returns `Ok(None)` when help was asked for.
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+        let mut rom = None;
+        let mut options = Options {
+            rom: String::new(),
+            frames: None,
+            presses: Vec::new(),
+            movie: None,
+            record: None,
+            trace: None,
+            format: Format::Ascii,
+            output: None,
+            profile: None,
+            speed: None,
+            vip_timing: false,
+            recompile: false,
+            seed: 1,
+            disassemble: None,
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "--frames" => options.frames = Some(parse_number(&value()?)?),
+                "--press" => options.presses.push(Press::parse(&value()?)?),
+                "--movie" => options.movie = Some(value()?),
+                "--record" => options.record = Some(value()?),
+                "--trace" => options.trace = Some(value()?),
+                "--format" => {
+                    let format = value()?;
+                    options.format = Format::parse(&format)
+                        .ok_or_else(|| format!("unknown format '{}'", format))?;
+                }
+                "--output" => options.output = Some(value()?),
+                "--quirks" => {
+                    let name = value()?;
+                    let profile = name
+                        .parse()
+                        .map_err(|error| format!("{} '{}'", error, name))?;
+                    options.profile = Some(profile);
+                }
+                "--speed" => options.speed = Some(parse_number(&value()?)?),
+                "--vip-timing" => options.vip_timing = true,
+                "--recompile" => options.recompile = true,
+                "--seed" => options.seed = parse_number(&value()?)?,
+                "--disassemble" => options.disassemble = Some(parse_syntax(&value()?)?),
+                _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+                _ if rom.is_none() => rom = Some(arg),
+                _ => return Err(format!("unexpected argument '{}'", arg)),
+            }
+        }
+        options.rom = rom.ok_or("missing <ROM>")?;
+        if options.movie.is_some() && (!options.presses.is_empty() || options.record.is_some()) {
+            return Err("--movie can't be combined with --press or --record".to_string());
+        }
+        Ok(Some(options))
+    }
+}
+
+fn parse_number(value: &str) -> Result<u32, String> {
+    value
+        .parse()
+        .map_err(|_| format!("'{}' is not a number", value))
+}
+
+fn parse_syntax(name: &str) -> Result<Syntax, String> {
+    match name {
+        "octo" => Ok(Syntax::Octo),
+        "cowgod" => Ok(Syntax::Cowgod),
+        _ => Err(format!("unknown syntax '{}'", name)),
+    }
+}
+
+/// Prints the disassembly of the ROM file or bundled game at `rom`.
+fn print_disassembly(rom: &str, syntax: Syntax) -> Result<(), Box<dyn Error>> {
+    let (code, _) = read_rom_or_game(rom, None).map_err(|error| format!("{}: {}", rom, error))?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    write!(out, "{}", disassemble(&code, 0x200).display(syntax))?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Runs the ROM, returning whether it halted on a fault.
+fn run(options: &Options) -> Result<bool, Box<dyn Error>> {
+    let mut chip8 = Chip8::with_seed(options.seed);
+    chip8
+        .load_rom_or_game(&options.rom, options.profile)
+        .map_err(|error| format!("{}: {}", options.rom, error))?;
+    // Dump exactly what the ROM drew, without flicker smoothing.
+    chip8.set_flicker_filter(FlickerFilter::Off);
+    if let Some(speed) = options.speed {
+        chip8.set_cpu_speed(speed);
+    }
+    if options.vip_timing {
+        chip8.set_timing_mode(TimingMode::CosmacVip);
+    }
+    if options.recompile {
+        chip8.set_engine(Engine::Recompiler);
+    }
+    let mut frames = options.frames.unwrap_or(600);
+    if let Some(path) = &options.movie {
+        let movie = Movie::from_bytes(&fs::read(path)?)?;
+        chip8.play_movie(&movie)?;
+        frames = options.frames.unwrap_or_else(|| movie.frames());
+    }
+    if options.record.is_some() {
+        chip8.start_recording();
+    }
+    if let Some(path) = &options.trace {
+        chip8.set_trace_sink(FileSink::create(path)?);
+    }
+
+    let mut halted = false;
+    for frame in 0..frames {
+        // Releases go first, so a key can be pressed again on the frame it is released.
+        for press in &options.presses {
+            if press.frame + press.hold == frame {
+                chip8.release_key(press.key)?;
+            }
+        }
+        for press in &options.presses {
+            if press.frame == frame {
+                chip8.press_key(press.key)?;
+            }
+        }
+        if let Err(error) = chip8.run_frame() {
+            eprintln!("chip8: halted in frame {}: {}", frame, error);
+            halted = true;
+            break;
+        }
+        if chip8.has_exited() {
+            break;
+        }
+    }
+    if let Some(path) = &options.record {
+        // Recording is on, so there is a movie.
+        if let Some(movie) = chip8.stop_recording() {
+            fs::write(path, movie.to_bytes())?;
+        }
+    }
+    if let Some(mut sink) = chip8.take_trace_sink() {
+        sink.flush()?;
+    }
+
+    let screen = Screen::capture(&chip8);
+    match &options.output {
+        Some(path) => {
+            let mut out = BufWriter::new(File::create(path)?);
+            screen.write(options.format, &mut out)?;
+            out.flush()?;
+        }
+        None => {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            screen.write(options.format, &mut out)?;
+            out.flush()?;
+        }
+    }
+    Ok(halted)
+}
+
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(error) => {
+            eprintln!("chip8: {}\n\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Some(syntax) = options.disassemble {
+        if let Err(error) = print_disassembly(&options.rom, syntax) {
+            eprintln!("chip8: {}", error);
+            process::exit(2);
+        }
+        return;
+    }
+    match run(&options) {
+        Ok(false) => {}
+        Ok(true) => process::exit(1),
+        Err(error) => {
+            eprintln!("chip8: {}", error);
+            process::exit(2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::games::DEFAULT_QUIRKS;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn parses_key_presses() {
+        assert_eq!(
+            Press::parse("5@120"),
+            Ok(Press {
+                key: 5,
+                frame: 120,
+                hold: DEFAULT_HOLD_FRAMES
+            })
+        );
+        assert_eq!(
+            Press::parse("a@3+10"),
+            Ok(Press {
+                key: 0xA,
+                frame: 3,
+                hold: 10
+            })
+        );
+        assert!(Press::parse("10@3").is_err());
+        assert!(Press::parse("5").is_err());
+        assert!(Press::parse("5@x").is_err());
+    }
+
+    #[test]
+    fn parses_options() {
+        let options = Options::parse(args(&[
+            "--frames", "60", "--press", "5@1", "--format", "pbm", "tetris",
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(options.rom, "tetris");
+        assert_eq!(options.frames, Some(60));
+        assert_eq!(options.presses.len(), 1);
+        assert_eq!(options.format, Format::Pbm);
+        assert_eq!(options.disassemble, None);
+        assert!(!options.recompile);
+        assert_eq!(options.trace, None);
+
+        assert!(Options::parse(args(&["--help"])).unwrap().is_none());
+        assert!(Options::parse(args(&[])).is_err());
+        assert!(Options::parse(args(&["--frames"])).is_err());
+        assert!(Options::parse(args(&["--format", "gif", "tetris"])).is_err());
+        assert!(Options::parse(args(&["--movie", "pong.c8mv", "--press", "1@0", "pong"])).is_err());
+        assert!(Options::parse(args(&["--disassemble", "nasm", "pong"])).is_err());
+    }
+
+    #[test]
+    fn dumps_the_screen_a_rom_draws() {
+        let mut chip8 = Chip8::with_seed(1);
+        // Draws the 0 font glyph at the top left corner, then loops.
+        chip8
+            .load_rom(
+                &[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06],
+                DEFAULT_QUIRKS,
+            )
+            .unwrap();
+        chip8.set_flicker_filter(FlickerFilter::Off);
+        chip8.run_frame().unwrap();
+        let screen = Screen::capture(&chip8);
+        let ascii = screen.to_ascii();
+        let rows: Vec<&str> = ascii.lines().collect();
+        assert_eq!(rows.len(), 32);
+        assert_eq!(&rows[0][..6], "####..");
+        assert_eq!(&rows[1][..6], "#..#..");
+
+        let mut pbm = Vec::new();
+        screen.write(Format::Pbm, &mut pbm).unwrap();
+        assert!(pbm.starts_with(b"P4\n64 32\n"));
+        assert_eq!(pbm.len(), "P4\n64 32\n".len() + 64 * 32 / 8);
+        assert_eq!(pbm["P4\n64 32\n".len()], 0xF0);
+
+        let mut png = Vec::new();
+        screen.write(Format::Png, &mut png).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+    }
+}
@@ -0,0 +1,114 @@
+use std::io::{self, Write};
+
+use chip8_core::Chip8;
+
+// Indexed by the bits of the two XO-CHIP planes, like the palette of the web frontend.
+const PALETTE: [[u8; 3]; 4] = [
+    [0x00, 0x00, 0x00],
+    [0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55],
+];
+const ASCII: [char; 4] = ['.', '#', '+', '@'];
+
+/// The formats the screen can be dumped in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// One character per pixel, `.` for off and `#` for on.
+    Ascii,
+    /// A binary portable bitmap, pixels on in either plane are black.
+    Pbm,
+    /// A PNG in the colours of the web frontend.
+    Png,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "ascii" => Some(Format::Ascii),
+            "pbm" => Some(Format::Pbm),
+            "png" => Some(Format::Png),
+            _ => None,
+        }
+    }
+}
+
+/// A copy of the display, with each pixel holding the bits of both planes.
+pub struct Screen {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Screen {
+    pub fn capture(chip8: &Chip8) -> Self {
+        let (width, height) = (chip8.display_width(), chip8.display_height());
+        // Both planes always exist.
+        let planes = [0, 1].map(|plane| chip8.display_plane(plane).unwrap_or_default());
+        let pixels = (0..width * height)
+            .map(|bit| {
+                let mask = 0b1000_0000 >> (bit % 8);
+                planes
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, plane)| plane[bit / 8] & mask != 0)
+                    .fold(0, |pixel, (index, _)| pixel | 1 << index)
+            })
+            .collect();
+        Screen {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        self.pixels.chunks(self.width)
+    }
+
+    pub fn to_ascii(&self) -> String {
+        let mut ascii = String::with_capacity((self.width + 1) * self.height);
+        for row in self.rows() {
+            ascii.extend(row.iter().map(|&pixel| ASCII[pixel as usize]));
+            ascii.push('\n');
+        }
+        ascii
+    }
+
+    pub fn write(&self, format: Format, out: &mut impl Write) -> io::Result<()> {
+        match format {
+            Format::Ascii => out.write_all(self.to_ascii().as_bytes()),
+            Format::Pbm => self.write_pbm(out),
+            Format::Png => self.write_png(out),
+        }
+    }
+
+    fn write_pbm(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "P4\n{} {}\n", self.width, self.height)?;
+        // The display is always a whole number of bytes wide, so rows pack exactly.
+        let packed: Vec<u8> = self
+            .pixels
+            .chunks(8)
+            .map(|byte| {
+                byte.iter()
+                    .fold(0, |packed, &pixel| packed << 1 | (pixel != 0) as u8)
+            })
+            .collect();
+        out.write_all(&packed)
+    }
+
+    fn write_png(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(out, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let rgb: Vec<u8> = self
+            .pixels
+            .iter()
+            .flat_map(|&pixel| PALETTE[pixel as usize])
+            .collect();
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&rgb)?;
+        writer.finish()?;
+        Ok(())
+    }
+}
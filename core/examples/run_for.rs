@@ -0,0 +1,43 @@
+//! Times BRIX running frame by frame, as `run_for` runs it, with each engine and
+//! with the COSMAC VIP display mirror off and on:
+//! `cargo run --release --example run_for`.
+use std::time::{Duration, Instant};
+
+use chip8_core::games::{BRIX, DEFAULT_QUIRKS};
+use chip8_core::{Chip8, Engine};
+
+// A minute of play at a speed where the interpreter's overhead shows.
+const FRAMES: u32 = 60 * 60;
+const CPU_SPEED: u32 = 60_000;
+// The fastest of several runs, which is the least disturbed by whatever else the
+// machine is doing.
+const RUNS: u32 = 7;
+
+fn time(engine: Engine, mirror: bool) -> Duration {
+    let mut chip8 = Chip8::with_seed(1);
+    chip8.load_rom(BRIX, DEFAULT_QUIRKS).unwrap();
+    chip8.set_engine(engine);
+    chip8.set_vip_display_mirror(mirror);
+    chip8.set_cpu_speed(CPU_SPEED);
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        chip8.run_frame().unwrap();
+    }
+    start.elapsed()
+}
+
+fn main() {
+    for engine in [Engine::Interpreter, Engine::Recompiler] {
+        for mirror in [false, true] {
+            let fastest = (0..RUNS).map(|_| time(engine, mirror)).min().unwrap();
+            let instructions = (FRAMES * (CPU_SPEED / 60)) as f64;
+            println!(
+                "{:?}, display mirror {}: {:.1} ms, {:.1} million instructions per second",
+                engine,
+                if mirror { "on" } else { "off" },
+                fastest.as_secs_f64() * 1000.0,
+                instructions / fastest.as_secs_f64() / 1e6
+            );
+        }
+    }
+}
@@ -0,0 +1,886 @@
+//! An assembler for Octo, the CHIP-8 assembly language of the Octo IDE.
+//!
+//! It covers what Octo programs commonly use: labels, `:const`, `:alias`,
+//! `:macro`, `:calc`, `:byte`, `:call`, `loop`, `while` and `again`, `if ... then`
+//! and `if ... begin ... else ... end`, the SUPER-CHIP and XO-CHIP instructions,
+//! and numbers on their own for sprite data. Comparisons with `<` and `>` and the
+//! directives for Octo's own tooling, such as `:assert`, aren't supported.
+//!
+//! Like Octo, the ROM starts with a jump to the `main` label, which is left out
+//! when `main` comes first.
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::error::AssembleError;
+use crate::memory::EXTENDED_MEMORY_SIZE;
+
+const START: usize = 0x200;
+// Macros can expand into themselves, this stops the ones that never stop.
+const MAX_EXPANSIONS: usize = 100_000;
+
+/// An assembled ROM, along with the address of every label.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Program {
+    code: Vec<u8>,
+    labels: BTreeMap<String, u16>,
+}
+
+impl Program {
+    /// The ROM, to be loaded at 0x200 with `Chip8::load_program` or `load_rom`.
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    /// The symbol table: the address of every label, by name.
+    pub fn labels(&self) -> &BTreeMap<String, u16> {
+        &self.labels
+    }
+
+    pub fn label(&self, name: &str) -> Option<u16> {
+        self.labels.get(name).copied()
+    }
+}
+
+/// Assembles Octo `source` into a ROM.
+pub fn assemble(source: &str) -> Result<Program, AssembleError> {
+    let mut assembler = Assembler::new(source);
+    while let Some(token) = assembler.tokens.pop_front() {
+        assembler.statement(token)?;
+    }
+    assembler.finish()
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn error(&self, message: impl Into<String>) -> AssembleError {
+        AssembleError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+}
+
+/// Splits `source` into tokens at whitespace. Comments go from a token starting
+/// with `#` to the end of the line.
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (index, line) in source.lines().enumerate() {
+        let mut column = 0;
+        let mut chars = line.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+                column += 1;
+                continue;
+            }
+            if c == '#' {
+                break;
+            }
+            let start = column;
+            let mut text = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                text.push(c);
+                chars.next();
+                column += 1;
+            }
+            tokens.push_back(Token {
+                text,
+                line: index + 1,
+                column: start + 1,
+            });
+        }
+    }
+    tokens
+}
+
+/// Parses a decimal, `0x` hexadecimal or `0b` binary integer, optionally negative.
+fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let parse = |digits: &str, radix: u32| {
+        if !digits.is_empty() && digits.chars().all(|c| c.is_digit(radix)) {
+            i64::from_str_radix(digits, radix).ok()
+        } else {
+            None
+        }
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        parse(hex, 16)?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        parse(binary, 2)?
+    } else {
+        parse(digits, 10)?
+    } as f64;
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => {
+            digit.to_digit(16).map(|x| x as u8)
+        }
+        _ => None,
+    }
+}
+
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        && parse_register(text).is_none()
+}
+
+/// The values `:calc` works with are floating point, like in Octo, and are
+/// rounded down when they end up in the ROM.
+fn integer(value: f64) -> i64 {
+    value.floor() as i64
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// A block of structured control flow that is still open.
+enum Block {
+    // The jump past the block, taken when the condition doesn't hold.
+    If(usize),
+    // The jump past the `else` block, at the end of the `if` block.
+    Else(usize),
+    // The jumps out of the loop made by its `while`s.
+    Loop { start: usize, exits: Vec<usize> },
+}
+
+/// How an address referring to a label that isn't defined yet is filled in.
+#[derive(Clone, Copy)]
+enum Width {
+    // The low 12 bits of an instruction.
+    Nnn,
+    // The word after `i := long`.
+    Long,
+}
+
+struct Fixup {
+    offset: usize,
+    width: Width,
+    name: Token,
+}
+
+/// What `if` and `while` test: the skip that runs the next instruction only when
+/// the condition holds, and the one that runs it only when it doesn't.
+struct Condition {
+    skip_unless: u16,
+    skip_if: u16,
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    // Where the source ends, for errors about what's missing from it.
+    end: Token,
+    code: Vec<u8>,
+    // Whether the first two bytes are the jump to `main`.
+    jumps_to_main: bool,
+    labels: BTreeMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<(Block, Token)>,
+    expansions: usize,
+}
+
+impl Assembler {
+    fn new(source: &str) -> Self {
+        let line = source.lines().count().max(1);
+        let column = source.lines().last().map_or(0, |last| last.chars().count()) + 1;
+        Assembler {
+            tokens: tokenize(source),
+            end: Token {
+                text: String::new(),
+                line,
+                column,
+            },
+            code: vec![0x10, 0x00],
+            jumps_to_main: true,
+            labels: BTreeMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            expansions: 0,
+        }
+    }
+
+    fn here(&self) -> usize {
+        START + self.code.len()
+    }
+
+    fn next(&mut self, expected: &str) -> Result<Token, AssembleError> {
+        self.tokens.pop_front().ok_or_else(|| {
+            self.end.error(format!(
+                "expected {}, found the end of the source",
+                expected
+            ))
+        })
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token, AssembleError> {
+        let token = self.next(&format!("'{}'", text))?;
+        if token.text != text {
+            return Err(token.error(format!("expected '{}', found '{}'", text, token.text)));
+        }
+        Ok(token)
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.front().is_some_and(|token| token.text == text)
+    }
+
+    fn emit(&mut self, opcode: u16) {
+        self.code.extend_from_slice(&opcode.to_be_bytes());
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), AssembleError> {
+        match token.text.as_str() {
+            ":" => self.define_label()?,
+            ":const" => {
+                let name = self.new_name()?;
+                let value = self.next("a value")?;
+                let value = self.value(&value)?;
+                self.constants.insert(name.text, value);
+            }
+            ":alias" => {
+                let name = self.new_name()?;
+                let register = self.next("a register")?;
+                let register = self.register(&register)?;
+                self.aliases.insert(name.text, register);
+            }
+            ":macro" => self.define_macro()?,
+            ":calc" => {
+                let name = self.new_name()?;
+                let value = self.braced_expression()?;
+                self.constants.insert(name.text, value);
+            }
+            ":byte" => {
+                let value = if self.peek_is("{") {
+                    self.braced_expression()?
+                } else {
+                    let value = self.next("a value")?;
+                    self.value(&value)?
+                };
+                let byte = self.byte(value, &token)?;
+                self.code.push(byte);
+            }
+            ":call" => self.address_instruction(0x2000)?,
+            // Breakpoints are for Octo's debugger, their names mean nothing here.
+            ":breakpoint" => drop(self.next("a name")?),
+            "clear" => self.emit(0x00E0),
+            "return" | ";" => self.emit(0x00EE),
+            "scroll-right" => self.emit(0x00FB),
+            "scroll-left" => self.emit(0x00FC),
+            "exit" => self.emit(0x00FD),
+            "lores" => self.emit(0x00FE),
+            "hires" => self.emit(0x00FF),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(0x00C0 | n);
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(0x00D0 | n);
+            }
+            "audio" => self.emit(0xF002),
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(0xF001 | n << 8);
+            }
+            "native" => self.address_instruction(0x0000)?,
+            "jump" => self.address_instruction(0x1000)?,
+            "jump0" => self.address_instruction(0xB000)?,
+            "bcd" => {
+                let x = self.next_register()? << 8;
+                self.emit(0xF033 | x);
+            }
+            "save" | "load" => {
+                let x = self.next_register()? << 8;
+                if self.peek_is("-") {
+                    self.tokens.pop_front();
+                    let y = self.next_register()? << 4;
+                    let opcode = if token.text == "save" { 0x5002 } else { 0x5003 };
+                    self.emit(opcode | x | y);
+                } else {
+                    let opcode = if token.text == "save" { 0xF055 } else { 0xF065 };
+                    self.emit(opcode | x);
+                }
+            }
+            "saveflags" => {
+                let x = self.next_register()? << 8;
+                self.emit(0xF075 | x);
+            }
+            "loadflags" => {
+                let x = self.next_register()? << 8;
+                self.emit(0xF085 | x);
+            }
+            "sprite" => {
+                let x = self.next_register()? << 8;
+                let y = self.next_register()? << 4;
+                let n = self.nibble()?;
+                self.emit(0xD000 | x | y | n);
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.next_register()? << 8;
+                let opcode = match token.text.as_str() {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                self.emit(opcode | x);
+            }
+            "i" => self.i_statement()?,
+            "if" => {
+                let condition = self.condition()?;
+                let how = self.next("'then' or 'begin'")?;
+                match how.text.as_str() {
+                    "then" => self.emit(condition.skip_unless),
+                    "begin" => {
+                        self.emit(condition.skip_if);
+                        let jump = self.jump_to_patch();
+                        self.blocks.push((Block::If(jump), token));
+                    }
+                    _ => {
+                        return Err(
+                            how.error(format!("expected 'then' or 'begin', found '{}'", how.text))
+                        )
+                    }
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some((Block::If(jump), _)) => {
+                    let past_else = self.jump_to_patch();
+                    self.patch(jump, self.here(), &token)?;
+                    self.blocks.push((Block::Else(past_else), token));
+                }
+                _ => return Err(token.error("'else' without 'if ... begin'")),
+            },
+            "end" => match self.blocks.pop() {
+                Some((Block::If(jump), _)) | Some((Block::Else(jump), _)) => {
+                    self.patch(jump, self.here(), &token)?
+                }
+                _ => return Err(token.error("'end' without 'if ... begin'")),
+            },
+            "loop" => {
+                let start = self.here();
+                self.blocks.push((
+                    Block::Loop {
+                        start,
+                        exits: Vec::new(),
+                    },
+                    token,
+                ));
+            }
+            "while" => {
+                if !matches!(self.blocks.last(), Some((Block::Loop { .. }, _))) {
+                    return Err(token.error("'while' outside of a loop"));
+                }
+                let condition = self.condition()?;
+                self.emit(condition.skip_if);
+                let exit = self.jump_to_patch();
+                if let Some((Block::Loop { exits, .. }, _)) = self.blocks.last_mut() {
+                    exits.push(exit);
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some((Block::Loop { start, exits }, _)) => {
+                    let start = check_address(start as i64, Width::Nnn, &token)?;
+                    self.emit(0x1000 | start);
+                    for exit in exits {
+                        self.patch(exit, self.here(), &token)?;
+                    }
+                }
+                _ => return Err(token.error("'again' without 'loop'")),
+            },
+            _ => self.other_statement(token)?,
+        }
+        Ok(())
+    }
+
+    /// Register operations, macros, data and calls by name.
+    fn other_statement(&mut self, token: Token) -> Result<(), AssembleError> {
+        if let Ok(x) = self.register(&token) {
+            return self.register_statement(x);
+        }
+        if self.macros.contains_key(&token.text) {
+            return self.expand_macro(&token);
+        }
+        if let Some(&address) = self.labels.get(&token.text) {
+            let address = check_address(address as i64, Width::Nnn, &token)?;
+            self.emit(0x2000 | address);
+            return Ok(());
+        }
+        if let Some(value) = parse_number(&token.text).or_else(|| self.constant(&token.text)) {
+            let byte = self.byte(value, &token)?;
+            self.code.push(byte);
+            return Ok(());
+        }
+        if is_name(&token.text) {
+            // A call to a subroutine further down.
+            self.fixups.push(Fixup {
+                offset: self.code.len(),
+                width: Width::Nnn,
+                name: token,
+            });
+            self.emit(0x2000);
+            return Ok(());
+        }
+        Err(token.error(format!("unknown statement '{}'", token.text)))
+    }
+
+    fn register_statement(&mut self, x: u8) -> Result<(), AssembleError> {
+        let x = (x as u16) << 8;
+        let operator = self.next("an operator")?;
+        let operand = self.next("a register or a value")?;
+        let y = self.register(&operand).ok().map(|y| (y as u16) << 4);
+        let opcode = match (operator.text.as_str(), y) {
+            (":=", Some(y)) => 0x8000 | y,
+            (":=", None) => match operand.text.as_str() {
+                "random" => {
+                    let mask = self.next("a value")?;
+                    0xC000 | self.byte_operand(&mask)? as u16
+                }
+                "key" => 0xF00A,
+                "delay" => 0xF007,
+                _ => 0x6000 | self.byte_operand(&operand)? as u16,
+            },
+            ("+=", Some(y)) => 0x8004 | y,
+            ("+=", None) => 0x7000 | self.byte_operand(&operand)? as u16,
+            ("-=", Some(y)) => 0x8005 | y,
+            ("-=", None) => 0x7000 | self.byte_operand(&operand)?.wrapping_neg() as u16,
+            ("=-", Some(y)) => 0x8007 | y,
+            ("|=", Some(y)) => 0x8001 | y,
+            ("&=", Some(y)) => 0x8002 | y,
+            ("^=", Some(y)) => 0x8003 | y,
+            (">>=", Some(y)) => 0x8006 | y,
+            ("<<=", Some(y)) => 0x800E | y,
+            ("=-", None)
+            | ("|=", None)
+            | ("&=", None)
+            | ("^=", None)
+            | (">>=", None)
+            | ("<<=", None) => {
+                return Err(operand.error(format!("expected a register, found '{}'", operand.text)))
+            }
+            _ => {
+                return Err(operator.error(format!("unknown register operator '{}'", operator.text)))
+            }
+        };
+        self.emit(opcode | x);
+        Ok(())
+    }
+
+    fn i_statement(&mut self) -> Result<(), AssembleError> {
+        let operator = self.next("':=' or '+='")?;
+        match operator.text.as_str() {
+            ":=" if self.peek_is("long") => {
+                self.tokens.pop_front();
+                self.emit(0xF000);
+                let target = self.next("an address")?;
+                let address = self.address(&target, Width::Long)?;
+                self.emit(address);
+            }
+            ":=" if self.peek_is("hex") || self.peek_is("bighex") => {
+                let font = self.tokens.pop_front().map(|token| token.text);
+                let x = self.next_register()? << 8;
+                let opcode = if font.as_deref() == Some("hex") {
+                    0xF029
+                } else {
+                    0xF030
+                };
+                self.emit(opcode | x);
+            }
+            ":=" => self.address_instruction(0xA000)?,
+            "+=" => {
+                let x = self.next_register()? << 8;
+                self.emit(0xF01E | x);
+            }
+            _ => {
+                return Err(
+                    operator.error(format!("expected ':=' or '+=', found '{}'", operator.text))
+                )
+            }
+        }
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, AssembleError> {
+        let x = self.next_register()? << 8;
+        let operator = self.next("a comparison")?;
+        let (skip_unless, skip_if) = match operator.text.as_str() {
+            "==" | "!=" => {
+                let operand = self.next("a register or a value")?;
+                let (equal, not_equal) = match self.register(&operand) {
+                    Ok(y) => {
+                        let y = (y as u16) << 4;
+                        (0x5000 | y, 0x9000 | y)
+                    }
+                    Err(_) => {
+                        let byte = self.byte_operand(&operand)? as u16;
+                        (0x3000 | byte, 0x4000 | byte)
+                    }
+                };
+                // `equal` skips when the operands are equal, `not_equal` when they
+                // aren't.
+                if operator.text == "==" {
+                    (not_equal, equal)
+                } else {
+                    (equal, not_equal)
+                }
+            }
+            "key" => (0xE0A1, 0xE09E),
+            "-key" => (0xE09E, 0xE0A1),
+            _ => {
+                return Err(operator.error(format!(
+                    "expected '==', '!=', 'key' or '-key', found '{}'",
+                    operator.text
+                )))
+            }
+        };
+        Ok(Condition {
+            skip_unless: skip_unless | x,
+            skip_if: skip_if | x,
+        })
+    }
+
+    fn define_label(&mut self) -> Result<(), AssembleError> {
+        let name = self.new_name()?;
+        // Nothing comes before `main`, so it doesn't have to be jumped to.
+        if name.text == "main"
+            && self.jumps_to_main
+            && self.code.len() == 2
+            && self.labels.is_empty()
+            && self.fixups.is_empty()
+        {
+            self.code.clear();
+            self.jumps_to_main = false;
+        }
+        self.labels.insert(name.text, self.here() as u16);
+        Ok(())
+    }
+
+    fn define_macro(&mut self) -> Result<(), AssembleError> {
+        let name = self.new_name()?;
+        let mut parameters = Vec::new();
+        loop {
+            let token = self.next("'{'")?;
+            if token.text == "{" {
+                break;
+            }
+            parameters.push(token.text);
+        }
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self.next("'}'")?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => break,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+        self.macros.insert(name.text, Macro { parameters, body });
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, name: &Token) -> Result<(), AssembleError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(name.error(format!("macro '{}' expands without end", name.text)));
+        }
+        let count = self.macros[&name.text].parameters.len();
+        let mut arguments = Vec::with_capacity(count);
+        for _ in 0..count {
+            arguments.push(self.next("a macro argument")?.text);
+        }
+        let definition = &self.macros[&name.text];
+        for token in definition.body.iter().rev() {
+            let mut token = token.clone();
+            if let Some(index) = definition
+                .parameters
+                .iter()
+                .position(|parameter| *parameter == token.text)
+            {
+                token.text = arguments[index].clone();
+            }
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    /// A name that isn't defined yet.
+    fn new_name(&mut self) -> Result<Token, AssembleError> {
+        let name = self.next("a name")?;
+        if !is_name(&name.text) {
+            return Err(name.error(format!("'{}' can't be used as a name", name.text)));
+        }
+        if self.labels.contains_key(&name.text)
+            || self.constants.contains_key(&name.text)
+            || self.aliases.contains_key(&name.text)
+            || self.macros.contains_key(&name.text)
+        {
+            return Err(name.error(format!("'{}' is already defined", name.text)));
+        }
+        Ok(name)
+    }
+
+    fn register(&self, token: &Token) -> Result<u8, AssembleError> {
+        parse_register(&token.text)
+            .or_else(|| self.aliases.get(&token.text).copied())
+            .ok_or_else(|| token.error(format!("expected a register, found '{}'", token.text)))
+    }
+
+    fn next_register(&mut self) -> Result<u16, AssembleError> {
+        let token = self.next("a register")?;
+        Ok(self.register(&token)? as u16)
+    }
+
+    fn constant(&self, name: &str) -> Option<f64> {
+        self.constants
+            .get(name)
+            .copied()
+            .or_else(|| self.labels.get(name).map(|&address| address as f64))
+    }
+
+    /// A number, a constant or a label that is already defined.
+    fn value(&self, token: &Token) -> Result<f64, AssembleError> {
+        parse_number(&token.text)
+            .or_else(|| self.constant(&token.text))
+            .ok_or_else(|| token.error(format!("expected a value, found '{}'", token.text)))
+    }
+
+    fn byte(&self, value: f64, token: &Token) -> Result<u8, AssembleError> {
+        let value = integer(value);
+        if (-128..=255).contains(&value) {
+            Ok(value as u8)
+        } else {
+            Err(token.error(format!("{} doesn't fit in a byte", value)))
+        }
+    }
+
+    fn byte_operand(&self, token: &Token) -> Result<u8, AssembleError> {
+        let value = self.value(token)?;
+        self.byte(value, token)
+    }
+
+    fn nibble(&mut self) -> Result<u16, AssembleError> {
+        let token = self.next("a value")?;
+        let value = integer(self.value(&token)?);
+        if (0..=15).contains(&value) {
+            Ok(value as u16)
+        } else {
+            Err(token.error(format!("{} doesn't fit in a nibble", value)))
+        }
+    }
+
+    /// An address, which may be a label defined further down.
+    fn address(&mut self, token: &Token, width: Width) -> Result<u16, AssembleError> {
+        let value = match self.value(token) {
+            Ok(value) => integer(value),
+            Err(_) if is_name(&token.text) => {
+                self.fixups.push(Fixup {
+                    offset: self.code.len(),
+                    width,
+                    name: token.clone(),
+                });
+                return Ok(0);
+            }
+            Err(error) => return Err(error),
+        };
+        check_address(value, width, token)
+    }
+
+    fn address_instruction(&mut self, opcode: u16) -> Result<(), AssembleError> {
+        let target = self.next("an address")?;
+        // The fixup, if any, is for the instruction about to be emitted.
+        let address = self.address(&target, Width::Nnn)?;
+        self.emit(opcode | address);
+        Ok(())
+    }
+
+    /// Emits a jump whose target is patched in later.
+    fn jump_to_patch(&mut self) -> usize {
+        let offset = self.code.len();
+        self.emit(0x1000);
+        offset
+    }
+
+    /// Fills in the target of the jump at `offset`, made for `token`.
+    fn patch(&mut self, offset: usize, address: usize, token: &Token) -> Result<(), AssembleError> {
+        let opcode = 0x1000 | check_address(address as i64, Width::Nnn, token)?;
+        self.code[offset..offset + 2].copy_from_slice(&opcode.to_be_bytes());
+        Ok(())
+    }
+
+    fn braced_expression(&mut self) -> Result<f64, AssembleError> {
+        self.expect("{")?;
+        let value = self.expression()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    /// Evaluates an expression the way Octo does: right to left, with all the binary
+    /// operators binding equally, unless parentheses say otherwise.
+    fn expression(&mut self) -> Result<f64, AssembleError> {
+        let left = self.term()?;
+        let operator = match self.tokens.front() {
+            Some(token) if BINARY_OPERATORS.contains(&token.text.as_str()) => {
+                self.next("an operator")?
+            }
+            _ => return Ok(left),
+        };
+        let right = self.expression()?;
+        let (a, b) = (integer(left), integer(right));
+        Ok(match operator.text.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" => a.checked_shl(b as u32).unwrap_or(0) as f64,
+            ">>" => a.checked_shr(b as u32).unwrap_or(0) as f64,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "<" => (left < right) as u8 as f64,
+            "<=" => (left <= right) as u8 as f64,
+            ">" => (left > right) as u8 as f64,
+            ">=" => (left >= right) as u8 as f64,
+            "==" => (left == right) as u8 as f64,
+            _ => (left != right) as u8 as f64,
+        })
+    }
+
+    fn term(&mut self) -> Result<f64, AssembleError> {
+        let token = self.next("a value")?;
+        Ok(match token.text.as_str() {
+            "(" => {
+                let value = self.expression()?;
+                self.expect(")")?;
+                value
+            }
+            "-" => -self.term()?,
+            "~" => !integer(self.term()?) as f64,
+            "!" => (self.term()? == 0.0) as u8 as f64,
+            "abs" => self.term()?.abs(),
+            "sqrt" => self.term()?.sqrt(),
+            "sin" => self.term()?.sin(),
+            "cos" => self.term()?.cos(),
+            "tan" => self.term()?.tan(),
+            "exp" => self.term()?.exp(),
+            "log" => self.term()?.ln(),
+            "sign" => self.term()?.signum(),
+            "ceil" => self.term()?.ceil(),
+            "floor" => self.term()?.floor(),
+            // The byte of the ROM at an address.
+            "@" => {
+                let address = integer(self.term()?);
+                let offset = address - START as i64;
+                let byte = if offset >= 0 {
+                    self.code.get(offset as usize)
+                } else {
+                    None
+                };
+                match byte {
+                    Some(&byte) => byte as f64,
+                    None => {
+                        return Err(token.error(format!("there is no byte at 0x{:X} yet", address)))
+                    }
+                }
+            }
+            "HERE" => self.here() as f64,
+            "PI" => std::f64::consts::PI,
+            "E" => std::f64::consts::E,
+            _ => self.value(&token)?,
+        })
+    }
+
+    fn finish(mut self) -> Result<Program, AssembleError> {
+        if let Some((_, token)) = self.blocks.last() {
+            return Err(token.error(format!("'{}' is never closed", token.text)));
+        }
+        if self.code.len() > EXTENDED_MEMORY_SIZE - START {
+            return Err(self.end.error(format!(
+                "the program takes {} bytes, more than fit in memory",
+                self.code.len()
+            )));
+        }
+        if self.jumps_to_main {
+            match self.labels.get("main") {
+                Some(&main) => {
+                    let end = self.end.clone();
+                    self.patch(0, main as usize, &end)?
+                }
+                None => return Err(self.end.error("the program has no 'main' label")),
+            }
+        }
+        for fixup in &self.fixups {
+            let address = match self.labels.get(&fixup.name.text) {
+                Some(&address) => address as i64,
+                None => {
+                    return Err(fixup
+                        .name
+                        .error(format!("'{}' is not defined", fixup.name.text)))
+                }
+            };
+            let address = check_address(address, fixup.width, &fixup.name)?;
+            let bytes = &mut self.code[fixup.offset..];
+            match fixup.width {
+                Width::Nnn => {
+                    bytes[0] |= (address >> 8) as u8;
+                    bytes[1] = address as u8;
+                }
+                Width::Long => bytes[..2].copy_from_slice(&address.to_be_bytes()),
+            }
+        }
+        Ok(Program {
+            code: self.code,
+            labels: self.labels,
+        })
+    }
+}
+
+const BINARY_OPERATORS: &[&str] = &[
+    "+", "-", "*", "/", "%", "&", "|", "^", "<<", ">>", "pow", "min", "max", "<", "<=", ">", ">=",
+    "==", "!=",
+];
+
+fn check_address(value: i64, width: Width, token: &Token) -> Result<u16, AssembleError> {
+    let max = match width {
+        Width::Nnn => 0xFFF,
+        Width::Long => 0xFFFF,
+    };
+    if (0..=max).contains(&value) {
+        Ok(value as u16)
+    } else {
+        Err(token.error(format!("address 0x{:X} is out of reach", value)))
+    }
+}
@@ -0,0 +1,78 @@
+pub const PATTERN_SIZE: usize = 16;
+const PATTERN_BITS: f32 = (PATTERN_SIZE * 8) as f32;
+// Plain CHIP-8 ROMs never load a pattern, so they get a fixed square wave instead.
+const BEEPER_FREQUENCY: f32 = 440.0;
+const VOLUME: f32 = 0.25;
+
+/// Renders the sound the interpreter makes while the sound timer is running.
+///
+/// XO-CHIP ROMs load a 128 bit pattern with F002 and play it back one bit per
+/// sample at a rate derived from the pitch register set with Fx3A.
+pub(crate) struct Audio {
+    pub(crate) pattern: [u8; PATTERN_SIZE],
+    pub(crate) pattern_loaded: bool,
+    pub(crate) pitch: u8,
+    pub(crate) sample_rate: f32,
+    // Position in the pattern, in bits, or in the beeper's period, in cycles.
+    phase: f32,
+}
+
+impl Audio {
+    pub(crate) fn new() -> Self {
+        Audio {
+            pattern: [0; PATTERN_SIZE],
+            pattern_loaded: false,
+            pitch: 64,
+            sample_rate: 44100.0,
+            phase: 0.0,
+        }
+    }
+
+    /// Back to the beeper, keeping the sample rate.
+    pub(crate) fn reset(&mut self) {
+        *self = Audio {
+            sample_rate: self.sample_rate,
+            ..Audio::new()
+        };
+    }
+
+    pub(crate) fn load_pattern(&mut self, pattern: [u8; PATTERN_SIZE]) {
+        self.pattern = pattern;
+        self.pattern_loaded = true;
+    }
+
+    pub(crate) fn set_pitch(&mut self, pitch: u8) {
+        self.pitch = pitch;
+    }
+
+    /// How many pattern bits are played per second: 4000 at the default pitch of 64,
+    /// doubling every 48 steps.
+    pub(crate) fn playback_rate(&self) -> f32 {
+        4000.0 * libm::exp2f((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    /// Fills `out` with samples in the -1.0..=1.0 range. Silence is rendered, and the
+    /// waveform restarts, when the sound timer isn't `playing`.
+    pub(crate) fn render(&mut self, playing: bool, out: &mut [f32]) {
+        if !playing {
+            self.phase = 0.0;
+            out.fill(0.0);
+            return;
+        }
+        if self.pattern_loaded {
+            let step = self.playback_rate() / self.sample_rate;
+            for sample in out.iter_mut() {
+                let bit = self.phase as usize;
+                let on = self.pattern[bit / 8] & (0b1000_0000 >> (bit % 8)) != 0;
+                *sample = if on { VOLUME } else { -VOLUME };
+                self.phase = (self.phase + step) % PATTERN_BITS;
+            }
+        } else {
+            let step = BEEPER_FREQUENCY / self.sample_rate;
+            for sample in out.iter_mut() {
+                *sample = if self.phase < 0.5 { VOLUME } else { -VOLUME };
+                self.phase = (self.phase + step) % 1.0;
+            }
+        }
+    }
+}
@@ -0,0 +1,207 @@
+const FRAMES_PER_SECOND: u32 = 60;
+const FRAME_MS: f64 = 1000.0 / FRAMES_PER_SECOND as f64;
+// After a long pause (e.g. the browser tab was hidden) don't try to catch up on
+// more than this, the game would just fast forward.
+const MAX_CATCH_UP_MS: f64 = 250.0;
+
+// The COSMAC VIP's 1802 ran at 1.7609 MHz, with 8 clock pulses per machine cycle.
+const VIP_CYCLES_PER_FRAME: i32 = 1_760_900 / 8 / FRAMES_PER_SECOND as i32;
+// Every frame the display interrupt hands the bus to the video chip, which DMAs 8
+// bytes for each of the 128 scanlines, and then runs the interrupt routine that
+// decrements the timers. The interpreter doesn't get to run in the meantime.
+const VIP_INTERRUPT_CYCLES: i32 = 128 * 8 + 36;
+// The interpreter loop fetching an instruction and dispatching on its first nibble.
+const VIP_FETCH_CYCLES: u32 = 40;
+
+/// Where the emulator learns how much time has passed, for hosts that would
+/// rather not keep track of it themselves. See `Chip8::run`.
+pub trait TimeSource {
+    /// Milliseconds since the previous call.
+    fn elapsed_ms(&mut self) -> f64;
+}
+
+/// Measures time with the system's monotonic clock.
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub struct SystemClock {
+    last: Option<std::time::Instant>,
+}
+
+#[cfg(feature = "std")]
+impl TimeSource for SystemClock {
+    /// The first call starts the clock and returns 0.
+    fn elapsed_ms(&mut self) -> f64 {
+        let now = std::time::Instant::now();
+        let elapsed = self
+            .last
+            .map_or(0.0, |last| now.duration_since(last).as_secs_f64() * 1000.0);
+        self.last = Some(now);
+        elapsed
+    }
+}
+
+/// How the emulator decides how many instructions make up a frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimingMode {
+    /// Every instruction takes the same time, a frame runs as many as the
+    /// configured CPU speed allows.
+    #[default]
+    Fixed,
+    /// Instructions take as many machine cycles as they did in the COSMAC VIP
+    /// interpreter, and frames end when the 1802 would have been interrupted.
+    CosmacVip,
+}
+
+/// Turns the wall clock time reported by the host into 60 Hz frames, and frames
+/// into instructions, so emulation speed doesn't depend on how often the host
+/// calls in (e.g. the refresh rate of the monitor).
+pub(crate) struct Clock {
+    pub(crate) mode: TimingMode,
+    pub(crate) instructions_per_second: u32,
+    // Time reported by the host that hasn't been turned into frames yet.
+    elapsed_ms: f64,
+    // instructions_per_second rarely divides evenly into frames, the leftover
+    // instructions are carried over to the next frames.
+    pub(crate) leftover_instructions: u32,
+    // Machine cycles left in the current frame in CosmacVip mode. Goes negative when
+    // an instruction runs past the interrupt, which delays the next frame.
+    pub(crate) cycle_budget: i32,
+    // Whether a frame has started and not ended yet, which happens when the
+    // debugger stops execution in the middle of one.
+    frame_started: bool,
+    // Instructions left in the current frame in Fixed mode.
+    frame_instructions: u32,
+}
+
+impl Clock {
+    pub(crate) fn new() -> Self {
+        Clock {
+            mode: TimingMode::Fixed,
+            instructions_per_second: 600,
+            elapsed_ms: 0.0,
+            leftover_instructions: 0,
+            cycle_budget: 0,
+            frame_started: false,
+            frame_instructions: 0,
+        }
+    }
+
+    /// Adds `elapsed_ms` to the clock and returns how many whole frames are due.
+    pub(crate) fn frames_due(&mut self, elapsed_ms: f64) -> u32 {
+        self.elapsed_ms = (self.elapsed_ms + elapsed_ms.max(0.0)).min(MAX_CATCH_UP_MS);
+        let frames = (self.elapsed_ms / FRAME_MS) as u32;
+        self.elapsed_ms -= frames as f64 * FRAME_MS;
+        frames
+    }
+
+    /// How many instructions to run in the next frame.
+    fn instructions_for_frame(&mut self) -> u32 {
+        let instructions = self.instructions_per_second / FRAMES_PER_SECOND;
+        self.leftover_instructions += self.instructions_per_second % FRAMES_PER_SECOND;
+        if self.leftover_instructions >= FRAMES_PER_SECOND {
+            self.leftover_instructions -= FRAMES_PER_SECOND;
+            instructions + 1
+        } else {
+            instructions
+        }
+    }
+
+    pub(crate) fn set_mode(&mut self, mode: TimingMode) {
+        self.mode = mode;
+        self.cycle_budget = 0;
+        self.frame_started = false;
+    }
+
+    /// Whether the current frame has room for another instruction, starting a new
+    /// frame if the last one ended. In CosmacVip mode the display interrupt takes
+    /// its share of a frame first.
+    pub(crate) fn frame_has_time_left(&mut self) -> bool {
+        if !self.frame_started {
+            self.frame_started = true;
+            match self.mode {
+                TimingMode::Fixed => self.frame_instructions = self.instructions_for_frame(),
+                TimingMode::CosmacVip => {
+                    self.cycle_budget += VIP_CYCLES_PER_FRAME - VIP_INTERRUPT_CYCLES
+                }
+            }
+        }
+        match self.mode {
+            TimingMode::Fixed => self.frame_instructions > 0,
+            TimingMode::CosmacVip => self.cycle_budget > 0,
+        }
+    }
+
+    /// Counts an instruction against the current frame. In CosmacVip mode it takes
+    /// `cycles` machine cycles.
+    pub(crate) fn executed(&mut self, cycles: u32) {
+        match self.mode {
+            TimingMode::Fixed => self.frame_instructions -= 1,
+            TimingMode::CosmacVip => self.cycle_budget -= cycles as i32,
+        }
+    }
+
+    /// How many instructions are left in the current frame in Fixed mode, starting
+    /// a new frame if the last one ended.
+    #[cfg(feature = "std")]
+    pub(crate) fn instructions_left(&mut self) -> u32 {
+        self.frame_has_time_left();
+        self.frame_instructions
+    }
+
+    /// Counts `count` instructions against the current frame in Fixed mode.
+    #[cfg(feature = "std")]
+    pub(crate) fn executed_instructions(&mut self, count: u32) {
+        self.frame_instructions -= count;
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        self.frame_started = false;
+    }
+
+    /// The interpreter is blocked until something changes, which can only happen in
+    /// the interrupt, so the rest of the frame is spent waiting for it.
+    pub(crate) fn wait_for_interrupt(&mut self) {
+        self.cycle_budget = self.cycle_budget.min(0);
+    }
+}
+
+/// Approximately how many 1802 machine cycles the COSMAC VIP interpreter spent on
+/// `opcode`, including fetching it. `vx` is the value of its Vx register, which
+/// drawing and BCD conversion depend on, and `skipped` whether a conditional skip
+/// was taken.
+pub(crate) fn cosmac_vip_cycles(opcode: u16, vx: u8, skipped: bool) -> u32 {
+    let skip = if skipped { 4 } else { 0 };
+    let execute = match opcode >> 12 {
+        0x0 => match opcode {
+            // Clears the 256 bytes of display memory one at a time.
+            0x00E0 => 24 + 256 * 4,
+            _ => 10,
+        },
+        0x1 => 12,
+        0x2 => 26,
+        0x3 | 0x4 => 10 + skip,
+        0x5 | 0x9 => 14 + skip,
+        0x6 => 6,
+        0x7 => 10,
+        0x8 => 44,
+        0xA => 12,
+        0xB => 22,
+        0xC => 36,
+        // Each sprite row is shifted into place one bit at a time, so unaligned
+        // sprites are slower.
+        0xD => 26 + (opcode & 0xF) as u32 * (46 + 4 * (vx % 8) as u32),
+        0xE => 14 + skip,
+        _ => match opcode & 0xFF {
+            0x1E => 16,
+            0x29 => 20,
+            // Each digit is found by repeated subtraction.
+            0x33 => {
+                let digits = vx / 100 + vx / 10 % 10 + vx % 10;
+                40 + 16 * digits as u32
+            }
+            0x55 | 0x65 => 14 + 14 * ((opcode >> 8 & 0xF) as u32 + 1),
+            _ => 10,
+        },
+    };
+    VIP_FETCH_CYCLES + execute
+}
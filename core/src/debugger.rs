@@ -0,0 +1,345 @@
+//! The debugger: breakpoints, watchpoints on memory and registers, and stepping.
+//!
+//! Execution stops when a breakpoint is reached, before the instruction there is
+//! executed, or when an instruction touches something watched, after it was
+//! executed. The machine then stays stopped, with `run_frame` and `run_for` doing
+//! nothing, until `resume` carries on, either freely or for a step.
+use core::ops::Range;
+
+use crate::{Chip8, Chip8Error, RandomSource, RegisterBank};
+
+/// A register the debugger can watch and read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    /// One of V0 to VF.
+    V(u8),
+    I,
+    Delay,
+    Sound,
+}
+
+impl Register {
+    fn read(self, registers: &RegisterBank) -> usize {
+        match self {
+            Register::V(x) => registers.Vx[x as usize & 0xF] as usize,
+            Register::I => registers.I,
+            Register::Delay => registers.delay as usize,
+            Register::Sound => registers.sound as usize,
+        }
+    }
+}
+
+/// The kind of memory access a watchpoint stops on, or that stopped execution.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    /// Both reads and writes. Never the access that stopped execution.
+    ReadWrite,
+}
+
+impl Access {
+    pub(crate) fn includes(self, access: Access) -> bool {
+        self == Access::ReadWrite || self == access
+    }
+}
+
+/// Why execution stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// The instruction at the breakpoint `pc` is about to be executed.
+    Breakpoint { pc: usize },
+    /// The instruction at `pc` read or wrote the watched `address`.
+    Memory {
+        pc: usize,
+        address: usize,
+        access: Access,
+    },
+    /// The instruction at `pc` changed a watched register from `old` to `new`.
+    Register {
+        pc: usize,
+        register: Register,
+        old: usize,
+        new: usize,
+    },
+    /// The step, step over, step out or run to asked of `resume` is done.
+    Step,
+    /// The host called `pause`.
+    Paused,
+    /// The machine halted on a fault. Only returned by `debug_run`.
+    Halted(Chip8Error),
+    /// The ROM ended itself. Only returned by `debug_run`.
+    Exited,
+}
+
+/// How `resume` carries on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resume {
+    /// Until a breakpoint or watchpoint stops it.
+    Continue,
+    /// For a single instruction.
+    StepInto,
+    /// For a single instruction, or until a 2NNN call returns.
+    StepOver,
+    /// Until the current subroutine returns with 00EE. Like `Continue` outside of
+    /// subroutines.
+    StepOut,
+    /// Until the instruction at the address is about to be executed.
+    RunTo(usize),
+}
+
+/// Where a step ends.
+#[derive(Clone, Copy)]
+enum Target {
+    None,
+    Instruction,
+    // The instruction after a call, once the stack is back to its depth.
+    Return { pc: usize, sp: usize },
+    // Once the stack is shallower than this.
+    Out { sp: usize },
+    Address(usize),
+}
+
+pub(crate) struct Debugger {
+    breakpoints: Vec<usize>,
+    registers: Vec<Register>,
+    stopped: Option<StopReason>,
+    target: Target,
+    // Where execution resumed, which mustn't stop on the breakpoint it stopped at.
+    resumed_at: Option<usize>,
+}
+
+impl Debugger {
+    pub(crate) fn new() -> Self {
+        Debugger {
+            breakpoints: Vec::new(),
+            registers: Vec::new(),
+            stopped: None,
+            target: Target::None,
+            resumed_at: None,
+        }
+    }
+
+    /// Whether the debugger has nothing to check between instructions.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.breakpoints.is_empty()
+            && self.registers.is_empty()
+            && self.stopped.is_none()
+            && matches!(self.target, Target::None)
+            && self.resumed_at.is_none()
+    }
+
+    pub(crate) fn forget_stop(&mut self) {
+        self.stopped = None;
+        self.target = Target::None;
+        self.resumed_at = None;
+    }
+}
+
+impl<R: RandomSource> Chip8<R> {
+    /// Returns whether execution has to stop before the next instruction.
+    pub(crate) fn debug_before_instruction(&mut self) -> bool {
+        if self.debugger.stopped.is_some() {
+            return true;
+        }
+        self.memory.watch_hit.set(None);
+        let pc = self.pc;
+        if self.debugger.resumed_at.take() == Some(pc) {
+            return false;
+        }
+        let reason = if self.debugger.breakpoints.contains(&pc) {
+            StopReason::Breakpoint { pc }
+        } else if matches!(self.debugger.target, Target::Address(address) if address == pc) {
+            StopReason::Step
+        } else {
+            return false;
+        };
+        self.stop(reason);
+        true
+    }
+
+    /// The registers as they were before an instruction, if any are watched.
+    pub(crate) fn watched_registers(&self) -> Option<RegisterBank> {
+        if self.debugger.registers.is_empty() {
+            None
+        } else {
+            Some(self.registers.clone())
+        }
+    }
+
+    /// Returns whether the instruction at `pc`, which has just been executed, stops
+    /// execution. `before` are the registers before it, if any are watched.
+    pub(crate) fn debug_after_instruction(
+        &mut self,
+        pc: usize,
+        before: Option<RegisterBank>,
+    ) -> bool {
+        self.debugger.resumed_at = None;
+        let changed = before.and_then(|before| {
+            self.debugger.registers.iter().find_map(|&register| {
+                let (old, new) = (register.read(&before), register.read(&self.registers));
+                if old != new {
+                    Some(StopReason::Register {
+                        pc,
+                        register,
+                        old,
+                        new,
+                    })
+                } else {
+                    None
+                }
+            })
+        });
+        let step_done = match self.debugger.target {
+            Target::None | Target::Address(_) => false,
+            Target::Instruction => true,
+            Target::Return { pc, sp } => self.pc == pc && self.sp == sp,
+            Target::Out { sp } => self.sp < sp,
+        };
+        let reason = match self.memory.watch_hit.take() {
+            Some((address, access)) => StopReason::Memory {
+                pc,
+                address,
+                access,
+            },
+            None => match changed {
+                Some(reason) => reason,
+                None if step_done => StopReason::Step,
+                None => return false,
+            },
+        };
+        self.stop(reason);
+        true
+    }
+
+    fn stop(&mut self, reason: StopReason) {
+        self.debugger.stopped = Some(reason);
+        self.debugger.target = Target::None;
+    }
+
+    /// Stops before the instruction at `address` whenever it is reached.
+    pub fn add_breakpoint(&mut self, address: usize) {
+        if !self.debugger.breakpoints.contains(&address) {
+            self.debugger.breakpoints.push(address);
+        }
+    }
+
+    /// Returns whether there was a breakpoint at `address`.
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        let count = self.debugger.breakpoints.len();
+        self.debugger
+            .breakpoints
+            .retain(|&breakpoint| breakpoint != address);
+        self.debugger.breakpoints.len() != count
+    }
+
+    pub fn breakpoints(&self) -> &[usize] {
+        &self.debugger.breakpoints
+    }
+
+    /// Stops after instructions that make the `access` to an address in `addresses`.
+    /// Only the memory a ROM accesses with its instructions counts, not fetching
+    /// them.
+    pub fn watch_memory(&mut self, addresses: Range<usize>, access: Access) {
+        self.memory.watches.push((addresses, access));
+    }
+
+    /// Removes the watchpoints on exactly `addresses`, returning whether there were any.
+    pub fn unwatch_memory(&mut self, addresses: Range<usize>) -> bool {
+        let count = self.memory.watches.len();
+        self.memory
+            .watches
+            .retain(|(watched, _)| *watched != addresses);
+        self.memory.watches.len() != count
+    }
+
+    /// Stops after instructions that change `register`. The timers counting down
+    /// don't count.
+    pub fn watch_register(&mut self, register: Register) {
+        if !self.debugger.registers.contains(&register) {
+            self.debugger.registers.push(register);
+        }
+    }
+
+    /// Returns whether `register` was watched.
+    pub fn unwatch_register(&mut self, register: Register) -> bool {
+        let count = self.debugger.registers.len();
+        self.debugger
+            .registers
+            .retain(|&watched| watched != register);
+        self.debugger.registers.len() != count
+    }
+
+    /// Removes every breakpoint and watchpoint.
+    pub fn clear_breakpoints(&mut self) {
+        self.debugger.breakpoints.clear();
+        self.debugger.registers.clear();
+        self.memory.watches.clear();
+    }
+
+    /// Why execution is stopped, `None` while it is running.
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.debugger.stopped
+    }
+
+    /// Stops execution before the next instruction.
+    pub fn pause(&mut self) {
+        self.stop(StopReason::Paused);
+    }
+
+    /// Carries on after execution stopped, the way `how` says. The next calls to
+    /// `run_frame` or `run_for` execute instructions until it stops again.
+    pub fn resume(&mut self, how: Resume) {
+        let pc = self.pc;
+        let opcode = match (self.memory().get(pc), self.memory().get(pc + 1)) {
+            (Some(&high), Some(&low)) => (high as u16) << 8 | low as u16,
+            _ => 0,
+        };
+        self.debugger.target = match how {
+            Resume::Continue => Target::None,
+            Resume::StepInto => Target::Instruction,
+            Resume::StepOver if opcode >> 12 == 0x2 => Target::Return {
+                pc: pc + 2,
+                sp: self.sp,
+            },
+            Resume::StepOver => Target::Instruction,
+            Resume::StepOut if self.sp > 0 => Target::Out { sp: self.sp },
+            Resume::StepOut => Target::None,
+            Resume::RunTo(address) => Target::Address(address),
+        };
+        self.debugger.stopped = None;
+        self.debugger.resumed_at = Some(pc);
+    }
+
+    /// Resumes the way `how` says and runs up to `max_frames` frames, or until
+    /// execution stops. Returns why it stopped, `None` if it didn't.
+    pub fn debug_run(&mut self, how: Resume, max_frames: u32) -> Option<StopReason> {
+        self.resume(how);
+        for _ in 0..max_frames {
+            if let Err(error) = self.run_frame() {
+                return Some(StopReason::Halted(error));
+            }
+            if let Some(reason) = self.stop_reason() {
+                return Some(reason);
+            }
+            if self.has_exited() {
+                return Some(StopReason::Exited);
+            }
+        }
+        None
+    }
+
+    /// The address of the next instruction.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn register(&self, register: Register) -> usize {
+        register.read(&self.registers)
+    }
+
+    /// The return addresses of the subroutines being executed, innermost last.
+    pub fn stack(&self) -> &[usize] {
+        &self.stack[..self.sp]
+    }
+}
@@ -0,0 +1,155 @@
+//! Disassembling whole ROMs, with labels for the addresses they jump to and call.
+//!
+//! ROMs mix code and data, and there is no telling them apart without running
+//! them, so every two bytes are disassembled as an instruction. Sprites come out
+//! as nonsense instructions, which is what other disassemblers do too.
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+
+use crate::{Instruction, Syntax};
+
+/// What a line of a disassembly holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Item {
+    Instruction(Instruction),
+    /// The XO-CHIP F000 NNNN, which loads I with the word NNNN after the opcode.
+    LongLoad(u16),
+    /// A byte left over at the end of the ROM, too short to be an instruction.
+    Byte(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    pub item: Item,
+}
+
+impl Line {
+    /// How many bytes of the ROM the line takes.
+    pub fn size(&self) -> u16 {
+        match self.item {
+            Item::Instruction(_) => 2,
+            Item::LongLoad(_) => 4,
+            Item::Byte(_) => 1,
+        }
+    }
+}
+
+/// A disassembled ROM. Shown with `display`, or looked through line by line.
+pub struct Disassembly {
+    lines: Vec<Line>,
+    labels: BTreeMap<u16, String>,
+}
+
+/// Disassembles `code`, loaded at `load_address` (0x200 for most ROMs).
+pub fn disassemble(code: &[u8], load_address: u16) -> Disassembly {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < code.len() {
+        let address = load_address.wrapping_add(offset as u16);
+        let word = |offset: usize| (code[offset] as u16) << 8 | code[offset + 1] as u16;
+        let item = if offset + 1 == code.len() {
+            Item::Byte(code[offset])
+        } else {
+            match Instruction::decode(word(offset)) {
+                Instruction::LoadLongI if offset + 3 < code.len() => {
+                    Item::LongLoad(word(offset + 2))
+                }
+                instruction => Item::Instruction(instruction),
+            }
+        };
+        let line = Line { address, item };
+        offset += line.size() as usize;
+        lines.push(line);
+    }
+
+    // Only addresses a line starts at can be labelled. Subroutines are named as
+    // such, even if they are also jumped to.
+    let starts: Vec<u16> = lines.iter().map(|line| line.address).collect();
+    let mut labels = BTreeMap::new();
+    for &(name, calls) in &[("sub", true), ("label", false)] {
+        for line in &lines {
+            let target = match line.item {
+                Item::Instruction(instruction) => instruction.target(),
+                _ => None,
+            };
+            let is_call = matches!(line.item, Item::Instruction(Instruction::Call(_)));
+            match target {
+                Some(target) if is_call == calls && starts.binary_search(&target).is_ok() => {
+                    labels
+                        .entry(target)
+                        .or_insert_with(|| format!("{}_{:03X}", name, target));
+                }
+                _ => {}
+            }
+        }
+    }
+    Disassembly { lines, labels }
+}
+
+impl Disassembly {
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// The label given to `address`, if it is jumped to or called.
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    /// Writes the disassembly in `syntax`, a line per instruction with its address
+    /// in a comment.
+    pub fn display(&self, syntax: Syntax) -> DisassemblyDisplay<'_> {
+        DisassemblyDisplay {
+            disassembly: self,
+            syntax,
+        }
+    }
+
+    fn write_item(&self, out: &mut String, item: Item, syntax: Syntax) -> fmt::Result {
+        struct Labelled<'a>(&'a Disassembly, Instruction, Syntax);
+        impl fmt::Display for Labelled<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.1.write(f, self.2, &|address| self.0.label(address))
+            }
+        }
+        match (item, syntax) {
+            (Item::Instruction(instruction), _) => {
+                write!(out, "{}", Labelled(self, instruction, syntax))
+            }
+            (Item::LongLoad(address), Syntax::Octo) => write!(out, "i := long 0x{:04X}", address),
+            (Item::LongLoad(address), Syntax::Cowgod) => write!(out, "LD I, LONG #{:04X}", address),
+            (Item::Byte(byte), Syntax::Octo) => write!(out, "0x{:02X}", byte),
+            (Item::Byte(byte), Syntax::Cowgod) => write!(out, "DB #{:02X}", byte),
+        }
+    }
+}
+
+/// A disassembly written in a syntax, see `Disassembly::display`.
+pub struct DisassemblyDisplay<'a> {
+    disassembly: &'a Disassembly,
+    syntax: Syntax,
+}
+
+impl fmt::Display for DisassemblyDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let comment = match self.syntax {
+            Syntax::Octo => '#',
+            Syntax::Cowgod => ';',
+        };
+        let mut text = String::new();
+        for line in &self.disassembly.lines {
+            if let Some(label) = self.disassembly.label(line.address) {
+                match self.syntax {
+                    Syntax::Octo => writeln!(f, ": {}", label)?,
+                    Syntax::Cowgod => writeln!(f, "{}:", label)?,
+                }
+            }
+            text.clear();
+            self.disassembly
+                .write_item(&mut text, line.item, self.syntax)?;
+            writeln!(f, "  {:<28}{} {:03X}", text, comment, line.address)?;
+        }
+        Ok(())
+    }
+}
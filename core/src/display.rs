@@ -0,0 +1,383 @@
+use crate::memory::Memory;
+
+// XO-CHIP bitplanes, each one has its own frames.
+pub const PLANES: usize = 2;
+// Large enough for the 128x64 SUPER-CHIP high resolution mode.
+pub const MAX_FRAME_SIZE: usize = 128 * 64 / 8;
+#[cfg(feature = "flicker")]
+const MAX_PIXELS: usize = 128 * 64;
+// Where the COSMAC VIP interpreter kept display memory: the frame ends at the top of RAM.
+const VIP_DISPLAY_END: usize = 0x1000;
+
+/// How a frame is turned into what hosts show, to hide the flicker of ROMs that
+/// erase sprites and draw them again.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FlickerFilter {
+    /// Show the frame exactly as the ROM left it.
+    Off,
+    /// OR every drawn sprite with the frame before it was drawn.
+    #[default]
+    OrPrevious,
+    /// Show pixels that were on at any point in the last few frames.
+    #[cfg(feature = "flicker")]
+    Persistence,
+    /// Let pixels fade out like the phosphor of a CRT, in shades of grey.
+    #[cfg(feature = "flicker")]
+    PhosphorDecay,
+}
+
+/// The sizes the display can take.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Resolution {
+    /// 64x32, the original CHIP-8 display.
+    #[default]
+    Low,
+    /// 64x64, the two-page display of the hires CHIP-8 interpreter.
+    Tall,
+    /// 128x64, SUPER-CHIP's and XO-CHIP's high resolution mode.
+    High,
+}
+
+impl Resolution {
+    pub fn width(self) -> usize {
+        match self {
+            Resolution::Low | Resolution::Tall => 64,
+            Resolution::High => 128,
+        }
+    }
+
+    pub fn height(self) -> usize {
+        match self {
+            Resolution::Low => 32,
+            Resolution::Tall | Resolution::High => 64,
+        }
+    }
+}
+
+/// One bitplane. Sprites are drawn onto `current`, while `shown` and `intensity`
+/// are what hosts display once the flicker filter has been applied.
+///
+/// The buffers the persistence and phosphor decay filters need take 16 KiB a
+/// plane, they only exist with the `flicker` feature.
+struct Plane {
+    shown: [u8; MAX_FRAME_SIZE],
+    current: [u8; MAX_FRAME_SIZE],
+    // Every pixel that was on at some point during the frame.
+    #[cfg(feature = "flicker")]
+    lit: [u8; MAX_FRAME_SIZE],
+    // One byte per pixel, from 0 (off) to 255 (fully lit).
+    #[cfg(feature = "flicker")]
+    intensity: [u8; MAX_PIXELS],
+    // Frames since each pixel was last lit, for the persistence filter.
+    #[cfg(feature = "flicker")]
+    age: [u8; MAX_PIXELS],
+}
+
+impl Plane {
+    fn new() -> Self {
+        Plane {
+            shown: [0; MAX_FRAME_SIZE],
+            current: [0; MAX_FRAME_SIZE],
+            #[cfg(feature = "flicker")]
+            lit: [0; MAX_FRAME_SIZE],
+            #[cfg(feature = "flicker")]
+            intensity: [0; MAX_PIXELS],
+            #[cfg(feature = "flicker")]
+            age: [u8::MAX; MAX_PIXELS],
+        }
+    }
+}
+
+fn bit(frame: &[u8], bit: usize) -> bool {
+    frame[bit / 8] & (0b1000_0000 >> (bit % 8)) != 0
+}
+
+fn set_bit(frame: &mut [u8], bit: usize, on: bool) {
+    let mask = 0b1000_0000 >> (bit % 8);
+    if on {
+        frame[bit / 8] |= mask;
+    } else {
+        frame[bit / 8] &= !mask;
+    }
+}
+
+/// The display, kept apart from the interpreter's memory so ROMs can't corrupt it
+/// by accident. Pixels are packed 8 to a byte, row by row, and only the first
+/// `size()` bytes of each frame are in use at the current resolution.
+pub(crate) struct Framebuffer {
+    planes: [Plane; PLANES],
+    resolution: Resolution,
+    // Bitmask of the XO-CHIP planes drawing, clearing and scrolling apply to.
+    selected_planes: u8,
+    pub(crate) filter: FlickerFilter,
+    // How many of the latest frames the persistence filter looks back over.
+    #[cfg(feature = "flicker")]
+    pub(crate) persistence_frames: u8,
+    // The share of its intensity a pixel keeps every frame with phosphor decay.
+    #[cfg(feature = "flicker")]
+    pub(crate) decay: f32,
+}
+
+impl Framebuffer {
+    pub(crate) fn new() -> Self {
+        Framebuffer {
+            planes: [(); PLANES].map(|()| Plane::new()),
+            resolution: Resolution::default(),
+            selected_planes: 0b01,
+            filter: FlickerFilter::default(),
+            #[cfg(feature = "flicker")]
+            persistence_frames: 2,
+            #[cfg(feature = "flicker")]
+            decay: 0.6,
+        }
+    }
+
+    /// Blanks the display and goes back to low resolution, keeping the filter.
+    pub(crate) fn reset(&mut self) {
+        self.set_resolution(Resolution::default());
+        self.selected_planes = 0b01;
+    }
+
+    pub(crate) fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    /// Switching resolution clears every plane, not only the selected ones.
+    pub(crate) fn set_resolution(&mut self, resolution: Resolution) {
+        self.resolution = resolution;
+        for plane in self.planes.iter_mut() {
+            *plane = Plane::new();
+        }
+    }
+
+    pub(crate) fn width(&self) -> usize {
+        self.resolution.width()
+    }
+
+    pub(crate) fn height(&self) -> usize {
+        self.resolution.height()
+    }
+
+    /// Size in bytes of a frame at the current resolution.
+    pub(crate) fn size(&self) -> usize {
+        self.width() * self.height() / 8
+    }
+
+    pub(crate) fn select_planes(&mut self, planes: u8) {
+        self.selected_planes = planes & 0b11;
+    }
+
+    /// The bitmask of the currently selected planes.
+    pub(crate) fn selected_plane_mask(&self) -> u8 {
+        self.selected_planes
+    }
+
+    /// The indices of the currently selected planes.
+    pub(crate) fn selected_planes(&self) -> impl Iterator<Item = usize> {
+        let selected = self.selected_planes;
+        (0..PLANES).filter(move |plane| selected & (1 << plane) != 0)
+    }
+
+    pub(crate) fn shown(&self, plane: usize) -> &[u8] {
+        &self.planes[plane].shown[..self.size()]
+    }
+
+    pub(crate) fn current(&self, plane: usize) -> &[u8] {
+        &self.planes[plane].current[..self.size()]
+    }
+
+    /// One byte per pixel, row by row.
+    #[cfg(feature = "flicker")]
+    pub(crate) fn intensity(&self, plane: usize) -> &[u8] {
+        &self.planes[plane].intensity[..self.width() * self.height()]
+    }
+
+    pub(crate) fn pixel(&self, plane: usize, x: usize, y: usize) -> bool {
+        bit(&self.planes[plane].current, y * self.width() + x)
+    }
+
+    pub(crate) fn set_pixel(&mut self, plane: usize, x: usize, y: usize, on: bool) {
+        let width = self.width();
+        set_bit(&mut self.planes[plane].current, y * width + x, on);
+    }
+
+    pub(crate) fn clear(&mut self, plane: usize) {
+        self.planes[plane].current.fill(0);
+    }
+
+    /// Updates what is shown after the current frame was changed by anything but
+    /// drawing a sprite.
+    pub(crate) fn present(&mut self) {
+        let filter = self.filter;
+        for plane in self.planes.iter_mut() {
+            #[cfg(feature = "flicker")]
+            for (lit, current) in plane.lit.iter_mut().zip(plane.current.iter()) {
+                *lit |= current;
+            }
+            match filter {
+                FlickerFilter::Off | FlickerFilter::OrPrevious => {
+                    plane.shown.copy_from_slice(&plane.current)
+                }
+                // Pixels turned off during the frame stay on until it ends.
+                #[cfg(feature = "flicker")]
+                FlickerFilter::Persistence | FlickerFilter::PhosphorDecay => {
+                    for (shown, current) in plane.shown.iter_mut().zip(plane.current.iter()) {
+                        *shown |= current;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Applies the flicker filter to the frame that just ended, which updates the
+    /// intensity buffers. Called on every 60 Hz timer tick.
+    #[cfg(feature = "flicker")]
+    pub(crate) fn end_frame(&mut self) {
+        let pixels = self.width() * self.height();
+        let (filter, persistence_frames, decay) =
+            (self.filter, self.persistence_frames, self.decay);
+        for plane in self.planes.iter_mut() {
+            for pixel in 0..pixels {
+                let on = bit(&plane.lit, pixel);
+                let intensity = match filter {
+                    FlickerFilter::Off | FlickerFilter::OrPrevious => {
+                        if bit(&plane.shown, pixel) {
+                            u8::MAX
+                        } else {
+                            0
+                        }
+                    }
+                    FlickerFilter::Persistence => {
+                        plane.age[pixel] = if on {
+                            0
+                        } else {
+                            plane.age[pixel].saturating_add(1)
+                        };
+                        if plane.age[pixel] < persistence_frames {
+                            u8::MAX
+                        } else {
+                            0
+                        }
+                    }
+                    FlickerFilter::PhosphorDecay => {
+                        if on {
+                            u8::MAX
+                        } else {
+                            (plane.intensity[pixel] as f32 * decay) as u8
+                        }
+                    }
+                };
+                plane.intensity[pixel] = intensity;
+                if let FlickerFilter::Persistence | FlickerFilter::PhosphorDecay = filter {
+                    // Hosts reading bits see pixels for as long as they are at least
+                    // half as bright as a lit one.
+                    set_bit(&mut plane.shown, pixel, intensity >= 0x80);
+                }
+            }
+            plane.lit.copy_from_slice(&plane.current);
+        }
+    }
+
+    /// Without the intensity buffers, what is shown only changes when the frame does.
+    #[cfg(not(feature = "flicker"))]
+    pub(crate) fn end_frame(&mut self) {}
+
+    /// Puts back frames saved from `current`, as if the ROM had just drawn them.
+    pub(crate) fn restore(
+        &mut self,
+        resolution: Resolution,
+        selected_planes: u8,
+        frames: [&[u8]; PLANES],
+    ) {
+        self.set_resolution(resolution);
+        self.select_planes(selected_planes);
+        let size = self.size();
+        for (plane, frame) in self.planes.iter_mut().zip(frames.iter()) {
+            plane.current[..size].copy_from_slice(frame);
+            plane.shown.copy_from_slice(&plane.current);
+            #[cfg(feature = "flicker")]
+            plane.lit.copy_from_slice(&plane.current);
+        }
+        self.end_frame();
+    }
+
+    /// XORs a sprite onto one plane, returning the number of rows that collided and
+    /// the number that were clipped off the bottom of the screen when `clip` is on.
+    pub(crate) fn draw_sprite(
+        &mut self,
+        plane: usize,
+        vx: u8,
+        vy: u8,
+        sprite: &[u8],
+        sprite_width: usize,
+        clip: bool,
+    ) -> (usize, usize) {
+        let (width, height) = (self.width(), self.height());
+        let filter = self.filter;
+        let Plane {
+            shown,
+            current,
+            #[cfg(feature = "flicker")]
+            lit,
+            ..
+        } = &mut self.planes[plane];
+        if filter == FlickerFilter::OrPrevious {
+            shown.copy_from_slice(current);
+        }
+
+        // The starting position always wraps, the rest of the sprite is clipped
+        // at the edges of the screen when the clip quirk is on.
+        let (vx, vy) = (vx as usize % width, vy as usize % height);
+        let rows = sprite.len() / (sprite_width / 8);
+        let (mut collisions, mut clipped) = (0, 0);
+        for (y, row) in sprite.chunks(sprite_width / 8).enumerate() {
+            if clip && vy + y >= height {
+                clipped = rows - y;
+                break;
+            }
+            let sprite_row =
+                row.iter().fold(0u16, |acc, &byte| acc << 8 | byte as u16) << (16 - sprite_width);
+            let mut row_collided = false;
+            for x in 0..sprite_width {
+                if clip && vx + x >= width {
+                    break;
+                }
+                if sprite_row & (0b1000_0000_0000_0000 >> x) == 0 {
+                    continue;
+                }
+                let bit = ((vy + y) % height) * width + (vx + x) % width;
+                let screen_mask: u8 = 0b1000_0000 >> (bit % 8);
+                if current[bit / 8] & screen_mask != 0 {
+                    row_collided = true;
+                }
+                current[bit / 8] ^= screen_mask;
+            }
+            if row_collided {
+                collisions += 1;
+            }
+        }
+
+        #[cfg(feature = "flicker")]
+        for (lit, current) in lit.iter_mut().zip(current.iter()) {
+            *lit |= current;
+        }
+        if filter == FlickerFilter::Off {
+            shown.copy_from_slice(current);
+        } else {
+            for (shown, current) in shown.iter_mut().zip(current.iter()) {
+                *shown |= current;
+            }
+        }
+        (collisions, clipped)
+    }
+
+    /// Copies the first plane into `memory` where the COSMAC VIP kept its display,
+    /// for ROMs that read the screen directly. Only the 64x32 and 64x64 frames fit
+    /// below the top of 4 KiB of RAM, high resolution frames aren't mirrored.
+    pub(crate) fn mirror_into(&self, memory: &mut Memory) {
+        if self.resolution == Resolution::High {
+            return;
+        }
+        memory.copy_in(VIP_DISPLAY_END - self.size(), self.current(0));
+    }
+}
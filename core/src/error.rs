@@ -0,0 +1,319 @@
+use core::fmt;
+
+/// Everything that can go wrong while the interpreter is executing a ROM.
+///
+/// Each variant carries the address of the offending instruction (`pc`) and the
+/// instruction itself (`opcode`), so a host can tell the user exactly where the
+/// ROM went off the rails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Error {
+    UnknownOpcode {
+        pc: usize,
+        opcode: u16,
+    },
+    StackOverflow {
+        pc: usize,
+        opcode: u16,
+    },
+    StackUnderflow {
+        pc: usize,
+        opcode: u16,
+    },
+    MemoryOutOfBounds {
+        pc: usize,
+        opcode: u16,
+        address: usize,
+    },
+    InvalidKey {
+        pc: usize,
+        opcode: u16,
+        key: u8,
+    },
+}
+
+impl Chip8Error {
+    /// Stable, machine readable identifier of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Chip8Error::UnknownOpcode { .. } => "UNKNOWN_OPCODE",
+            Chip8Error::StackOverflow { .. } => "STACK_OVERFLOW",
+            Chip8Error::StackUnderflow { .. } => "STACK_UNDERFLOW",
+            Chip8Error::MemoryOutOfBounds { .. } => "MEMORY_OUT_OF_BOUNDS",
+            Chip8Error::InvalidKey { .. } => "INVALID_KEY",
+        }
+    }
+
+    pub fn pc(&self) -> usize {
+        match *self {
+            Chip8Error::UnknownOpcode { pc, .. }
+            | Chip8Error::StackOverflow { pc, .. }
+            | Chip8Error::StackUnderflow { pc, .. }
+            | Chip8Error::MemoryOutOfBounds { pc, .. }
+            | Chip8Error::InvalidKey { pc, .. } => pc,
+        }
+    }
+
+    pub fn opcode(&self) -> u16 {
+        match *self {
+            Chip8Error::UnknownOpcode { opcode, .. }
+            | Chip8Error::StackOverflow { opcode, .. }
+            | Chip8Error::StackUnderflow { opcode, .. }
+            | Chip8Error::MemoryOutOfBounds { opcode, .. }
+            | Chip8Error::InvalidKey { opcode, .. } => opcode,
+        }
+    }
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Chip8Error::UnknownOpcode { .. } => write!(f, "Unknown instruction encountered")?,
+            Chip8Error::StackOverflow { .. } => write!(f, "Stack exceeded maximum size")?,
+            Chip8Error::StackUnderflow { .. } => write!(f, "Returned with an empty stack")?,
+            Chip8Error::MemoryOutOfBounds { address, .. } => {
+                write!(f, "Memory access out of bounds at {:04X}", address)?
+            }
+            Chip8Error::InvalidKey { key, .. } => write!(f, "Invalid key index {:X}", key)?,
+        }
+        write!(f, " (pc: {:04X}, opcode: {:04X})", self.pc(), self.opcode())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Chip8Error {}
+
+/// Mistakes a host can make when driving the interpreter, as opposed to faults
+/// in the ROM it is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostError {
+    /// There is no bundled game with that title.
+    UnknownGame,
+    /// The ROM doesn't fit in memory above 0x200.
+    RomTooLarge { size: usize, capacity: usize },
+    /// Keys go from 0 to F.
+    InvalidKey(usize),
+    /// The display has the planes 0 and 1.
+    InvalidPlane(usize),
+    /// There is no quirk profile by that name.
+    UnknownQuirkProfile,
+}
+
+impl fmt::Display for HostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            HostError::UnknownGame => write!(f, "unknown game chosen"),
+            HostError::RomTooLarge { size, capacity } => write!(
+                f,
+                "ROM of {} bytes doesn't fit in the {} bytes available",
+                size, capacity
+            ),
+            HostError::InvalidKey(key) => write!(f, "invalid key index {}", key),
+            HostError::InvalidPlane(plane) => write!(f, "invalid display plane {}", plane),
+            HostError::UnknownQuirkProfile => write!(f, "unknown quirk profile"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HostError {}
+
+/// Why a save state couldn't be saved or loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    /// The data doesn't start with the save state header.
+    NotASaveState,
+    /// The state was saved in a version of the format this build can't read.
+    UnsupportedVersion(u16),
+    /// The data ends before the state does.
+    Truncated,
+    /// The checksum doesn't match, the state was damaged after it was saved.
+    ChecksumMismatch,
+    /// The state holds a value no machine can be in, e.g. a stack pointer past the
+    /// end of the stack. The field is named.
+    InvalidValue(&'static str),
+    /// The buffer passed to `save_state_into` can't hold the state.
+    BufferTooSmall { size: usize, needed: usize },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            StateError::NotASaveState => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => write!(
+                f,
+                "save state version {} is not supported, only version {} is",
+                version,
+                crate::state::STATE_VERSION
+            ),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::ChecksumMismatch => write!(f, "save state is corrupted"),
+            StateError::InvalidValue(field) => {
+                write!(f, "save state holds an invalid {}", field)
+            }
+            StateError::BufferTooSmall { size, needed } => write!(
+                f,
+                "save state of {} bytes doesn't fit in the {} bytes given",
+                needed, size
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for StateError {}
+
+/// Why a movie couldn't be read or played back.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovieError {
+    /// The data doesn't start with the movie header.
+    NotAMovie,
+    /// The movie was written in a version of the format this build can't read.
+    UnsupportedVersion(u16),
+    /// The data ends before the movie does.
+    Truncated,
+    /// The checksum doesn't match, the movie was damaged after it was written.
+    ChecksumMismatch,
+    /// The movie holds a value that can't be played back. The field is named.
+    InvalidValue(&'static str),
+    /// The movie was recorded with another ROM than the one loaded, by CRC-32.
+    RomMismatch { expected: u32, found: u32 },
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            MovieError::NotAMovie => write!(f, "not a movie"),
+            MovieError::UnsupportedVersion(version) => write!(
+                f,
+                "movie version {} is not supported, only version {} is",
+                version,
+                crate::movie::MOVIE_VERSION
+            ),
+            MovieError::Truncated => write!(f, "movie is truncated"),
+            MovieError::ChecksumMismatch => write!(f, "movie is corrupted"),
+            MovieError::InvalidValue(field) => write!(f, "movie holds an invalid {}", field),
+            MovieError::RomMismatch { expected, found } => write!(
+                f,
+                "movie was recorded with the ROM {:08X}, not the loaded {:08X}",
+                expected, found
+            ),
+        }
+    }
+}
+
+// Movies embed a save state and read their fields the same way.
+#[cfg(feature = "std")]
+impl From<StateError> for MovieError {
+    fn from(error: StateError) -> Self {
+        match error {
+            StateError::Truncated => MovieError::Truncated,
+            StateError::InvalidValue(field) => MovieError::InvalidValue(field),
+            _ => MovieError::InvalidValue("start state"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MovieError {}
+
+/// Why Octo source couldn't be assembled, and where. Lines and columns count from 1.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AssembleError {}
+
+/// Why a ROM file or bundled game couldn't be loaded.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum RomError {
+    /// There is neither a file nor a bundled game by that name.
+    NotFound,
+    /// The file couldn't be read.
+    Io(std::io::Error),
+    /// The file is Octo source that doesn't assemble.
+    Assemble(AssembleError),
+    /// The ROM couldn't be loaded, e.g. because it doesn't fit in memory.
+    Host(HostError),
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::NotFound => write!(f, "neither a ROM file nor a bundled game"),
+            RomError::Io(error) => write!(f, "{}", error),
+            RomError::Assemble(error) => write!(f, "{}", error),
+            RomError::Host(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for RomError {
+    fn from(error: std::io::Error) -> Self {
+        RomError::Io(error)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<AssembleError> for RomError {
+    fn from(error: AssembleError) -> Self {
+        RomError::Assemble(error)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<HostError> for RomError {
+    fn from(error: HostError) -> Self {
+        RomError::Host(error)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RomError {}
+
+/// Failures detected by the instruction handlers, which don't know where they were called from.
+/// `step` turns them into a `Chip8Error` by attaching the pc and opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Fault {
+    UnknownOpcode,
+    StackOverflow,
+    StackUnderflow,
+    MemoryOutOfBounds(usize),
+    InvalidKey(u8),
+}
+
+impl Fault {
+    pub(crate) fn at(self, pc: usize, opcode: u16) -> Chip8Error {
+        match self {
+            Fault::UnknownOpcode => Chip8Error::UnknownOpcode { pc, opcode },
+            Fault::StackOverflow => Chip8Error::StackOverflow { pc, opcode },
+            Fault::StackUnderflow => Chip8Error::StackUnderflow { pc, opcode },
+            Fault::MemoryOutOfBounds(address) => Chip8Error::MemoryOutOfBounds {
+                pc,
+                opcode,
+                address,
+            },
+            Fault::InvalidKey(key) => Chip8Error::InvalidKey { pc, opcode, key },
+        }
+    }
+}
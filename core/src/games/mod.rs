@@ -0,0 +1,156 @@
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+#[cfg(feature = "std")]
+use crate::assembler::assemble;
+use crate::error::HostError;
+#[cfg(feature = "std")]
+use crate::error::RomError;
+#[cfg(feature = "std")]
+use crate::quirks::QuirkProfile;
+use crate::quirks::Quirks;
+
+pub const TETRIS: &[u8] = include_bytes!("TETRIS");
+pub const BRIX: &[u8] = include_bytes!("BRIX");
+pub const PONG: &[u8] = include_bytes!("PONG");
+pub const PONG2: &[u8] = include_bytes!("PONG2");
+pub const INVADERS: &[u8] = include_bytes!("INVADERS");
+pub const SCTEST: &[u8] = include_bytes!("test_ROMs/SCTEST.ch8");
+pub const BCTEST: &[u8] = include_bytes!("test_ROMs/BC_test.ch8");
+pub const C8TEST: &[u8] = include_bytes!("test_ROMs/c8_test.ch8");
+pub const SAMPLE: &[u8] = include_bytes!("test_ROMs/sample.ch8");
+pub const OPCODE_TEST: &[u8] = include_bytes!("test_ROMs/opcode_test.ch8");
+
+// The quirks every bundled game has been played with so far.
+pub const DEFAULT_QUIRKS: Quirks = Quirks {
+    clip: false,
+    jump: false,
+    ..Quirks::chip48()
+};
+
+#[derive(Clone, Copy)]
+pub struct Game {
+    pub code: &'static [u8],
+    pub quirks: Quirks,
+}
+
+// The bundled games by title, which is matched ignoring case.
+const GAMES: &[(&str, Game)] = &[
+    (
+        "tetris",
+        Game {
+            code: TETRIS,
+            quirks: DEFAULT_QUIRKS,
+        },
+    ),
+    (
+        "brix",
+        Game {
+            code: BRIX,
+            quirks: DEFAULT_QUIRKS,
+        },
+    ),
+    (
+        "pong",
+        Game {
+            code: PONG,
+            quirks: DEFAULT_QUIRKS,
+        },
+    ),
+    (
+        "pong2",
+        Game {
+            code: PONG2,
+            quirks: DEFAULT_QUIRKS,
+        },
+    ),
+    (
+        "invaders",
+        Game {
+            code: INVADERS,
+            quirks: DEFAULT_QUIRKS,
+        },
+    ),
+    (
+        "sctest",
+        Game {
+            code: SCTEST,
+            quirks: Quirks {
+                index_overflow: true,
+                ..Quirks::super_chip_modern()
+            },
+        },
+    ),
+    (
+        "bctest",
+        Game {
+            code: BCTEST,
+            quirks: DEFAULT_QUIRKS,
+        },
+    ),
+    (
+        "c8test",
+        Game {
+            code: C8TEST,
+            quirks: DEFAULT_QUIRKS,
+        },
+    ),
+    (
+        "sample",
+        Game {
+            code: SAMPLE,
+            quirks: DEFAULT_QUIRKS,
+        },
+    ),
+    (
+        "opcode_test",
+        Game {
+            code: OPCODE_TEST,
+            quirks: DEFAULT_QUIRKS,
+        },
+    ),
+];
+
+impl Game {
+    pub fn new(title: &str) -> Result<Self, HostError> {
+        GAMES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(title))
+            .map(|(_, game)| *game)
+            .ok_or(HostError::UnknownGame)
+    }
+}
+
+/// Reads the ROM file at `rom`, assembling it first if it is Octo source, or takes
+/// the bundled game of that title if there is no such file. The quirks to run it
+/// with are `profile`'s, or else the game's, or `DEFAULT_QUIRKS` for files.
+#[cfg(feature = "std")]
+pub fn read_rom_or_game(
+    rom: &str,
+    profile: Option<QuirkProfile>,
+) -> Result<(Vec<u8>, Quirks), RomError> {
+    let path = Path::new(rom);
+    let (code, quirks) = if path.is_file() {
+        let code = if path.extension().is_some_and(|extension| extension == "8o") {
+            assemble(&fs::read_to_string(path)?)?.code().to_vec()
+        } else {
+            fs::read(path)?
+        };
+        (code, DEFAULT_QUIRKS)
+    } else {
+        let game = Game::new(rom).map_err(|_| RomError::NotFound)?;
+        (game.code.to_vec(), game.quirks)
+    };
+    Ok((code, profile.map_or(quirks, Quirks::from)))
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Game {
+            code: BCTEST,
+            quirks: DEFAULT_QUIRKS,
+        }
+    }
+}
@@ -0,0 +1,390 @@
+//! Decoding opcodes into instructions, and writing instructions in Octo syntax or
+//! as the classic mnemonics of Cowgod's technical reference.
+use core::fmt;
+
+/// A decoded CHIP-8, SUPER-CHIP or XO-CHIP instruction. `x` and `y` name the
+/// registers Vx and Vy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 00CN, SUPER-CHIP: scrolls the display down `n` pixels.
+    ScrollDown(u8),
+    /// 00DN, XO-CHIP: scrolls the display up `n` pixels.
+    ScrollUp(u8),
+    /// 00E0
+    Clear,
+    /// 0230, the hires CHIP-8 interpreter's clear for its 64x64 display.
+    ClearHires,
+    /// 00EE
+    Return,
+    /// 00FB, SUPER-CHIP: scrolls the display right 4 pixels.
+    ScrollRight,
+    /// 00FC, SUPER-CHIP: scrolls the display left 4 pixels.
+    ScrollLeft,
+    /// 00FD, SUPER-CHIP
+    Exit,
+    /// 00FE, SUPER-CHIP: switches to the 64x32 display.
+    Lores,
+    /// 00FF, SUPER-CHIP: switches to the 128x64 display.
+    Hires,
+    /// 1NNN
+    Jump(u16),
+    /// 2NNN
+    Call(u16),
+    /// 3XNN: skips the next instruction if Vx == NN.
+    SkipIfEqualByte { x: u8, byte: u8 },
+    /// 4XNN: skips the next instruction if Vx != NN.
+    SkipIfNotEqualByte { x: u8, byte: u8 },
+    /// 5XY0: skips the next instruction if Vx == Vy.
+    SkipIfEqual { x: u8, y: u8 },
+    /// 5XY2, XO-CHIP: stores Vx to Vy at I.
+    StoreRange { x: u8, y: u8 },
+    /// 5XY3, XO-CHIP: loads Vx to Vy from I.
+    LoadRange { x: u8, y: u8 },
+    /// 6XNN
+    LoadByte { x: u8, byte: u8 },
+    /// 7XNN
+    AddByte { x: u8, byte: u8 },
+    /// 8XY0
+    Load { x: u8, y: u8 },
+    /// 8XY1
+    Or { x: u8, y: u8 },
+    /// 8XY2
+    And { x: u8, y: u8 },
+    /// 8XY3
+    Xor { x: u8, y: u8 },
+    /// 8XY4
+    Add { x: u8, y: u8 },
+    /// 8XY5: Vx = Vx - Vy.
+    Sub { x: u8, y: u8 },
+    /// 8XY6
+    ShiftRight { x: u8, y: u8 },
+    /// 8XY7: Vx = Vy - Vx.
+    SubReverse { x: u8, y: u8 },
+    /// 8XYE
+    ShiftLeft { x: u8, y: u8 },
+    /// 9XY0: skips the next instruction if Vx != Vy.
+    SkipIfNotEqual { x: u8, y: u8 },
+    /// ANNN
+    LoadI(u16),
+    /// BNNN: jumps to NNN plus V0, or plus Vx with the jump quirk.
+    JumpRelative(u16),
+    /// CXNN: loads a random number masked with NN.
+    Random { x: u8, byte: u8 },
+    /// DXYN
+    Draw { x: u8, y: u8, n: u8 },
+    /// EX9E
+    SkipIfKey { x: u8 },
+    /// EXA1
+    SkipIfNotKey { x: u8 },
+    /// F000 NNNN, XO-CHIP: loads I with the word that follows the instruction.
+    LoadLongI,
+    /// FN01, XO-CHIP: selects the bitplanes drawn to.
+    SelectPlanes(u8),
+    /// F002, XO-CHIP: loads the audio pattern from I.
+    LoadAudio,
+    /// FX07
+    LoadDelay { x: u8 },
+    /// FX0A
+    WaitKey { x: u8 },
+    /// FX15
+    SetDelay { x: u8 },
+    /// FX18
+    SetSound { x: u8 },
+    /// FX1E
+    AddI { x: u8 },
+    /// FX29
+    Font { x: u8 },
+    /// FX30, SUPER-CHIP
+    BigFont { x: u8 },
+    /// FX33
+    Bcd { x: u8 },
+    /// FX3A, XO-CHIP
+    SetPitch { x: u8 },
+    /// FX55
+    Store { x: u8 },
+    /// FX65
+    LoadMemory { x: u8 },
+    /// FX75, SUPER-CHIP: saves V0 to Vx to the RPL flags.
+    StoreFlags { x: u8 },
+    /// FX85, SUPER-CHIP
+    LoadFlags { x: u8 },
+    /// Any opcode that isn't an instruction.
+    Unknown(u16),
+}
+
+impl Instruction {
+    pub fn decode(opcode: u16) -> Instruction {
+        use Instruction::*;
+        let nibbles = (
+            (opcode >> 12 & 0xF) as u8,
+            (opcode >> 8 & 0xF) as u8,
+            (opcode >> 4 & 0xF) as u8,
+            (opcode & 0xF) as u8,
+        );
+        let byte = opcode as u8;
+        let address = opcode & 0x0FFF;
+        match nibbles {
+            (0x0, 0x0, 0xC, n) => ScrollDown(n),
+            (0x0, 0x0, 0xD, n) => ScrollUp(n),
+            (0x0, 0x0, 0xE, 0x0) => Clear,
+            (0x0, 0x2, 0x3, 0x0) => ClearHires,
+            (0x0, 0x0, 0xE, 0xE) => Return,
+            (0x0, 0x0, 0xF, 0xB) => ScrollRight,
+            (0x0, 0x0, 0xF, 0xC) => ScrollLeft,
+            (0x0, 0x0, 0xF, 0xD) => Exit,
+            (0x0, 0x0, 0xF, 0xE) => Lores,
+            (0x0, 0x0, 0xF, 0xF) => Hires,
+            (0x1, _, _, _) => Jump(address),
+            (0x2, _, _, _) => Call(address),
+            (0x3, x, _, _) => SkipIfEqualByte { x, byte },
+            (0x4, x, _, _) => SkipIfNotEqualByte { x, byte },
+            (0x5, x, y, 0) => SkipIfEqual { x, y },
+            (0x5, x, y, 2) => StoreRange { x, y },
+            (0x5, x, y, 3) => LoadRange { x, y },
+            (0x6, x, _, _) => LoadByte { x, byte },
+            (0x7, x, _, _) => AddByte { x, byte },
+            (0x8, x, y, 0) => Load { x, y },
+            (0x8, x, y, 1) => Or { x, y },
+            (0x8, x, y, 2) => And { x, y },
+            (0x8, x, y, 3) => Xor { x, y },
+            (0x8, x, y, 4) => Add { x, y },
+            (0x8, x, y, 5) => Sub { x, y },
+            (0x8, x, y, 6) => ShiftRight { x, y },
+            (0x8, x, y, 7) => SubReverse { x, y },
+            (0x8, x, y, 0xE) => ShiftLeft { x, y },
+            (0x9, x, y, 0) => SkipIfNotEqual { x, y },
+            (0xA, _, _, _) => LoadI(address),
+            (0xB, _, _, _) => JumpRelative(address),
+            (0xC, x, _, _) => Random { x, byte },
+            (0xD, x, y, n) => Draw { x, y, n },
+            (0xE, x, 0x9, 0xE) => SkipIfKey { x },
+            (0xE, x, 0xA, 0x1) => SkipIfNotKey { x },
+            (0xF, 0x0, 0x0, 0x0) => LoadLongI,
+            (0xF, n, 0x0, 0x1) => SelectPlanes(n),
+            (0xF, 0x0, 0x0, 0x2) => LoadAudio,
+            (0xF, x, 0x0, 0x7) => LoadDelay { x },
+            (0xF, x, 0x0, 0xA) => WaitKey { x },
+            (0xF, x, 0x1, 0x5) => SetDelay { x },
+            (0xF, x, 0x1, 0x8) => SetSound { x },
+            (0xF, x, 0x1, 0xE) => AddI { x },
+            (0xF, x, 0x2, 0x9) => Font { x },
+            (0xF, x, 0x3, 0x0) => BigFont { x },
+            (0xF, x, 0x3, 0x3) => Bcd { x },
+            (0xF, x, 0x3, 0xA) => SetPitch { x },
+            (0xF, x, 0x5, 0x5) => Store { x },
+            (0xF, x, 0x6, 0x5) => LoadMemory { x },
+            (0xF, x, 0x7, 0x5) => StoreFlags { x },
+            (0xF, x, 0x8, 0x5) => LoadFlags { x },
+            _ => Unknown(opcode),
+        }
+    }
+
+    /// The address the instruction jumps to or calls, for labelling it.
+    pub fn target(self) -> Option<u16> {
+        match self {
+            Instruction::Jump(address)
+            | Instruction::Call(address)
+            | Instruction::JumpRelative(address) => Some(address),
+            _ => None,
+        }
+    }
+
+    /// Writes the instruction in `syntax`. The operand of `LoadLongI` is the word
+    /// after it, which isn't part of the instruction and so isn't written.
+    pub fn display(self, syntax: Syntax) -> InstructionDisplay {
+        InstructionDisplay {
+            instruction: self,
+            syntax,
+        }
+    }
+
+    /// Writes the instruction, with the name `label` gives an address if it gives one.
+    pub(crate) fn write<'a>(
+        self,
+        f: &mut fmt::Formatter<'_>,
+        syntax: Syntax,
+        label: &dyn Fn(u16) -> Option<&'a str>,
+    ) -> fmt::Result {
+        match syntax {
+            Syntax::Octo => self.write_octo(f, label),
+            Syntax::Cowgod => self.write_cowgod(f, label),
+        }
+    }
+
+    fn write_octo<'a>(
+        self,
+        f: &mut fmt::Formatter<'_>,
+        label: &dyn Fn(u16) -> Option<&'a str>,
+    ) -> fmt::Result {
+        use Instruction::*;
+        let address = |address: u16| Address {
+            address,
+            label: label(address),
+            prefix: "0x",
+        };
+        match self {
+            ScrollDown(n) => write!(f, "scroll-down {}", n),
+            ScrollUp(n) => write!(f, "scroll-up {}", n),
+            Clear => write!(f, "clear"),
+            // Octo doesn't know the hires CHIP-8 instructions.
+            ClearHires => write!(f, "0x02 0x30"),
+            Return => write!(f, "return"),
+            ScrollRight => write!(f, "scroll-right"),
+            ScrollLeft => write!(f, "scroll-left"),
+            Exit => write!(f, "exit"),
+            Lores => write!(f, "lores"),
+            Hires => write!(f, "hires"),
+            Jump(target) => write!(f, "jump {}", address(target)),
+            // Octo calls labels by their name alone.
+            Call(target) => match label(target) {
+                Some(name) => write!(f, "{}", name),
+                None => write!(f, ":call {}", address(target)),
+            },
+            // The skips are written as the condition under which the next
+            // instruction runs.
+            SkipIfEqualByte { x, byte } => write!(f, "if v{:x} != 0x{:02X} then", x, byte),
+            SkipIfNotEqualByte { x, byte } => write!(f, "if v{:x} == 0x{:02X} then", x, byte),
+            SkipIfEqual { x, y } => write!(f, "if v{:x} != v{:x} then", x, y),
+            StoreRange { x, y } => write!(f, "save v{:x} - v{:x}", x, y),
+            LoadRange { x, y } => write!(f, "load v{:x} - v{:x}", x, y),
+            LoadByte { x, byte } => write!(f, "v{:x} := 0x{:02X}", x, byte),
+            AddByte { x, byte } => write!(f, "v{:x} += 0x{:02X}", x, byte),
+            Load { x, y } => write!(f, "v{:x} := v{:x}", x, y),
+            Or { x, y } => write!(f, "v{:x} |= v{:x}", x, y),
+            And { x, y } => write!(f, "v{:x} &= v{:x}", x, y),
+            Xor { x, y } => write!(f, "v{:x} ^= v{:x}", x, y),
+            Add { x, y } => write!(f, "v{:x} += v{:x}", x, y),
+            Sub { x, y } => write!(f, "v{:x} -= v{:x}", x, y),
+            ShiftRight { x, y } => write!(f, "v{:x} >>= v{:x}", x, y),
+            SubReverse { x, y } => write!(f, "v{:x} =- v{:x}", x, y),
+            ShiftLeft { x, y } => write!(f, "v{:x} <<= v{:x}", x, y),
+            SkipIfNotEqual { x, y } => write!(f, "if v{:x} == v{:x} then", x, y),
+            LoadI(target) => write!(f, "i := {}", address(target)),
+            JumpRelative(target) => write!(f, "jump0 {}", address(target)),
+            Random { x, byte } => write!(f, "v{:x} := random 0x{:02X}", x, byte),
+            Draw { x, y, n } => write!(f, "sprite v{:x} v{:x} {}", x, y, n),
+            SkipIfKey { x } => write!(f, "if v{:x} -key then", x),
+            SkipIfNotKey { x } => write!(f, "if v{:x} key then", x),
+            LoadLongI => write!(f, "i := long"),
+            SelectPlanes(n) => write!(f, "plane {}", n),
+            LoadAudio => write!(f, "audio"),
+            LoadDelay { x } => write!(f, "v{:x} := delay", x),
+            WaitKey { x } => write!(f, "v{:x} := key", x),
+            SetDelay { x } => write!(f, "delay := v{:x}", x),
+            SetSound { x } => write!(f, "buzzer := v{:x}", x),
+            AddI { x } => write!(f, "i += v{:x}", x),
+            Font { x } => write!(f, "i := hex v{:x}", x),
+            BigFont { x } => write!(f, "i := bighex v{:x}", x),
+            Bcd { x } => write!(f, "bcd v{:x}", x),
+            SetPitch { x } => write!(f, "pitch := v{:x}", x),
+            Store { x } => write!(f, "save v{:x}", x),
+            LoadMemory { x } => write!(f, "load v{:x}", x),
+            StoreFlags { x } => write!(f, "saveflags v{:x}", x),
+            LoadFlags { x } => write!(f, "loadflags v{:x}", x),
+            Unknown(opcode) => write!(f, "0x{:02X} 0x{:02X}", opcode >> 8, opcode & 0xFF),
+        }
+    }
+
+    fn write_cowgod<'a>(
+        self,
+        f: &mut fmt::Formatter<'_>,
+        label: &dyn Fn(u16) -> Option<&'a str>,
+    ) -> fmt::Result {
+        use Instruction::*;
+        let address = |address: u16| Address {
+            address,
+            label: label(address),
+            prefix: "#",
+        };
+        match self {
+            ScrollDown(n) => write!(f, "SCD {}", n),
+            ScrollUp(n) => write!(f, "SCU {}", n),
+            Clear | ClearHires => write!(f, "CLS"),
+            Return => write!(f, "RET"),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            Lores => write!(f, "LOW"),
+            Hires => write!(f, "HIGH"),
+            Jump(target) => write!(f, "JP {}", address(target)),
+            Call(target) => write!(f, "CALL {}", address(target)),
+            SkipIfEqualByte { x, byte } => write!(f, "SE V{:X}, #{:02X}", x, byte),
+            SkipIfNotEqualByte { x, byte } => write!(f, "SNE V{:X}, #{:02X}", x, byte),
+            SkipIfEqual { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            StoreRange { x, y } => write!(f, "LD [I], V{:X}-V{:X}", x, y),
+            LoadRange { x, y } => write!(f, "LD V{:X}-V{:X}, [I]", x, y),
+            LoadByte { x, byte } => write!(f, "LD V{:X}, #{:02X}", x, byte),
+            AddByte { x, byte } => write!(f, "ADD V{:X}, #{:02X}", x, byte),
+            Load { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Add { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            SubReverse { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SkipIfNotEqual { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LoadI(target) => write!(f, "LD I, {}", address(target)),
+            JumpRelative(target) => write!(f, "JP V0, {}", address(target)),
+            Random { x, byte } => write!(f, "RND V{:X}, #{:02X}", x, byte),
+            Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            SkipIfKey { x } => write!(f, "SKP V{:X}", x),
+            SkipIfNotKey { x } => write!(f, "SKNP V{:X}", x),
+            LoadLongI => write!(f, "LD I, LONG"),
+            SelectPlanes(n) => write!(f, "PLANE {}", n),
+            LoadAudio => write!(f, "AUDIO"),
+            LoadDelay { x } => write!(f, "LD V{:X}, DT", x),
+            WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            AddI { x } => write!(f, "ADD I, V{:X}", x),
+            Font { x } => write!(f, "LD F, V{:X}", x),
+            BigFont { x } => write!(f, "LD HF, V{:X}", x),
+            Bcd { x } => write!(f, "LD B, V{:X}", x),
+            SetPitch { x } => write!(f, "LD PITCH, V{:X}", x),
+            Store { x } => write!(f, "LD [I], V{:X}", x),
+            LoadMemory { x } => write!(f, "LD V{:X}, [I]", x),
+            StoreFlags { x } => write!(f, "LD R, V{:X}", x),
+            LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+            Unknown(opcode) => write!(f, "DW #{:04X}", opcode),
+        }
+    }
+}
+
+/// The assembly language instructions are written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    /// The language of the Octo assembler, e.g. `v0 += 0x01`.
+    Octo,
+    /// The mnemonics of Cowgod's CHIP-8 technical reference, e.g. `ADD V0, #01`.
+    Cowgod,
+}
+
+/// An instruction written in a syntax, see `Instruction::display`.
+pub struct InstructionDisplay {
+    instruction: Instruction,
+    syntax: Syntax,
+}
+
+impl fmt::Display for InstructionDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.instruction.write(f, self.syntax, &|_| None)
+    }
+}
+
+/// An address, or its label if it has one.
+struct Address<'a> {
+    address: u16,
+    label: Option<&'a str>,
+    prefix: &'static str,
+}
+
+impl fmt::Display for Address<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.label {
+            Some(label) => write!(f, "{}", label),
+            None => write!(f, "{}{:03X}", self.prefix, self.address),
+        }
+    }
+}
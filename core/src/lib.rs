@@ -0,0 +1,3190 @@
+//! A CHIP-8, SUPER-CHIP and XO-CHIP interpreter written in plain Rust, without
+//! any assumptions about the platform it runs on. Hosts feed it time and key
+//! presses, and read back the display and the audio it produces.
+//!
+//! Without the default `std` feature the crate is `#![no_std]` and never
+//! allocates, so it can drive a small display from a microcontroller. The host
+//! then provides the random numbers (`RandomSource`), the passing of time
+//! (`TimeSource`) and, with the `log` feature, a logger for the `log` facade.
+#![cfg_attr(not(feature = "std"), no_std)]
+#![allow(non_snake_case)]
+mod error;
+pub mod games;
+
+#[cfg(feature = "std")]
+pub use assembler::{assemble, Program};
+use audio::{Audio, PATTERN_SIZE};
+#[cfg(feature = "std")]
+pub use clock::SystemClock;
+use clock::{cosmac_vip_cycles, Clock};
+pub use clock::{TimeSource, TimingMode};
+#[cfg(feature = "std")]
+use debugger::Debugger;
+#[cfg(feature = "std")]
+pub use debugger::{Access, Register, Resume, StopReason};
+#[cfg(feature = "std")]
+pub use disassembler::{disassemble, Disassembly, DisassemblyDisplay, Item, Line};
+pub use display::{FlickerFilter, Resolution};
+use display::{Framebuffer, PLANES};
+use error::Fault;
+#[cfg(feature = "std")]
+pub use error::{AssembleError, MovieError, RomError};
+pub use error::{Chip8Error, HostError, StateError};
+use games::Game;
+pub use instruction::{Instruction, InstructionDisplay, Syntax};
+pub use memory::MemoryPolicy;
+use memory::{Memory, MEMORY_CAPACITY, MEMORY_SIZE};
+#[cfg(feature = "std")]
+pub use movie::{InputEvent, Movie, MOVIE_VERSION};
+pub use quirks::{QuirkProfile, Quirks};
+#[cfg(feature = "std")]
+use rand::{thread_rng, Rng};
+pub use random::{BuiltinRandom, CosmacVipRandom, RandomSource, ScriptedRandom, SeededRandom};
+#[cfg(feature = "std")]
+pub use recompiler::Engine;
+pub use state::{MAX_STATE_SIZE, STATE_VERSION};
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub use trace::FileSink;
+#[cfg(feature = "std")]
+use trace::Tracer;
+#[cfg(feature = "std")]
+pub use trace::{
+    ConsoleSink, NullSink, RingBufferSink, Trace, TraceEvent, TraceFilter, TraceKind, TraceSink,
+};
+
+// A macro to provide `println!(..)`-style syntax for logging. Messages go through
+// the `log` facade, hosts decide where they end up by installing a logger.
+#[cfg(feature = "log")]
+macro_rules! log {
+    ( $( $t:tt )* ) => {
+        log::debug!( $( $t )* )
+    }
+}
+
+// Without the `log` feature messages are type checked but never built.
+#[cfg(not(feature = "log"))]
+macro_rules! log {
+    ( $( $t:tt )* ) => {
+        if false {
+            let _ = format_args!( $( $t )* );
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+mod assembler;
+mod audio;
+mod clock;
+#[cfg(feature = "std")]
+mod debugger;
+#[cfg(feature = "std")]
+mod disassembler;
+mod display;
+mod instruction;
+mod memory;
+#[cfg(feature = "std")]
+mod movie;
+mod quirks;
+mod random;
+#[cfg(feature = "std")]
+mod recompiler;
+#[cfg(feature = "std")]
+mod rewind;
+mod state;
+#[cfg(feature = "std")]
+mod trace;
+
+const FONT_LOCATION: usize = 0x50;
+const BIG_FONT_LOCATION: usize = 0xA0;
+const STACK_DEPTH: usize = 16;
+
+// The 4x5 hex digits every interpreter has.
+const FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// The 8x10 hex digits of SUPER-CHIP and XO-CHIP.
+const BIG_FONT: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+pub struct Chip8<R = BuiltinRandom> {
+    memory: Memory,
+    framebuffer: Framebuffer,
+    // Mirror the display into memory like the COSMAC VIP, for ROMs that read it.
+    vip_display_mirror: bool,
+    registers: RegisterBank,
+    pc: usize,
+    // The stack lives outside of addressable memory, as XO-CHIP ROMs may use all of it.
+    stack: [usize; STACK_DEPTH],
+    sp: usize,
+    keypad: [bool; 16],
+    audio: Audio,
+    random: R,
+    quirks: Quirks,
+    // Set by every timer tick and consumed by Dxyn when the display_wait quirk is on.
+    vblank: bool,
+    halted: Option<Chip8Error>,
+    // Set by the SUPER-CHIP 00FD instruction.
+    exited: bool,
+    // Progress of an Fx0A waiting for a key to be pressed and released.
+    key_wait: Option<KeyWait>,
+    clock: Clock,
+    // CRC-32 of the loaded ROM, which identifies it in save states.
+    rom_hash: u32,
+    // The latest frames, kept once rewinding is turned on.
+    #[cfg(feature = "std")]
+    history: Option<rewind::History<R>>,
+    // The movie being recorded or played back.
+    #[cfg(feature = "std")]
+    movie: Option<movie::Session>,
+    #[cfg(feature = "std")]
+    debugger: Debugger,
+    // Set while the recompiler is the engine.
+    #[cfg(feature = "std")]
+    recompiler: Option<recompiler::Recompiler>,
+    #[cfg(feature = "std")]
+    tracer: Tracer,
+}
+
+/// What `advance` did.
+enum Progress {
+    Executed,
+    FrameEnded,
+    // The debugger stopped execution.
+    #[cfg(feature = "std")]
+    Stopped,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KeyWait {
+    Press,
+    Release(u8),
+}
+
+#[derive(Default, Clone)]
+struct RegisterBank {
+    Vx: [u8; 16],
+    I: usize,
+    delay: u8,
+    sound: u8,
+    // The HP48 "RPL user flags" SUPER-CHIP saves registers to with Fx75.
+    rpl: [u8; 16],
+}
+
+#[cfg(feature = "std")]
+impl Default for Chip8 {
+    fn default() -> Self {
+        Chip8::with_seed(thread_rng().gen())
+    }
+}
+
+impl Chip8 {
+    /// A machine whose random numbers come from a generator seeded with a random seed.
+    #[cfg(feature = "std")]
+    pub fn new() -> Self {
+        Chip8::default()
+    }
+
+    /// A machine whose random numbers are reproduced by using the same `seed`.
+    pub fn with_seed(seed: u32) -> Self {
+        Chip8::with_random_source(BuiltinRandom::Seeded(SeededRandom::new(seed)))
+    }
+
+    /// The seed of the random number generator, which reproduces the run when passed
+    /// to `set_random_seed`.
+    pub fn random_seed(&self) -> u32 {
+        self.random.seed()
+    }
+
+    /// Makes CXNN use a seeded generator, so runs can be reproduced.
+    pub fn set_random_seed(&mut self, seed: u32) {
+        self.random = BuiltinRandom::Seeded(SeededRandom::new(seed));
+    }
+
+    /// Makes CXNN generate numbers the way the COSMAC VIP interpreter did.
+    pub fn use_cosmac_vip_random(&mut self, seed: u16) {
+        self.random = BuiltinRandom::CosmacVip(CosmacVipRandom::new(seed));
+    }
+}
+
+impl<R: RandomSource> Chip8<R> {
+    /// A machine whose CXNN draws its numbers from `random`.
+    pub fn with_random_source(random: R) -> Self {
+        let mut chip8 = Chip8 {
+            memory: Memory::new(),
+            framebuffer: Framebuffer::new(),
+            vip_display_mirror: false,
+            registers: RegisterBank::default(),
+            pc: 0x200,
+            stack: [0; STACK_DEPTH],
+            sp: 0,
+            keypad: [false; 16],
+            audio: Audio::new(),
+            random,
+            quirks: Game::default().quirks,
+            vblank: false,
+            halted: None,
+            exited: false,
+            key_wait: None,
+            clock: Clock::new(),
+            rom_hash: 0,
+            #[cfg(feature = "std")]
+            history: None,
+            #[cfg(feature = "std")]
+            movie: None,
+            #[cfg(feature = "std")]
+            debugger: Debugger::new(),
+            #[cfg(feature = "std")]
+            recompiler: None,
+            #[cfg(feature = "std")]
+            tracer: Tracer::new(),
+        };
+        chip8.load_fonts();
+        chip8
+    }
+
+    fn load_fonts(&mut self) {
+        self.memory[FONT_LOCATION..FONT_LOCATION + FONT.len()].copy_from_slice(&FONT);
+        self.memory[BIG_FONT_LOCATION..BIG_FONT_LOCATION + BIG_FONT.len()]
+            .copy_from_slice(&BIG_FONT);
+    }
+
+    /// Puts the machine back the way it was switched on: memory holds nothing but
+    /// the fonts, the display is blank and the registers, stack and timers are
+    /// cleared, as is a fault it halted on. Settings, like the quirks, the CPU speed
+    /// and the keys held down, are kept, and so are breakpoints and watchpoints.
+    /// Forgets the rewind history and ends any movie being recorded or played back.
+    pub fn reset(&mut self) {
+        for byte in self.memory.iter_mut() {
+            *byte = 0;
+        }
+        self.load_fonts();
+        self.framebuffer.reset();
+        self.registers = RegisterBank::default();
+        self.pc = 0x200;
+        self.stack = [0; STACK_DEPTH];
+        self.sp = 0;
+        self.audio.reset();
+        self.vblank = false;
+        self.halted = None;
+        self.exited = false;
+        self.key_wait = None;
+        self.clock.set_mode(self.clock.mode);
+        self.rom_hash = 0;
+        #[cfg(feature = "std")]
+        {
+            if let Some(history) = &mut self.history {
+                history.clear();
+            }
+            self.movie = None;
+            self.debugger.forget_stop();
+        }
+    }
+
+    /// The memory ROMs can address: 4 KiB, or 64 KiB with the extended_memory quirk
+    /// and the `xo-chip` feature.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory[..self.memory.size]
+    }
+
+    pub fn memory_policy(&self) -> MemoryPolicy {
+        self.memory.policy
+    }
+
+    /// Chooses how out of range memory, stack and `I` accesses are handled.
+    pub fn set_memory_policy(&mut self, policy: MemoryPolicy) {
+        self.memory.policy = policy;
+    }
+
+    /// What to show for one of the two XO-CHIP bitplanes once the flicker filter has
+    /// been applied, packed 8 pixels to a byte, row by row. Together, the bits of both
+    /// planes pick one of four colours for each pixel. `None` for planes other than
+    /// 0 and 1.
+    pub fn display_plane(&self, plane: usize) -> Option<&[u8]> {
+        if plane < PLANES {
+            Some(self.framebuffer.shown(plane))
+        } else {
+            None
+        }
+    }
+
+    pub fn display_width(&self) -> usize {
+        self.framebuffer.width()
+    }
+
+    pub fn display_height(&self) -> usize {
+        self.framebuffer.height()
+    }
+
+    /// The filtered display of one plane with one byte per pixel, row by row, from 0
+    /// for off to 255 for fully lit. Updated on every timer tick. `None` for planes
+    /// other than 0 and 1.
+    #[cfg(feature = "flicker")]
+    pub fn intensity_plane(&self, plane: usize) -> Option<&[u8]> {
+        if plane < PLANES {
+            Some(self.framebuffer.intensity(plane))
+        } else {
+            None
+        }
+    }
+
+    pub fn flicker_filter(&self) -> FlickerFilter {
+        self.framebuffer.filter
+    }
+
+    pub fn set_flicker_filter(&mut self, filter: FlickerFilter) {
+        self.framebuffer.filter = filter;
+    }
+
+    /// `FlickerFilter::Persistence` shows pixels that were on in any of the latest
+    /// `frames` frames, 2 by default.
+    #[cfg(feature = "flicker")]
+    pub fn set_persistence_frames(&mut self, frames: u8) {
+        self.framebuffer.persistence_frames = frames;
+    }
+
+    /// The share of its brightness a pixel keeps every frame with
+    /// `FlickerFilter::PhosphorDecay`, 0.6 by default.
+    #[cfg(feature = "flicker")]
+    pub fn set_phosphor_decay(&mut self, decay: f32) {
+        self.framebuffer.decay = decay.clamp(0.0, 1.0);
+    }
+
+    pub fn resolution(&self) -> Resolution {
+        self.framebuffer.resolution()
+    }
+
+    /// Switches the display to `resolution`, clearing it. ROMs switch between low and
+    /// high resolution themselves, hosts use this for hires CHIP-8 ROMs which expect
+    /// a 64x64 display from the start.
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.framebuffer.set_resolution(resolution);
+        self.display_changed();
+    }
+
+    /// Keeps a copy of the display at 0xF00 (0xE00 at 64x64), where the COSMAC VIP
+    /// kept it, for ROMs that read screen memory directly.
+    pub fn set_vip_display_mirror(&mut self, enabled: bool) {
+        self.vip_display_mirror = enabled;
+        self.display_changed();
+    }
+
+    pub fn press_key(&mut self, key: usize) -> Result<(), HostError> {
+        self.set_key(key, true)
+    }
+
+    pub fn release_key(&mut self, key: usize) -> Result<(), HostError> {
+        self.set_key(key, false)
+    }
+
+    /// Whether `key` is held down, `false` for keys outside of the keypad.
+    pub fn is_key_pressed(&self, key: usize) -> bool {
+        self.keypad.get(key).copied().unwrap_or(false)
+    }
+
+    fn set_key(&mut self, key: usize, pressed: bool) -> Result<(), HostError> {
+        if key >= self.keypad.len() {
+            return Err(HostError::InvalidKey(key));
+        }
+        #[cfg(feature = "std")]
+        if !self.movie_key(key, pressed) {
+            return Ok(());
+        }
+        self.keypad[key] = pressed;
+        Ok(())
+    }
+
+    /// Loads one of the bundled games. Unless a quirk `profile` is given, the game runs
+    /// with the quirks it is known to need.
+    pub fn load_game(
+        &mut self,
+        title: &str,
+        profile: Option<QuirkProfile>,
+    ) -> Result<(), HostError> {
+        let game = Game::new(title)?;
+        self.load_rom(game.code, profile.map_or(game.quirks, Quirks::from))
+    }
+
+    /// Loads a program assembled from Octo source, see `load_rom`.
+    #[cfg(feature = "std")]
+    pub fn load_program(&mut self, program: &Program, quirks: Quirks) -> Result<(), HostError> {
+        self.load_rom(program.code(), quirks)
+    }
+
+    /// Loads the ROM file at `rom`, or the bundled game of that title if there is no
+    /// such file, see `games::read_rom_or_game`.
+    #[cfg(feature = "std")]
+    pub fn load_rom_or_game(
+        &mut self,
+        rom: &str,
+        profile: Option<QuirkProfile>,
+    ) -> Result<(), RomError> {
+        let (code, quirks) = games::read_rom_or_game(rom, profile)?;
+        Ok(self.load_rom(&code, quirks)?)
+    }
+
+    /// Resets the machine, see `reset`, then loads a ROM at 0x200, where programs
+    /// start, and switches to the `quirks` it needs. A ROM that doesn't fit leaves
+    /// the machine as it was.
+    pub fn load_rom(&mut self, code: &[u8], quirks: Quirks) -> Result<(), HostError> {
+        let capacity = memory_size(quirks) - 0x200;
+        if code.len() > capacity {
+            return Err(HostError::RomTooLarge {
+                size: code.len(),
+                capacity,
+            });
+        }
+        self.reset();
+        self.set_quirks(quirks);
+        self.memory[0x200..0x200 + code.len()].copy_from_slice(code);
+        self.rom_hash = state::crc32(code);
+        Ok(())
+    }
+
+    /// The CRC-32 of the loaded ROM, 0 before one is loaded.
+    pub fn rom_hash(&self) -> u32 {
+        self.rom_hash
+    }
+
+    /// Executes a single instruction, unless the debugger stops before it, like
+    /// `run_frame` does: nothing runs at a breakpoint or while execution is stopped.
+    ///
+    /// If the instruction faults the machine halts: the error is returned, and every
+    /// following call returns it again without executing anything.
+    pub fn tick(&mut self) -> Result<(), Chip8Error> {
+        if let Some(error) = self.halted {
+            return Err(error);
+        }
+        #[cfg(feature = "std")]
+        if self.debug_before_instruction() {
+            return Ok(());
+        }
+        #[cfg(feature = "std")]
+        let (pc, watched) = (self.pc, self.watched_registers());
+        self.step()?;
+        #[cfg(feature = "std")]
+        self.debug_after_instruction(pc, watched);
+        Ok(())
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted.is_some()
+    }
+
+    /// Whether the ROM is blocked on Fx0A, waiting for a key to be pressed and released.
+    /// Hosts can use this to prompt the player for input.
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.key_wait.is_some()
+    }
+
+    /// Whether the ROM ended itself with the SUPER-CHIP exit instruction.
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.memory.set_size(memory_size(quirks));
+    }
+
+    /// Sample rate of the buffers filled by `render_audio`, defaults to 44100.
+    pub fn set_audio_sample_rate(&mut self, sample_rate: f32) {
+        self.audio.sample_rate = sample_rate;
+    }
+
+    /// Whether the sound timer is running, i.e. the ROM is making a sound.
+    pub fn sound_active(&self) -> bool {
+        self.registers.sound > 0
+    }
+
+    /// Fills `out` with the next PCM samples. Hosts call this once per frame with
+    /// enough room for a frame's worth of samples at their sample rate.
+    pub fn render_audio(&mut self, out: &mut [f32]) {
+        self.audio.render(self.sound_active(), out);
+    }
+
+    /// Replaces the source CXNN draws random numbers from.
+    pub fn set_random_source(&mut self, source: R) {
+        self.random = source;
+    }
+
+    /// How many instructions `run_frame` and `run_for` execute per second, 600 by default.
+    pub fn cpu_speed(&self) -> u32 {
+        self.clock.instructions_per_second
+    }
+
+    pub fn set_cpu_speed(&mut self, instructions_per_second: u32) {
+        self.clock.instructions_per_second = instructions_per_second;
+    }
+
+    pub fn timing_mode(&self) -> TimingMode {
+        self.clock.mode
+    }
+
+    /// Switches between a fixed CPU speed and COSMAC VIP cycle timing, which
+    /// ignores the CPU speed.
+    pub fn set_timing_mode(&mut self, mode: TimingMode) {
+        self.clock.set_mode(mode);
+    }
+
+    /// Runs one 60th of a second: a frame's worth of instructions followed by a
+    /// timer tick.
+    ///
+    /// If the debugger stops execution the frame is left unfinished, and carries on
+    /// where it stopped once execution resumes.
+    pub fn run_frame(&mut self) -> Result<(), Chip8Error> {
+        loop {
+            #[cfg(feature = "std")]
+            self.run_compiled()?;
+            match self.advance()? {
+                Progress::Executed => {}
+                Progress::FrameEnded => return Ok(()),
+                #[cfg(feature = "std")]
+                Progress::Stopped => return Ok(()),
+            }
+        }
+    }
+
+    /// Advances the machine by `elapsed_ms` of wall clock time and returns how many
+    /// frames were completed. Time that doesn't add up to a whole frame is kept for
+    /// the next call, so hosts can call this whenever they like, e.g. on every
+    /// `requestAnimationFrame`, and the timers still run at 60 Hz.
+    pub fn run_for(&mut self, elapsed_ms: f64) -> Result<u32, Chip8Error> {
+        let frames = self.clock.frames_due(elapsed_ms);
+        for frame in 0..frames {
+            self.run_frame()?;
+            if self.is_stopped() {
+                return Ok(frame);
+            }
+        }
+        Ok(frames)
+    }
+
+    // Whether the debugger stopped execution, which it can't without `std`.
+    fn is_stopped(&self) -> bool {
+        #[cfg(feature = "std")]
+        return self.stop_reason().is_some();
+        #[cfg(not(feature = "std"))]
+        false
+    }
+
+    /// Like `run_for`, with the elapsed time read from `time`.
+    pub fn run(&mut self, time: &mut impl TimeSource) -> Result<u32, Chip8Error> {
+        self.run_for(time.elapsed_ms())
+    }
+
+    pub fn decrement_timers(&mut self) {
+        #[cfg(feature = "std")]
+        self.movie_frame();
+        self.vblank = true;
+        self.framebuffer.end_frame();
+        self.random.frame();
+        self.registers.delay = self.registers.delay.saturating_sub(1);
+        self.registers.sound = self.registers.sound.saturating_sub(1);
+    }
+
+    /// Executes a single instruction and returns it, or `None` if the ROM has exited.
+    fn step(&mut self) -> Result<Option<u16>, Chip8Error> {
+        if let Some(error) = self.halted {
+            return Err(error);
+        }
+        if self.exited {
+            return Ok(None);
+        }
+        #[cfg(feature = "std")]
+        self.movie_instruction();
+        let pc = self.pc;
+        let result = match self.fetch() {
+            Ok((machine_code, instruction)) => {
+                #[cfg(feature = "std")]
+                let before = if self.is_tracing() {
+                    self.trace_fetch(self.pc - 2, machine_code, instruction)
+                } else {
+                    None
+                };
+                let result = self.execute(instruction);
+                #[cfg(feature = "std")]
+                if self.is_tracing() {
+                    self.trace_writes(before);
+                }
+                result
+                    .map(|()| machine_code)
+                    .map_err(|fault| fault.at(pc, machine_code))
+            }
+            Err(fault) => Err(fault.at(pc, 0)),
+        };
+        if let Err(error) = result {
+            log!("Halting: {}", error);
+            self.halted = Some(error);
+        }
+        result.map(Some)
+    }
+
+    /// Executes the next instruction of the current frame, or ends the frame with a
+    /// timer tick once it has used up its time. A frame lasts as many instructions
+    /// as the CPU speed allows, or in CosmacVip mode as many as the COSMAC VIP could
+    /// run between two display interrupts.
+    fn advance(&mut self) -> Result<Progress, Chip8Error> {
+        if let Some(error) = self.halted {
+            return Err(error);
+        }
+        if self.clock.frame_has_time_left() && !self.exited {
+            #[cfg(feature = "std")]
+            if self.debug_before_instruction() {
+                return Ok(Progress::Stopped);
+            }
+            #[cfg(feature = "std")]
+            let watched = self.watched_registers();
+            // The COSMAC VIP's timing depends on the registers before the instruction
+            // changes them, e.g. the X coordinate of a DFyN that sets VF.
+            let (pc, before) = (self.pc, self.registers.Vx);
+            if let Some(opcode) = self.step()? {
+                let cycles = match self.clock.mode {
+                    TimingMode::Fixed => 0,
+                    // Instructions that don't move on, like a Dxyn waiting for the
+                    // display interrupt or an Fx0A waiting for a key, are stuck
+                    // until the next frame.
+                    TimingMode::CosmacVip if self.pc == pc => {
+                        self.clock.wait_for_interrupt();
+                        0
+                    }
+                    TimingMode::CosmacVip => {
+                        let vx = before[(opcode >> 8 & 0xF) as usize];
+                        let skipped = self.pc.wrapping_sub(pc) > 2;
+                        cosmac_vip_cycles(opcode, vx, skipped)
+                    }
+                };
+                self.clock.executed(cycles);
+            }
+            #[cfg(feature = "std")]
+            if self.debug_after_instruction(pc, watched) {
+                return Ok(Progress::Stopped);
+            }
+            return Ok(Progress::Executed);
+        }
+        self.clock.end_frame();
+        self.decrement_timers();
+        #[cfg(feature = "std")]
+        if let Some(mut history) = self.history.take() {
+            history.record(self);
+            self.history = Some(history);
+        }
+        Ok(Progress::FrameEnded)
+    }
+
+    fn fetch(&mut self) -> Result<(u16, Instruction), Fault> {
+        self.pc = self.memory.address(self.pc)?;
+        let (machine_code, instruction) = self.memory.fetch(self.pc)?;
+        self.pc += 2;
+        Ok((machine_code, instruction))
+    }
+
+    fn execute(&mut self, instruction: Instruction) -> Result<(), Fault> {
+        use Instruction::*;
+        let v = |register: u8| self.registers.Vx[register as usize];
+        match instruction {
+            ScrollDown(n) => self.scroll_down(n as usize),
+            ScrollUp(n) => self.scroll_up(n as usize),
+            Clear | ClearHires => self.clear_display(),
+            Return => self.return_from_subroutine()?,
+            ScrollRight => self.scroll_right(),
+            ScrollLeft => self.scroll_left(),
+            Exit => self.exit(),
+            Lores => self.set_resolution(Resolution::Low),
+            Hires => self.set_resolution(Resolution::High),
+            Jump(address) => self.jump(address as usize),
+            Call(address) => self.call_subroutine(address as usize)?,
+            SkipIfEqualByte { x, byte } => self.skip_next_if_equal_to_byte(v(x), byte),
+            SkipIfNotEqualByte { x, byte } => self.skip_next_if_not_equal_to_byte(v(x), byte),
+            SkipIfEqual { x, y } => self.skip_next_if_equal_to_register(v(x), v(y)),
+            StoreRange { x, y } => self.store_range(x as usize, y as usize)?,
+            LoadRange { x, y } => self.load_range(x as usize, y as usize)?,
+            LoadByte { x, byte } => self.load_from_byte(x as usize, byte),
+            AddByte { x, byte } => self.add_byte(x as usize, byte),
+            Load { x, y } => self.load_from_register(x as usize, v(y)),
+            Or { x, y } => self.or(x as usize, v(y)),
+            And { x, y } => self.and(x as usize, v(y)),
+            Xor { x, y } => self.xor(x as usize, v(y)),
+            Add { x, y } => self.add_registers(x as usize, v(y)),
+            Sub { x, y } => self.sub_vy_from_vx(x as usize, v(y)),
+            // A logical right shift, not an arithmetic one.
+            ShiftRight { x, y } => self.shift_right(x as usize, y as usize),
+            SubReverse { x, y } => self.sub_vx_from_vy(x as usize, v(y)),
+            ShiftLeft { x, y } => self.shift_left(x as usize, y as usize),
+            SkipIfNotEqual { x, y } => self.skip_next_if_not_equal_to_register(v(x), v(y)),
+            LoadI(address) => self.set_I(address as usize),
+            // The register is only used with the jump quirk, as BXNN.
+            JumpRelative(address) => self.jump_relative((address >> 8) as usize, address as usize),
+            Random { x, byte } => self.set_random_number(x as usize, byte),
+            Draw { x, y, n } => self.draw(v(x), v(y), n)?,
+            SkipIfKey { x } => self.skip_if_key_is_pressed(v(x))?,
+            SkipIfNotKey { x } => self.skip_if_key_is_not_pressed(v(x))?,
+            LoadLongI => self.set_long_I()?,
+            SelectPlanes(planes) => self.select_planes(planes),
+            LoadAudio => self.load_audio_pattern()?,
+            LoadDelay { x } => self.load_from_delay_timer(x as usize),
+            WaitKey { x } => self.wait_for_key(x as usize),
+            SetDelay { x } => self.set_delay_timer(v(x)),
+            SetSound { x } => self.set_sound_timer(v(x)),
+            AddI { x } => self.increment_i(v(x))?,
+            Font { x } => self.load_font_location_in_I(v(x)),
+            BigFont { x } => self.load_big_font_location_in_I(v(x)),
+            Bcd { x } => self.store_bcd(v(x))?,
+            SetPitch { x } => self.set_pitch(v(x)),
+            Store { x } => self.bulk_store(x as usize)?,
+            LoadMemory { x } => self.bulk_load(x as usize)?,
+            StoreFlags { x } => self.store_rpl_flags(x as usize),
+            LoadFlags { x } => self.load_rpl_flags(x as usize),
+            Unknown(_) => return Err(Fault::UnknownOpcode),
+        }
+        Ok(())
+    }
+
+    fn clear_display(&mut self) {
+        for plane in self.framebuffer.selected_planes() {
+            self.framebuffer.clear(plane);
+        }
+        self.framebuffer.present();
+        self.display_changed();
+    }
+
+    fn scroll_down(&mut self, n: usize) {
+        let (width, height) = (self.display_width(), self.display_height());
+        for plane in self.framebuffer.selected_planes() {
+            for y in (0..height).rev() {
+                for x in 0..width {
+                    let pixel = y >= n && self.framebuffer.pixel(plane, x, y - n);
+                    self.framebuffer.set_pixel(plane, x, y, pixel);
+                }
+            }
+        }
+        self.framebuffer.present();
+        self.display_changed();
+    }
+
+    fn scroll_up(&mut self, n: usize) {
+        let (width, height) = (self.display_width(), self.display_height());
+        for plane in self.framebuffer.selected_planes() {
+            for y in 0..height {
+                for x in 0..width {
+                    let pixel = y + n < height && self.framebuffer.pixel(plane, x, y + n);
+                    self.framebuffer.set_pixel(plane, x, y, pixel);
+                }
+            }
+        }
+        self.framebuffer.present();
+        self.display_changed();
+    }
+
+    fn scroll_right(&mut self) {
+        let (width, height) = (self.display_width(), self.display_height());
+        for plane in self.framebuffer.selected_planes() {
+            for y in 0..height {
+                for x in (0..width).rev() {
+                    let pixel = x >= 4 && self.framebuffer.pixel(plane, x - 4, y);
+                    self.framebuffer.set_pixel(plane, x, y, pixel);
+                }
+            }
+        }
+        self.framebuffer.present();
+        self.display_changed();
+    }
+
+    fn scroll_left(&mut self) {
+        let (width, height) = (self.display_width(), self.display_height());
+        for plane in self.framebuffer.selected_planes() {
+            for y in 0..height {
+                for x in 0..width {
+                    let pixel = x + 4 < width && self.framebuffer.pixel(plane, x + 4, y);
+                    self.framebuffer.set_pixel(plane, x, y, pixel);
+                }
+            }
+        }
+        self.framebuffer.present();
+        self.display_changed();
+    }
+
+    fn exit(&mut self) {
+        self.exited = true;
+    }
+
+    fn select_planes(&mut self, planes: u8) {
+        self.framebuffer.select_planes(planes);
+    }
+
+    /// Updates the copy of the display in memory, if there is one.
+    fn display_changed(&mut self) {
+        if self.vip_display_mirror {
+            self.framebuffer.mirror_into(&mut self.memory);
+        }
+    }
+
+    fn return_from_subroutine(&mut self) -> Result<(), Fault> {
+        // The stack is a ring of STACK_DEPTH slots when the memory policy allows wrapping.
+        if self.sp == 0 {
+            self.memory.violation(Fault::StackUnderflow)?;
+            self.sp = STACK_DEPTH;
+        }
+        self.sp -= 1;
+        self.pc = self.stack[self.sp];
+        Ok(())
+    }
+
+    fn jump(&mut self, address: usize) {
+        self.pc = address;
+    }
+
+    fn call_subroutine(&mut self, address: usize) -> Result<(), Fault> {
+        if self.sp == STACK_DEPTH {
+            self.memory.violation(Fault::StackOverflow)?;
+            self.sp = 0;
+        }
+        self.stack[self.sp] = self.pc;
+        self.sp += 1;
+        self.pc = address;
+        Ok(())
+    }
+
+    /// Skips the next instruction, which takes 4 bytes if it is the XO-CHIP long I load.
+    fn skip_next(&mut self) {
+        let next = (
+            self.memory.read_code(self.pc),
+            self.memory.read_code(self.pc + 1),
+        );
+        self.pc += if next == (Ok(0xF0), Ok(0x00)) { 4 } else { 2 };
+    }
+
+    fn skip_next_if_equal_to_byte(&mut self, vx: u8, byte: u8) {
+        if vx == byte {
+            self.skip_next();
+        }
+    }
+
+    fn skip_next_if_not_equal_to_byte(&mut self, vx: u8, byte: u8) {
+        if vx != byte {
+            self.skip_next();
+        }
+    }
+
+    fn skip_next_if_equal_to_register(&mut self, vx: u8, vy: u8) {
+        if vx == vy {
+            self.skip_next();
+        }
+    }
+
+    /// Stores Vx to Vy in memory starting at I, in reverse order if x > y. I is left untouched.
+    fn store_range(&mut self, x: usize, y: usize) -> Result<(), Fault> {
+        for (offset, register) in register_range(x, y).enumerate() {
+            self.memory
+                .write(self.registers.I + offset, self.registers.Vx[register])?;
+        }
+        Ok(())
+    }
+
+    /// Loads Vx to Vy from memory starting at I, in reverse order if x > y. I is left untouched.
+    fn load_range(&mut self, x: usize, y: usize) -> Result<(), Fault> {
+        for (offset, register) in register_range(x, y).enumerate() {
+            self.registers.Vx[register] = self.memory.read(self.registers.I + offset)?;
+        }
+        Ok(())
+    }
+
+    fn load_from_byte(&mut self, x: usize, byte: u8) {
+        self.registers.Vx[x] = byte;
+    }
+
+    fn add_byte(&mut self, x: usize, byte: u8) {
+        self.registers.Vx[x] = self.registers.Vx[x].wrapping_add(byte);
+    }
+
+    fn load_from_register(&mut self, x: usize, vy: u8) {
+        self.registers.Vx[x] = vy;
+    }
+
+    fn or(&mut self, x: usize, vy: u8) {
+        self.registers.Vx[x] |= vy;
+        if self.quirks.vf_reset {
+            self.registers.Vx[0xF] = 0;
+        }
+    }
+
+    fn and(&mut self, x: usize, vy: u8) {
+        self.registers.Vx[x] &= vy;
+        if self.quirks.vf_reset {
+            self.registers.Vx[0xF] = 0;
+        }
+    }
+
+    fn xor(&mut self, x: usize, vy: u8) {
+        self.registers.Vx[x] ^= vy;
+        if self.quirks.vf_reset {
+            self.registers.Vx[0xF] = 0;
+        }
+    }
+
+    fn add_registers(&mut self, x: usize, vy: u8) {
+        let (result, overflow) = self.registers.Vx[x].overflowing_add(vy);
+        self.registers.Vx[0xF] = if overflow { 1 } else { 0 };
+        self.registers.Vx[x] = result;
+    }
+
+    fn sub_vy_from_vx(&mut self, x: usize, vy: u8) {
+        let (result, borrow) = self.registers.Vx[x].overflowing_sub(vy);
+        // VF is set when there is *no* borrow.
+        self.registers.Vx[0xF] = if borrow { 0 } else { 1 };
+        self.registers.Vx[x] = result;
+    }
+
+    fn shift_right(&mut self, x: usize, y: usize) {
+        if self.quirks.shift {
+            self.registers.Vx[0xF] = self.registers.Vx[x] & 1;
+            self.registers.Vx[x] >>= 1;
+        } else {
+            self.registers.Vx[0xF] = self.registers.Vx[y] & 1;
+            self.registers.Vx[x] = self.registers.Vx[y] >> 1;
+        }
+    }
+
+    fn sub_vx_from_vy(&mut self, x: usize, vy: u8) {
+        let (result, borrow) = vy.overflowing_sub(self.registers.Vx[x]);
+        self.registers.Vx[0xF] = if borrow { 0 } else { 1 };
+        self.registers.Vx[x] = result;
+    }
+
+    fn shift_left(&mut self, x: usize, y: usize) {
+        if self.quirks.shift {
+            self.registers.Vx[0xF] = (self.registers.Vx[x] & 0b10000000) >> 7;
+            self.registers.Vx[x] <<= 1;
+        } else {
+            self.registers.Vx[0xF] = (self.registers.Vx[y] & 0b10000000) >> 7;
+            self.registers.Vx[x] = self.registers.Vx[y] << 1;
+        }
+    }
+
+    fn skip_next_if_not_equal_to_register(&mut self, vx: u8, vy: u8) {
+        if vx != vy {
+            self.skip_next();
+        }
+    }
+
+    fn set_I(&mut self, address: usize) {
+        self.registers.I = address;
+    }
+
+    /// XO-CHIP F000 NNNN: loads I with the 16 bit word following the instruction.
+    fn set_long_I(&mut self) -> Result<(), Fault> {
+        self.registers.I = (self.memory.read_code(self.pc)? as usize) << 8
+            | self.memory.read_code(self.pc + 1)? as usize;
+        self.pc += 2;
+        Ok(())
+    }
+
+    fn jump_relative(&mut self, x: usize, address: usize) {
+        let offset = if self.quirks.jump {
+            self.registers.Vx[x]
+        } else {
+            self.registers.Vx[0]
+        };
+        self.pc = address + offset as usize;
+    }
+
+    fn set_random_number(&mut self, x: usize, byte: u8) {
+        let random_num = self.random.next_byte(&self.memory[..self.memory.size]);
+        self.registers.Vx[x] = random_num & byte;
+    }
+
+    fn draw(&mut self, vx: u8, vy: u8, n: u8) -> Result<(), Fault> {
+        if self.quirks.display_wait {
+            if !self.vblank {
+                self.pc -= 2;
+                return Ok(());
+            }
+            self.vblank = false;
+        }
+        // Dxy0 draws a 16x16 SUPER-CHIP sprite made of two bytes per row.
+        let (sprite_width, rows) = if n == 0 { (16, 16) } else { (8, n as usize) };
+        let sprite_size = rows * sprite_width / 8;
+        // Every selected plane gets its own sprite, stored one after the other starting at I.
+        let mut collisions = 0;
+        let mut address = self.registers.I;
+        for plane in self.framebuffer.selected_planes() {
+            let mut sprite = [0; 32];
+            for (offset, byte) in sprite.iter_mut().enumerate().take(sprite_size) {
+                *byte = self.memory.read(address + offset)?;
+            }
+            address += sprite_size;
+            let (collided, clipped) = self.framebuffer.draw_sprite(
+                plane,
+                vx,
+                vy,
+                &sprite[..sprite_size],
+                sprite_width,
+                self.quirks.clip,
+            );
+            // In high resolution mode VF counts the rows that collided or were
+            // clipped off the bottom of the screen, like SUPER-CHIP 1.1 did.
+            let plane_collisions = if self.framebuffer.resolution() == Resolution::High {
+                collided + clipped
+            } else {
+                collided.min(1)
+            };
+            collisions = collisions.max(plane_collisions);
+        }
+        self.registers.Vx[0xF] = collisions as u8;
+        #[cfg(feature = "std")]
+        self.trace(TraceEvent::Draw {
+            x: vx,
+            y: vy,
+            rows: n,
+            collision: collisions > 0,
+        });
+        self.display_changed();
+        Ok(())
+    }
+
+    fn skip_if_key_is_pressed(&mut self, vx: u8) -> Result<(), Fault> {
+        if self.key_is_pressed(vx)? {
+            self.skip_next();
+        }
+        Ok(())
+    }
+
+    fn skip_if_key_is_not_pressed(&mut self, vx: u8) -> Result<(), Fault> {
+        if !self.key_is_pressed(vx)? {
+            self.skip_next();
+        }
+        Ok(())
+    }
+
+    fn key_is_pressed(&self, key: u8) -> Result<bool, Fault> {
+        self.keypad
+            .get(key as usize)
+            .copied()
+            .ok_or(Fault::InvalidKey(key))
+    }
+
+    fn load_from_delay_timer(&mut self, x: usize) {
+        self.registers.Vx[x] = self.registers.delay;
+    }
+
+    /// Blocks until any key is pressed and then released, like the COSMAC VIP did,
+    /// and stores that key in Vx. Timers keep running while blocked.
+    fn wait_for_key(&mut self, x: usize) {
+        #[cfg(feature = "std")]
+        if self.key_wait.is_none() {
+            self.trace(TraceEvent::KeyWait { x: x as u8 });
+        }
+        match self.key_wait {
+            Some(KeyWait::Release(key)) if !self.keypad[key as usize] => {
+                self.registers.Vx[x] = key;
+                self.key_wait = None;
+                return;
+            }
+            Some(KeyWait::Release(_)) => {}
+            None | Some(KeyWait::Press) => {
+                let pressed = self.keypad.iter().position(|&pressed| pressed);
+                self.key_wait = Some(match pressed {
+                    Some(key) => KeyWait::Release(key as u8),
+                    None => KeyWait::Press,
+                });
+            }
+        }
+        self.pc -= 2;
+    }
+
+    fn set_delay_timer(&mut self, vx: u8) {
+        self.registers.delay = vx;
+        #[cfg(feature = "std")]
+        self.trace(TraceEvent::TimerSet {
+            timer: Register::Delay,
+            value: vx,
+        });
+    }
+
+    fn set_sound_timer(&mut self, vx: u8) {
+        self.registers.sound = vx;
+        #[cfg(feature = "std")]
+        self.trace(TraceEvent::TimerSet {
+            timer: Register::Sound,
+            value: vx,
+        });
+    }
+
+    fn load_audio_pattern(&mut self) -> Result<(), Fault> {
+        let mut pattern = [0; PATTERN_SIZE];
+        for (offset, byte) in pattern.iter_mut().enumerate() {
+            *byte = self.memory.read(self.registers.I + offset)?;
+        }
+        self.audio.load_pattern(pattern);
+        Ok(())
+    }
+
+    fn set_pitch(&mut self, vx: u8) {
+        self.audio.set_pitch(vx);
+    }
+
+    fn increment_i(&mut self, vx: u8) -> Result<(), Fault> {
+        let I = self.registers.I + vx as usize;
+        if self.quirks.index_overflow {
+            // ROMs relying on this expect I to overflow, so it isn't treated as a violation.
+            self.registers.Vx[0xF] = if I > 0xFFF { 1 } else { 0 };
+            self.registers.I = I & 0xFFF;
+        } else {
+            self.registers.I = self.memory.address(I)?;
+        }
+        Ok(())
+    }
+
+    fn load_font_location_in_I(&mut self, vx: u8) {
+        self.registers.I = FONT_LOCATION + (vx as usize) * 5;
+    }
+
+    fn load_big_font_location_in_I(&mut self, vx: u8) {
+        self.registers.I = BIG_FONT_LOCATION + (vx as usize & 0xF) * 10;
+    }
+
+    fn store_bcd(&mut self, vx: u8) -> Result<(), Fault> {
+        let hundreds = vx / 100;
+        let tens = (vx % 100) / 10;
+        let ones = vx % 10;
+        self.memory.write(self.registers.I, hundreds)?;
+        self.memory.write(self.registers.I + 1, tens)?;
+        self.memory.write(self.registers.I + 2, ones)
+    }
+
+    fn bulk_store(&mut self, x: usize) -> Result<(), Fault> {
+        for i in 0..=x {
+            self.memory
+                .write(self.registers.I + i, self.registers.Vx[i])?;
+        }
+        if !self.quirks.load_store {
+            self.registers.I += x + 1;
+        }
+        Ok(())
+    }
+
+    fn bulk_load(&mut self, x: usize) -> Result<(), Fault> {
+        for i in 0..=x {
+            self.registers.Vx[i] = self.memory.read(self.registers.I + i)?;
+        }
+        if !self.quirks.load_store {
+            self.registers.I += x + 1;
+        }
+        Ok(())
+    }
+
+    fn store_rpl_flags(&mut self, x: usize) {
+        self.registers.rpl[0..x + 1].copy_from_slice(&self.registers.Vx[0..x + 1]);
+    }
+
+    fn load_rpl_flags(&mut self, x: usize) {
+        self.registers.Vx[0..x + 1].copy_from_slice(&self.registers.rpl[0..x + 1]);
+    }
+}
+
+/// The memory ROMs can address with `quirks`. Without the `xo-chip` feature, the
+/// extended_memory quirk has no room to extend memory into.
+fn memory_size(quirks: Quirks) -> usize {
+    if quirks.extended_memory {
+        MEMORY_CAPACITY
+    } else {
+        MEMORY_SIZE
+    }
+}
+
+/// The registers from x to y inclusive, counting down if x > y.
+fn register_range(x: usize, y: usize) -> impl Iterator<Item = usize> {
+    let count = x.abs_diff(y);
+    (0..=count).map(move |i| if x <= y { x + i } else { x - i })
+}
+
+#[cfg(test)]
+mod idk {
+    use super::games::*;
+    use super::memory::MEMORY_SIZE;
+    use super::*;
+
+    // Without `std` there is no generator to seed machines randomly.
+    #[cfg(not(feature = "std"))]
+    impl Chip8 {
+        fn new() -> Self {
+            Chip8::with_seed(1)
+        }
+    }
+
+    #[test]
+    fn loads_games() {
+        let mut chip8 = Chip8::new();
+        chip8.load_game("TETRIS", None).unwrap();
+        assert_eq!(&chip8.memory()[0x200..(0x200 + TETRIS.len())], TETRIS);
+
+        chip8.load_game("brix", None).unwrap();
+        assert_eq!(&chip8.memory()[0x200..(0x200 + BRIX.len())], BRIX);
+
+        assert_eq!(chip8.load_game("zelda", None), Err(HostError::UnknownGame));
+        assert_eq!(
+            chip8.load_rom(&[0; MEMORY_SIZE], Quirks::chip48()),
+            Err(HostError::RomTooLarge {
+                size: MEMORY_SIZE,
+                capacity: MEMORY_SIZE - 0x200
+            })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn loads_rom_files_and_games_by_name() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_or_game("pong", None).unwrap();
+        assert_eq!(&chip8.memory()[0x200..(0x200 + PONG.len())], PONG);
+        assert!(matches!(
+            chip8.load_rom_or_game("zelda", None),
+            Err(RomError::NotFound)
+        ));
+
+        let path = std::env::temp_dir().join(format!("chip8-{}.8o", std::process::id()));
+        std::fs::write(&path, ": main\n  v0 := 5\n").unwrap();
+        let rom = path.to_str().unwrap();
+        let profile = "schip-modern".parse().unwrap();
+        chip8.load_rom_or_game(rom, Some(profile)).unwrap();
+        assert_eq!(chip8.memory()[0x200..0x202], [0x60, 0x05]);
+        assert_eq!(chip8.quirks(), Quirks::super_chip_modern());
+        std::fs::write(&path, ": main\n  v0 := nowhere\n").unwrap();
+        assert!(matches!(
+            chip8.load_rom_or_game(rom, None),
+            Err(RomError::Assemble(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            "schip".parse::<QuirkProfile>(),
+            Err(HostError::UnknownQuirkProfile)
+        );
+    }
+
+    #[test]
+    fn add_register_overflows_and_sets_flag() {
+        let mut chip8 = Chip8::new();
+        chip8.load_from_byte(0, 0xF0);
+        chip8.add_registers(0, 0x0F);
+        assert_eq!(chip8.registers.Vx[0], 0xFF);
+        assert_eq!(chip8.registers.Vx[0xF], 0);
+
+        chip8.add_registers(0, 0x0F);
+
+        assert_eq!(chip8.registers.Vx[0], 0x0E);
+        assert_eq!(chip8.registers.Vx[0xF], 1);
+    }
+
+    #[test]
+    fn draws_correctly() {
+        let mut chip8 = Chip8::new();
+        chip8.load_game("bctest", None).unwrap();
+        chip8.set_I(000);
+        let e: [u8; 5] = [0xF0, 0x80, 0xF0, 0x80, 0xF0]; // E
+        chip8.memory[0..5].copy_from_slice(&e);
+        chip8.draw(0, 0, 5).unwrap();
+        for (i, row) in e.iter().enumerate() {
+            assert_eq!(chip8.framebuffer.current(0)[8 * i], *row);
+        }
+        assert_eq!(chip8.registers.Vx[0xF], 0);
+
+        chip8.draw(0, 0, 5).unwrap();
+        for i in 0..5 {
+            assert_eq!(chip8.framebuffer.current(0)[8 * i], 0);
+        }
+        assert_eq!(chip8.registers.Vx[0xF], 1);
+    }
+    #[test]
+    fn return_reverts_call() {
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x200] = 0x24;
+        chip8.memory[0x201] = 0x00;
+        chip8.tick().unwrap();
+        chip8.memory[0x400] = 0x00;
+        chip8.memory[0x401] = 0xEE;
+        chip8.tick().unwrap();
+        assert_eq!(chip8.pc, 0x202);
+        assert_eq!(chip8.sp, 0);
+    }
+
+    #[test]
+    fn check_sub_vy_from_vx() {
+        let mut chip8 = Chip8::new();
+        chip8.registers.Vx[0] = 2;
+        chip8.registers.Vx[1] = 1;
+        chip8.memory[0x200] = 0x80;
+        chip8.memory[0x201] = 0x15;
+        chip8.tick().unwrap();
+        assert_eq!(chip8.registers.Vx[0], 1);
+        assert_eq!(chip8.registers.Vx[0xF], 1);
+        chip8.pc = 0x200;
+        chip8.tick().unwrap();
+        assert_eq!(chip8.registers.Vx[0], 0);
+        assert_eq!(chip8.registers.Vx[0xF], 1);
+        chip8.pc = 0x200;
+        chip8.tick().unwrap();
+        assert_eq!(chip8.registers.Vx[0], 0xFF);
+        assert_eq!(chip8.registers.Vx[0xF], 0);
+    }
+
+    #[test]
+    fn check_sub_vx_from_vy() {
+        let mut chip8 = Chip8::new();
+        chip8.registers.Vx[0] = 1;
+        chip8.registers.Vx[1] = 2;
+        chip8.memory[0x200] = 0x80;
+        chip8.memory[0x201] = 0x17;
+        chip8.tick().unwrap();
+        assert_eq!(chip8.registers.Vx[0], 1);
+        assert_eq!(chip8.registers.Vx[0xF], 1);
+        chip8.registers.Vx[0] = 3;
+        chip8.pc = 0x200;
+        chip8.tick().unwrap();
+        assert_eq!(chip8.registers.Vx[0], 0xFF);
+        assert_eq!(chip8.registers.Vx[0xF], 0);
+    }
+
+    #[test]
+    fn unknown_opcode_halts_the_machine() {
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x200] = 0xFF;
+        chip8.memory[0x201] = 0xFF;
+        let error = Chip8Error::UnknownOpcode {
+            pc: 0x200,
+            opcode: 0xFFFF,
+        };
+        assert_eq!(chip8.tick(), Err(error));
+        assert!(chip8.is_halted());
+        assert_eq!(chip8.tick(), Err(error));
+        assert_eq!(chip8.pc, 0x202);
+    }
+
+    #[test]
+    fn loading_a_rom_starts_the_machine_afresh() {
+        let mut chip8 = Chip8::new();
+        // Draws the 0 glyph, calls a subroutine and halts on an unknown opcode.
+        chip8
+            .load_rom(
+                &[
+                    0xA0, 0x50, 0xD0, 0x05, 0x22, 0x08, 0x00, 0x00, 0x61, 0x07, 0xFF, 0xFF,
+                ],
+                DEFAULT_QUIRKS,
+            )
+            .unwrap();
+        chip8.press_key(3).unwrap();
+        assert!(chip8.run_frame().is_err());
+        assert_eq!((chip8.sp, chip8.registers.Vx[1]), (1, 7));
+        assert_ne!(chip8.framebuffer.current(0)[0], 0);
+        let rom = [0x60, 0x01, 0x12, 0x02];
+
+        assert!(chip8.load_rom(&[0; 0x1000], DEFAULT_QUIRKS).is_err());
+        assert!(chip8.is_halted());
+
+        chip8.load_rom(&rom, DEFAULT_QUIRKS).unwrap();
+        assert!(!chip8.is_halted());
+        assert_eq!((chip8.pc, chip8.sp), (0x200, 0));
+        assert_eq!(chip8.registers.Vx[1], 0);
+        assert_eq!(chip8.framebuffer.current(0)[0], 0);
+        // Nothing of the previous ROM is left past the end of this one.
+        assert!(chip8.memory()[0x204..].iter().all(|&byte| byte == 0));
+        assert_eq!(chip8.memory()[FONT_LOCATION], 0xF0);
+        assert!(chip8.is_key_pressed(3));
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.registers.Vx[0], 1);
+
+        chip8.reset();
+        assert_eq!(chip8.rom_hash(), 0);
+        assert!(chip8.memory()[0x200..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn return_with_empty_stack_underflows() {
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x200] = 0x00;
+        chip8.memory[0x201] = 0xEE;
+        assert_eq!(
+            chip8.tick(),
+            Err(Chip8Error::StackUnderflow {
+                pc: 0x200,
+                opcode: 0x00EE
+            })
+        );
+        assert_eq!(chip8.sp, 0);
+    }
+
+    #[test]
+    fn recursive_call_overflows_stack() {
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x200] = 0x22;
+        chip8.memory[0x201] = 0x00;
+        for _ in 0..STACK_DEPTH {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(
+            chip8.tick(),
+            Err(Chip8Error::StackOverflow {
+                pc: 0x200,
+                opcode: 0x2200
+            })
+        );
+    }
+
+    #[test]
+    fn out_of_range_key_and_memory_are_reported() {
+        let mut chip8 = Chip8::new();
+        chip8.registers.Vx[0] = 0x10;
+        chip8.memory[0x200] = 0xE0;
+        chip8.memory[0x201] = 0x9E;
+        assert_eq!(
+            chip8.tick(),
+            Err(Chip8Error::InvalidKey {
+                pc: 0x200,
+                opcode: 0xE09E,
+                key: 0x10
+            })
+        );
+
+        let mut chip8 = Chip8::new();
+        chip8.registers.I = MEMORY_SIZE - 1;
+        chip8.memory[0x200] = 0xF0;
+        chip8.memory[0x201] = 0x33;
+        assert_eq!(
+            chip8.tick(),
+            Err(Chip8Error::MemoryOutOfBounds {
+                pc: 0x200,
+                opcode: 0xF033,
+                address: MEMORY_SIZE
+            })
+        );
+    }
+
+    #[test]
+    fn wrap_policy_wraps_memory_stack_and_i() {
+        let mut chip8 = Chip8::new();
+        chip8.set_memory_policy(MemoryPolicy::Wrap);
+        chip8.registers.I = MEMORY_SIZE - 1;
+        chip8.store_bcd(123).unwrap();
+        assert_eq!(chip8.memory[MEMORY_SIZE - 1], 1);
+        assert_eq!(&chip8.memory[0..2], &[2, 3]);
+
+        chip8.registers.I = 0xFFF;
+        chip8.increment_i(2).unwrap();
+        assert_eq!(chip8.registers.I, 1);
+
+        chip8.return_from_subroutine().unwrap();
+        assert_eq!(chip8.sp, STACK_DEPTH - 1);
+    }
+
+    #[test]
+    fn fault_policy_reports_i_overflow() {
+        let mut chip8 = Chip8::new();
+        chip8.registers.I = 0xFFF;
+        chip8.registers.Vx[3] = 1;
+        chip8.memory[0x200] = 0xF3;
+        chip8.memory[0x201] = 0x1E;
+        assert_eq!(
+            chip8.tick(),
+            Err(Chip8Error::MemoryOutOfBounds {
+                pc: 0x200,
+                opcode: 0xF31E,
+                address: MEMORY_SIZE
+            })
+        );
+    }
+
+    #[test]
+    fn log_policy_keeps_running() {
+        let mut chip8 = Chip8::new();
+        chip8.set_memory_policy(MemoryPolicy::Log);
+        chip8.memory[0x200] = 0x00;
+        chip8.memory[0x201] = 0xEE;
+        chip8.tick().unwrap();
+        assert!(!chip8.is_halted());
+    }
+
+    #[test]
+    fn vf_reset_quirk_clears_flag_on_logic_ops() {
+        let mut chip8 = Chip8::new();
+        chip8.set_quirks(Quirks::from_profile(QuirkProfile::CosmacVip));
+        chip8.registers.Vx[0xF] = 1;
+        chip8.or(0, 0x0F);
+        assert_eq!(chip8.registers.Vx[0xF], 0);
+
+        chip8.set_quirks(Quirks::from_profile(QuirkProfile::XoChip));
+        chip8.registers.Vx[0xF] = 1;
+        chip8.xor(0, 0x0F);
+        assert_eq!(chip8.registers.Vx[0xF], 1);
+    }
+
+    #[test]
+    fn jump_quirk_uses_vx() {
+        let mut chip8 = Chip8::new();
+        chip8.registers.Vx[0] = 1;
+        chip8.registers.Vx[3] = 2;
+        chip8.set_quirks(Quirks::chip48());
+        chip8.jump_relative(3, 0x300);
+        assert_eq!(chip8.pc, 0x302);
+
+        chip8.set_quirks(Quirks::cosmac_vip());
+        chip8.jump_relative(3, 0x300);
+        assert_eq!(chip8.pc, 0x301);
+    }
+
+    #[test]
+    fn clip_quirk_stops_sprites_at_the_edge() {
+        let mut chip8 = Chip8::new();
+        chip8.set_quirks(Quirks::chip48());
+        chip8.memory[0] = 0xFF;
+        chip8.set_I(0);
+        chip8.draw(60, 0, 1).unwrap();
+        assert_eq!(chip8.framebuffer.current(0)[7], 0x0F);
+        assert_eq!(chip8.framebuffer.current(0)[0], 0);
+
+        let mut chip8 = Chip8::new();
+        chip8.set_quirks(Quirks::xo_chip());
+        chip8.memory[0] = 0xFF;
+        chip8.set_I(0);
+        chip8.draw(60, 0, 1).unwrap();
+        assert_eq!(chip8.framebuffer.current(0)[7], 0x0F);
+        assert_eq!(chip8.framebuffer.current(0)[0], 0xF0);
+    }
+
+    #[test]
+    fn sprites_clipped_at_the_bottom_only_collide_in_hires() {
+        let mut chip8 = Chip8::new();
+        chip8.set_quirks(Quirks::chip48());
+        chip8.memory[0..2].copy_from_slice(&[0xFF, 0xFF]);
+        chip8.set_I(0);
+        chip8.draw(0, 31, 2).unwrap();
+        assert_eq!(chip8.registers.Vx[0xF], 0);
+
+        chip8.set_resolution(Resolution::High);
+        chip8.draw(0, 63, 2).unwrap();
+        assert_eq!(chip8.registers.Vx[0xF], 1);
+    }
+
+    #[test]
+    fn display_wait_quirk_draws_once_per_frame() {
+        let mut chip8 = Chip8::new();
+        chip8.set_quirks(Quirks::cosmac_vip());
+        chip8.memory[0x200] = 0xD0;
+        chip8.memory[0x201] = 0x01;
+        chip8.tick().unwrap();
+        assert_eq!(chip8.pc, 0x200);
+        chip8.decrement_timers();
+        chip8.tick().unwrap();
+        assert_eq!(chip8.pc, 0x202);
+    }
+
+    #[test]
+    fn index_overflow_quirk_sets_vf() {
+        let mut chip8 = Chip8::new();
+        chip8.set_quirks(Quirks {
+            index_overflow: true,
+            ..Quirks::xo_chip()
+        });
+        chip8.registers.I = 0xFFF;
+        chip8.increment_i(2).unwrap();
+        assert_eq!(chip8.registers.I, 1);
+        assert_eq!(chip8.registers.Vx[0xF], 1);
+    }
+
+    #[test]
+    fn hires_mode_grows_and_clears_the_display() {
+        let mut chip8 = Chip8::new();
+        chip8.framebuffer.set_pixel(0, 0, 0, true);
+        chip8.set_resolution(Resolution::High);
+        assert_eq!(chip8.display_width(), 128);
+        assert_eq!(chip8.display_height(), 64);
+        assert_eq!(chip8.display_plane(0).unwrap().len(), 128 * 64 / 8);
+        assert_eq!(chip8.framebuffer.current(0)[0], 0);
+        chip8.set_resolution(Resolution::Low);
+        assert_eq!(chip8.display_plane(0).unwrap().len(), 64 * 32 / 8);
+    }
+
+    #[test]
+    fn only_planes_0_and_1_can_be_shown() {
+        let chip8 = Chip8::new();
+        assert!(chip8.display_plane(1).is_some());
+        assert_eq!(chip8.display_plane(2), None);
+        assert_eq!(chip8.display_plane(usize::MAX), None);
+        #[cfg(feature = "flicker")]
+        {
+            assert!(chip8.intensity_plane(1).is_some());
+            assert_eq!(chip8.intensity_plane(2), None);
+        }
+    }
+
+    #[test]
+    fn big_sprites_count_colliding_rows_in_hires() {
+        let mut chip8 = Chip8::new();
+        chip8.set_resolution(Resolution::High);
+        chip8.memory[0x300..0x320].copy_from_slice(&[0xFF; 32]);
+        chip8.set_I(0x300);
+        chip8.draw(0, 0, 0).unwrap();
+        assert_eq!(&chip8.framebuffer.current(0)[..2], &[0xFF, 0xFF]);
+        assert_eq!(chip8.framebuffer.current(0)[2], 0);
+        assert_eq!(chip8.framebuffer.current(0)[15 * 16 + 1], 0xFF);
+        assert_eq!(chip8.registers.Vx[0xF], 0);
+
+        chip8.draw(0, 8, 0).unwrap();
+        assert_eq!(chip8.registers.Vx[0xF], 8);
+    }
+
+    #[test]
+    fn scrolls_the_display() {
+        let mut chip8 = Chip8::new();
+        chip8.framebuffer.set_pixel(0, 4, 0, true);
+        chip8.scroll_down(2);
+        assert!(!chip8.framebuffer.pixel(0, 4, 0));
+        assert!(chip8.framebuffer.pixel(0, 4, 2));
+        chip8.scroll_right();
+        assert!(chip8.framebuffer.pixel(0, 8, 2));
+        chip8.scroll_left();
+        chip8.scroll_left();
+        assert!(chip8.framebuffer.pixel(0, 0, 2));
+        chip8.scroll_left();
+        assert!(!chip8.framebuffer.pixel(0, 0, 2));
+    }
+
+    #[test]
+    fn rpl_flags_round_trip() {
+        let mut chip8 = Chip8::new();
+        chip8.registers.Vx[0..4].copy_from_slice(&[1, 2, 3, 4]);
+        chip8.store_rpl_flags(2);
+        chip8.registers.Vx = [0; 16];
+        chip8.load_rpl_flags(3);
+        assert_eq!(&chip8.registers.Vx[0..4], &[1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn exit_stops_execution() {
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x200] = 0x00;
+        chip8.memory[0x201] = 0xFD;
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+        assert!(chip8.has_exited());
+        assert_eq!(chip8.pc, 0x202);
+    }
+
+    #[test]
+    fn passes_sctest() {
+        let game = Game::new("sctest").unwrap();
+        let mut chip8 = Chip8::new();
+        chip8.set_quirks(game.quirks);
+        chip8.memory[0x200..0x200 + game.code.len()].copy_from_slice(game.code);
+        for _ in 0..100_000 {
+            chip8.tick().unwrap();
+        }
+        // SCTEST prints "OK" in the top left corner when every test passed.
+        let ok = [
+            "####.#..#",
+            "#..#.#.#.",
+            "#..#.##..",
+            "#..#.#.#.",
+            "####.#..#",
+        ];
+        for (y, row) in ok.iter().enumerate() {
+            for (x, pixel) in row.chars().enumerate() {
+                assert_eq!(
+                    chip8.framebuffer.pixel(0, x, y),
+                    pixel == '#',
+                    "({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn long_i_load_is_skipped_as_one_instruction() {
+        let mut chip8 = Chip8::new();
+        chip8.set_quirks(Quirks::xo_chip());
+        chip8.memory[0x200..0x208]
+            .copy_from_slice(&[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0xF0, 0x00]);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.pc, 0x206);
+        chip8.memory[0x208..0x20A].copy_from_slice(&[0xFF, 0xFE]);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.registers.I, 0xFFFE);
+        assert_eq!(chip8.pc, 0x20A);
+    }
+
+    #[test]
+    #[cfg(feature = "xo-chip")]
+    fn extended_memory_is_only_addressable_by_xo_chip() {
+        let mut chip8 = Chip8::new();
+        chip8.registers.I = 0x1000;
+        assert_eq!(chip8.bulk_store(0), Err(Fault::MemoryOutOfBounds(0x1000)));
+        chip8.set_quirks(Quirks::xo_chip());
+        chip8.registers.Vx[0] = 7;
+        chip8.bulk_store(0).unwrap();
+        assert_eq!(chip8.memory[0x1000], 7);
+    }
+
+    #[test]
+    #[cfg(not(feature = "xo-chip"))]
+    fn extended_memory_needs_the_xo_chip_feature() {
+        let mut chip8 = Chip8::new();
+        chip8.set_quirks(Quirks::xo_chip());
+        assert_eq!(chip8.memory().len(), MEMORY_SIZE);
+        chip8.registers.I = 0x1000;
+        assert_eq!(chip8.bulk_store(0), Err(Fault::MemoryOutOfBounds(0x1000)));
+
+        // Its states load in builds with the feature as those of a 4 KiB machine.
+        let mut state = [0; MAX_STATE_SIZE];
+        chip8.save_state_into(&mut state).unwrap();
+        assert!(!state::quirks_from_bits(state[10]).extended_memory);
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "xo-chip", feature = "flicker")))]
+    fn a_chip8_machine_takes_a_few_kib() {
+        assert!(core::mem::size_of::<Chip8>() < 10 * 1024);
+    }
+
+    #[test]
+    fn stores_and_loads_register_ranges() {
+        let mut chip8 = Chip8::new();
+        chip8.registers.I = 0x300;
+        chip8.registers.Vx[2..5].copy_from_slice(&[1, 2, 3]);
+        chip8.store_range(2, 4).unwrap();
+        assert_eq!(&chip8.memory[0x300..0x303], &[1, 2, 3]);
+        chip8.load_range(7, 5).unwrap();
+        assert_eq!(&chip8.registers.Vx[5..8], &[3, 2, 1]);
+        assert_eq!(chip8.registers.I, 0x300);
+    }
+
+    #[test]
+    fn draws_clears_and_scrolls_selected_planes() {
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x300..0x302].copy_from_slice(&[0xF0, 0x0F]);
+        chip8.set_I(0x300);
+        chip8.select_planes(0b11);
+        chip8.draw(0, 0, 1).unwrap();
+        assert_eq!(chip8.framebuffer.current(0)[0], 0xF0);
+        assert_eq!(chip8.framebuffer.current(1)[0], 0x0F);
+
+        chip8.select_planes(0b10);
+        chip8.scroll_down(1);
+        assert!(chip8.framebuffer.pixel(0, 0, 0));
+        assert!(!chip8.framebuffer.pixel(1, 4, 0));
+        assert!(chip8.framebuffer.pixel(1, 4, 1));
+
+        chip8.clear_display();
+        assert!(chip8.framebuffer.pixel(0, 0, 0));
+        assert!(!chip8.framebuffer.pixel(1, 4, 1));
+
+        chip8.select_planes(0);
+        chip8.draw(0, 0, 1).unwrap();
+        assert_eq!(chip8.registers.Vx[0xF], 0);
+        assert_eq!(chip8.framebuffer.current(0)[0], 0xF0);
+    }
+
+    #[test]
+    fn pitch_sets_the_playback_rate() {
+        let mut chip8 = Chip8::new();
+        assert_eq!(chip8.audio.playback_rate(), 4000.0);
+        chip8.set_pitch(112);
+        assert_eq!(chip8.audio.playback_rate(), 8000.0);
+    }
+
+    #[test]
+    fn renders_the_loaded_pattern_while_the_sound_timer_runs() {
+        let mut chip8 = Chip8::new();
+        chip8.set_audio_sample_rate(4000.0);
+        chip8.memory[0x300] = 0b1010_0000;
+        chip8.set_I(0x300);
+        chip8.load_audio_pattern().unwrap();
+
+        let mut out = [1.0; 4];
+        chip8.render_audio(&mut out);
+        assert_eq!(out, [0.0; 4]);
+
+        chip8.set_sound_timer(2);
+        chip8.render_audio(&mut out);
+        assert!(out[0] > 0.0 && out[1] < 0.0 && out[2] > 0.0 && out[3] < 0.0);
+    }
+
+    #[test]
+    fn falls_back_to_a_square_wave() {
+        let mut chip8 = Chip8::new();
+        chip8.set_audio_sample_rate(880.0);
+        chip8.set_sound_timer(1);
+        let mut out = [0.0; 4];
+        chip8.render_audio(&mut out);
+        assert!(out[0] > 0.0 && out[1] < 0.0 && out[2] > 0.0 && out[3] < 0.0);
+
+        chip8.decrement_timers();
+        chip8.render_audio(&mut out);
+        assert_eq!(out, [0.0; 4]);
+    }
+
+    #[test]
+    fn waits_for_a_key_press_and_release() {
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x200] = 0xF3;
+        chip8.memory[0x201] = 0x0A;
+        chip8.tick().unwrap();
+        assert!(chip8.is_waiting_for_key());
+        assert_eq!(chip8.pc, 0x200);
+
+        chip8.keypad[5] = true;
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+        assert!(chip8.is_waiting_for_key());
+        assert_eq!(chip8.pc, 0x200);
+
+        chip8.keypad[5] = false;
+        chip8.tick().unwrap();
+        assert!(!chip8.is_waiting_for_key());
+        assert_eq!(chip8.registers.Vx[3], 5);
+        assert_eq!(chip8.pc, 0x202);
+    }
+
+    #[test]
+    fn timers_run_while_waiting_for_a_key() {
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x200] = 0xF0;
+        chip8.memory[0x201] = 0x0A;
+        chip8.set_delay_timer(2);
+        chip8.tick().unwrap();
+        chip8.decrement_timers();
+        chip8.tick().unwrap();
+        assert_eq!(chip8.registers.delay, 1);
+    }
+
+    #[test]
+    fn seeded_runs_are_reproducible() {
+        let mut first = Chip8::new();
+        let mut second = Chip8::new();
+        first.set_random_seed(1234);
+        second.set_random_seed(first.random_seed());
+        for _ in 0..16 {
+            first.set_random_number(0, 0xFF);
+            second.set_random_number(0, 0xFF);
+            assert_eq!(first.registers.Vx[0], second.registers.Vx[0]);
+        }
+    }
+
+    #[test]
+    fn scripted_random_is_masked_with_nn() {
+        let mut chip8 = Chip8::with_random_source(ScriptedRandom::new(&[0xAB, 0xFF]));
+        chip8.set_random_number(1, 0x0F);
+        assert_eq!(chip8.registers.Vx[1], 0x0B);
+        chip8.set_random_number(1, 0xF0);
+        assert_eq!(chip8.registers.Vx[1], 0xF0);
+        chip8.set_random_number(1, 0xFF);
+        assert_eq!(chip8.registers.Vx[1], 0xAB);
+    }
+
+    #[test]
+    fn cosmac_vip_random_depends_on_frames_and_memory() {
+        let mut chip8 = Chip8::new();
+        chip8.use_cosmac_vip_random(0x0200);
+        chip8.memory[0x201] = 0x10;
+        chip8.memory[0x203] = 0x22;
+        chip8.set_random_number(0, 0xFF);
+        assert_eq!(chip8.registers.Vx[0], 0x12);
+        chip8.decrement_timers();
+        chip8.set_random_number(0, 0xFF);
+        assert_eq!(chip8.registers.Vx[0], 0x34);
+    }
+
+    // Adds 1 to V0 in a loop, so V0 counts every second instruction executed.
+    fn counting_loop() -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]);
+        chip8
+    }
+
+    #[test]
+    fn run_frame_executes_a_frame_of_instructions_and_ticks_the_timers() {
+        let mut chip8 = counting_loop();
+        chip8.set_cpu_speed(1200);
+        chip8.registers.delay = 10;
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.registers.Vx[0], 10);
+        assert_eq!(chip8.registers.delay, 9);
+    }
+
+    #[test]
+    fn run_for_carries_over_partial_frames_and_instructions() {
+        let mut chip8 = counting_loop();
+        chip8.set_cpu_speed(150);
+        chip8.registers.delay = 10;
+        assert_eq!(chip8.run_for(10.0).unwrap(), 0);
+        assert_eq!(chip8.registers.delay, 10);
+        assert_eq!(chip8.run_for(10.0).unwrap(), 1);
+        assert_eq!(chip8.run_for(1000.0 / 60.0).unwrap(), 1);
+        assert_eq!(chip8.registers.delay, 8);
+        // 150 instructions per second is 2.5 per frame.
+        assert_eq!(chip8.run_for(1000.0 / 30.0).unwrap(), 2);
+        assert_eq!(chip8.registers.Vx[0], 5);
+    }
+
+    #[test]
+    fn run_for_stops_on_a_fault() {
+        let mut chip8 = Chip8::new();
+        assert!(chip8.run_for(100.0).is_err());
+        assert!(chip8.is_halted());
+    }
+
+    #[test]
+    fn cosmac_vip_timing_runs_a_frame_of_machine_cycles() {
+        let mut chip8 = counting_loop();
+        chip8.set_timing_mode(TimingMode::CosmacVip);
+        chip8.run_frame().unwrap();
+        // 2608 cycles are left after the interrupt, each 7001 + 1200 pass takes 102.
+        assert_eq!(chip8.registers.Vx[0], 26);
+    }
+
+    #[test]
+    fn cosmac_vip_timing_draws_once_per_frame() {
+        let mut chip8 = Chip8::new();
+        chip8.set_quirks(Quirks::cosmac_vip());
+        chip8.set_timing_mode(TimingMode::CosmacVip);
+        chip8.memory[0x200..0x206].copy_from_slice(&[0x70, 0x01, 0xD1, 0x15, 0x12, 0x00]);
+        for _ in 0..3 {
+            chip8.run_frame().unwrap();
+        }
+        assert_eq!(chip8.registers.Vx[0], 3);
+    }
+
+    #[test]
+    fn cosmac_vip_timing_reads_vx_before_the_instruction_changes_it() {
+        let cycles_left = |opcode: u16, x: usize| {
+            let mut chip8 = Chip8::new();
+            chip8.set_quirks(Quirks::chip48());
+            chip8.set_timing_mode(TimingMode::CosmacVip);
+            chip8.memory[0x200..0x202].copy_from_slice(&opcode.to_be_bytes());
+            chip8.registers.Vx[x] = 3;
+            chip8.advance().unwrap();
+            // Nothing collided, so VF is no longer the X coordinate.
+            assert_eq!(chip8.registers.Vx[0xF], 0);
+            chip8.clock.cycle_budget
+        };
+        assert_eq!(cycles_left(0xDF01, 0xF), cycles_left(0xD101, 1));
+    }
+
+    #[test]
+    fn cosmac_vip_cycles_depend_on_operands() {
+        use super::clock::cosmac_vip_cycles;
+        assert!(cosmac_vip_cycles(0x3000, 0, true) > cosmac_vip_cycles(0x3000, 0, false));
+        assert!(cosmac_vip_cycles(0xD015, 3, false) > cosmac_vip_cycles(0xD015, 8, false));
+        assert!(cosmac_vip_cycles(0xF033, 199, false) > cosmac_vip_cycles(0xF033, 100, false));
+        assert!(cosmac_vip_cycles(0xD015, 0, false) > cosmac_vip_cycles(0x6000, 0, false));
+    }
+
+    #[test]
+    fn tall_resolution_is_64x64() {
+        let mut chip8 = Chip8::new();
+        chip8.set_resolution(Resolution::Tall);
+        assert_eq!(chip8.display_width(), 64);
+        assert_eq!(chip8.display_height(), 64);
+        chip8.memory[0x300] = 0x80;
+        chip8.set_I(0x300);
+        chip8.draw(0, 63, 1).unwrap();
+        assert!(chip8.framebuffer.pixel(0, 0, 63));
+        chip8.set_I(0x300);
+        chip8.draw(0, 63, 1).unwrap();
+        assert!(!chip8.framebuffer.pixel(0, 0, 63));
+    }
+
+    #[test]
+    fn stack_and_memory_writes_leave_the_display_alone() {
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x300] = 0xFF;
+        chip8.set_I(0x300);
+        chip8.draw(0, 0, 1).unwrap();
+        chip8.registers.Vx = [0xAA; 16];
+        chip8.set_I(0xF00);
+        chip8.bulk_store(0xF).unwrap();
+        for _ in 0..STACK_DEPTH {
+            chip8.call_subroutine(0x400).unwrap();
+        }
+        assert_eq!(chip8.framebuffer.current(0)[0], 0xFF);
+        assert_eq!(chip8.memory[0xF00], 0xAA);
+    }
+
+    #[test]
+    fn vip_display_mirror_copies_the_display_into_memory() {
+        let mut chip8 = Chip8::new();
+        chip8.set_vip_display_mirror(true);
+        chip8.memory[0x300] = 0xFF;
+        chip8.set_I(0x300);
+        chip8.draw(8, 1, 1).unwrap();
+        assert_eq!(chip8.memory[0xF00 + 8 + 1], 0xFF);
+        chip8.clear_display();
+        assert_eq!(chip8.memory[0xF00 + 8 + 1], 0);
+
+        chip8.set_resolution(Resolution::Tall);
+        chip8.set_I(0x300);
+        chip8.draw(0, 63, 1).unwrap();
+        assert_eq!(chip8.memory[0xFF8], 0xFF);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn vip_display_mirror_leaves_compiled_code_alone() {
+        let mut chip8 = Chip8::new();
+        chip8.set_engine(Engine::Recompiler);
+        chip8.set_vip_display_mirror(true);
+        chip8.memory[0x300] = 0xFF;
+        chip8.set_I(0x300);
+        chip8.memory.code_replaced = false;
+        chip8.draw(8, 1, 1).unwrap();
+        chip8.clear_display();
+        assert!(!chip8.memory.code_replaced);
+        assert!(chip8.memory.code_writes.is_empty());
+        assert_eq!(chip8.memory[0xF00 + 8 + 1], 0);
+    }
+
+    // Draws a single pixel at the top left corner, which erases it the second time.
+    fn toggle_corner(chip8: &mut Chip8) {
+        chip8.memory[0x300] = 0x80;
+        chip8.set_I(0x300);
+        chip8.draw(0, 0, 1).unwrap();
+    }
+
+    #[test]
+    fn flicker_filter_off_shows_the_frame_as_is() {
+        let mut chip8 = Chip8::new();
+        chip8.set_flicker_filter(FlickerFilter::Off);
+        toggle_corner(&mut chip8);
+        toggle_corner(&mut chip8);
+        assert_eq!(chip8.framebuffer.shown(0)[0], 0);
+        chip8.decrement_timers();
+        #[cfg(feature = "flicker")]
+        assert_eq!(chip8.framebuffer.intensity(0)[0], 0);
+    }
+
+    #[test]
+    #[cfg(feature = "flicker")]
+    fn persistence_keeps_pixels_lit_for_n_frames() {
+        let mut chip8 = Chip8::new();
+        chip8.set_flicker_filter(FlickerFilter::Persistence);
+        chip8.set_persistence_frames(2);
+        toggle_corner(&mut chip8);
+        chip8.decrement_timers();
+        toggle_corner(&mut chip8);
+        assert_eq!(chip8.framebuffer.shown(0)[0], 0x80);
+        // Pixels are shown while they were on in any of the last 2 frames, which
+        // includes this one.
+        for _ in 0..2 {
+            chip8.decrement_timers();
+            assert_eq!(chip8.framebuffer.intensity(0)[0], 0xFF);
+        }
+        chip8.decrement_timers();
+        assert_eq!(chip8.framebuffer.intensity(0)[0], 0);
+        assert_eq!(chip8.framebuffer.shown(0)[0], 0);
+    }
+
+    #[test]
+    #[cfg(feature = "flicker")]
+    fn phosphor_decay_fades_pixels_out() {
+        let mut chip8 = Chip8::new();
+        chip8.set_flicker_filter(FlickerFilter::PhosphorDecay);
+        chip8.set_phosphor_decay(0.5);
+        // Erased and redrawn within a frame: it never stops glowing.
+        toggle_corner(&mut chip8);
+        toggle_corner(&mut chip8);
+        chip8.decrement_timers();
+        assert_eq!(chip8.framebuffer.intensity(0)[0], 0xFF);
+        chip8.decrement_timers();
+        assert_eq!(chip8.framebuffer.intensity(0)[0], 0x7F);
+        assert_eq!(chip8.framebuffer.shown(0)[0], 0);
+        chip8.decrement_timers();
+        assert_eq!(chip8.framebuffer.intensity(0)[0], 0x3F);
+        assert_eq!(chip8.intensity_plane(0).unwrap().len(), 64 * 32);
+    }
+
+    #[test]
+    fn rejects_keys_outside_of_the_keypad() {
+        let mut chip8 = Chip8::new();
+        chip8.press_key(0xF).unwrap();
+        assert!(chip8.keypad[0xF]);
+        assert_eq!(chip8.press_key(16), Err(HostError::InvalidKey(16)));
+        assert_eq!(chip8.release_key(16), Err(HostError::InvalidKey(16)));
+    }
+
+    struct FixedStep(f64);
+
+    impl TimeSource for FixedStep {
+        fn elapsed_ms(&mut self) -> f64 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn runs_for_the_time_reported_by_a_time_source() {
+        let mut chip8 = counting_loop();
+        chip8.set_cpu_speed(120);
+        let mut time = FixedStep(2000.0 / 60.0);
+        assert_eq!(chip8.run(&mut time).unwrap(), 2);
+        assert_eq!(chip8.registers.Vx[0], 2);
+    }
+
+    fn brix_in_play() -> Chip8 {
+        let mut chip8 = Chip8::with_seed(7);
+        chip8.load_game("BRIX", None).unwrap();
+        chip8.press_key(4).unwrap();
+        for _ in 0..90 {
+            chip8.run_frame().unwrap();
+        }
+        chip8
+    }
+
+    // Without `std` too: states go into buffers the host provides.
+    #[test]
+    fn saves_states_into_fixed_buffers() {
+        let mut chip8 = brix_in_play();
+        let mut state = [0; MAX_STATE_SIZE];
+        let size = chip8.save_state_into(&mut state).unwrap();
+        assert_eq!(size, chip8.state_size());
+
+        let mut restored = Chip8::with_seed(1);
+        restored.load_state(&state[..size]).unwrap();
+        assert_eq!(restored.display_plane(0), chip8.display_plane(0));
+        for chip8 in [&mut chip8, &mut restored] {
+            chip8.run_frame().unwrap();
+        }
+        let mut restored_state = [0; MAX_STATE_SIZE];
+        restored.save_state_into(&mut restored_state).unwrap();
+        chip8.save_state_into(&mut state).unwrap();
+        assert_eq!(restored_state[..size], state[..size]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn loading_a_state_resumes_where_it_was_saved() {
+        let mut chip8 = brix_in_play();
+        let state = chip8.save_state();
+        assert_eq!(state.len(), chip8.state_size());
+        let run_on = |chip8: &mut Chip8| {
+            chip8.release_key(4).unwrap();
+            for _ in 0..120 {
+                chip8.run_frame().unwrap();
+            }
+            chip8.save_state()
+        };
+        let first_run = run_on(&mut chip8);
+        chip8.load_state(&state).unwrap();
+        assert_eq!(chip8.save_state(), state);
+        assert_eq!(run_on(&mut chip8), first_run);
+
+        // A fresh machine picks up the ROM, quirks and display too.
+        let mut restored = Chip8::with_seed(1);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.rom_hash(), brix_in_play().rom_hash());
+        assert_ne!(restored.rom_hash(), 0);
+        assert_eq!(restored.quirks(), brix_in_play().quirks());
+        assert_eq!(restored.display_plane(0), brix_in_play().display_plane(0));
+        assert!(restored.is_key_pressed(4));
+        assert_eq!(run_on(&mut restored), first_run);
+    }
+
+    #[test]
+    #[cfg(feature = "xo-chip")]
+    fn saves_xo_chip_memory_and_cosmac_vip_random() {
+        let mut chip8 = Chip8::with_seed(1);
+        chip8.use_cosmac_vip_random(0x1234);
+        chip8.load_rom(&[0x12, 0x00], Quirks::xo_chip()).unwrap();
+        chip8.memory[0xFFFF] = 0xAB;
+        chip8.set_resolution(Resolution::High);
+        chip8.framebuffer.set_pixel(1, 127, 63, true);
+        let mut state = [0; MAX_STATE_SIZE];
+        let size = chip8.save_state_into(&mut state).unwrap();
+        assert_eq!(size, MAX_STATE_SIZE);
+
+        let mut restored = Chip8::with_seed(1);
+        restored.load_state(&state[..size]).unwrap();
+        assert_eq!(restored.memory()[0xFFFF], 0xAB);
+        assert_eq!(restored.random_seed(), 0x1234);
+        assert_eq!(restored.resolution(), Resolution::High);
+        assert!(restored.framebuffer.pixel(1, 127, 63));
+        assert_eq!(
+            chip8.save_state_into(&mut state[..100]),
+            Err(StateError::BufferTooSmall {
+                size: 100,
+                needed: MAX_STATE_SIZE
+            })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn rejects_damaged_and_unknown_states() {
+        let mut chip8 = brix_in_play();
+        let state = chip8.save_state();
+        let pc = chip8.pc;
+        let damaged = |change: &dyn Fn(&mut Vec<u8>)| {
+            let mut state = state.clone();
+            change(&mut state);
+            state
+        };
+
+        assert_eq!(chip8.load_state(b"PNG"), Err(StateError::NotASaveState));
+        assert_eq!(
+            chip8.load_state(&damaged(&|state| state[4] = 2)),
+            Err(StateError::UnsupportedVersion(2))
+        );
+        assert_eq!(
+            chip8.load_state(&damaged(&|state| state.truncate(100))),
+            Err(StateError::Truncated)
+        );
+        assert_eq!(
+            chip8.load_state(&damaged(&|state| state[0x300] ^= 1)),
+            Err(StateError::ChecksumMismatch)
+        );
+        // A stack pointer past the end of the stack, with a checksum to match.
+        let sp = 10 + 4 + 1 + MEMORY_SIZE + 38 + 4 + 4 * STACK_DEPTH;
+        let invalid = damaged(&|state| {
+            state[sp] = 17;
+            let end = state.len() - 4;
+            let checksum = state::crc32(&state[..end]);
+            state[end..].copy_from_slice(&checksum.to_le_bytes());
+        });
+        assert_eq!(
+            chip8.load_state(&invalid),
+            Err(StateError::InvalidValue("stack pointer"))
+        );
+        assert_eq!(chip8.pc, pc);
+        assert_eq!(chip8.save_state(), state);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn rewinds_to_earlier_frames() {
+        let mut chip8 = Chip8::with_seed(7);
+        chip8.load_game("BRIX", None).unwrap();
+        assert_eq!(chip8.rewind(10), 0);
+        chip8.set_rewind_history(300);
+        chip8.press_key(4).unwrap();
+        let mut states = Vec::new();
+        for _ in 0..200 {
+            chip8.run_frame().unwrap();
+            states.push(chip8.save_state());
+        }
+        assert_eq!(chip8.rewind_available(), 199);
+
+        assert_eq!(chip8.rewind(1), 1);
+        assert_eq!(chip8.save_state(), states[198]);
+        assert_eq!(chip8.rewind(100), 100);
+        assert_eq!(chip8.save_state(), states[98]);
+        // Frames run after rewinding replace the ones rewound over.
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.save_state(), states[99]);
+        assert_eq!(chip8.rewind(1000), 99);
+        assert_eq!(chip8.save_state(), states[0]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn rewind_history_is_bounded_and_compact() {
+        let mut chip8 = brix_in_play();
+        chip8.set_rewind_history(120);
+        for _ in 0..600 {
+            chip8.run_frame().unwrap();
+        }
+        let history = chip8.history.as_ref().unwrap();
+        assert!((120..180).contains(&history.len()));
+        // Far less than a full state per frame.
+        assert!(history.size() < history.len() * chip8.state_size() / 8);
+
+        chip8.set_rewind_history(30);
+        assert!(chip8.rewind_available() < 90);
+        chip8.load_game("PONG", None).unwrap();
+        assert_eq!(chip8.rewind_available(), 0);
+        chip8.set_rewind_history(0);
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.rewind(1), 0);
+    }
+
+    /// Steps BRIX by hand for 240 frames, pressing and releasing keys in the
+    /// middle of frames when `input` is set, and pressing 0xF throughout.
+    #[cfg(feature = "std")]
+    fn play_brix(chip8: &mut Chip8, input: bool) {
+        for frame in 0..240 {
+            for instruction in 0..12 {
+                if input && frame % 7 == 0 && instruction == 5 {
+                    chip8.press_key(6).unwrap();
+                }
+                if input && frame % 7 == 3 && instruction == 9 {
+                    chip8.release_key(6).unwrap();
+                }
+                chip8.tick().unwrap();
+            }
+            chip8.press_key(0xF).unwrap();
+            chip8.decrement_timers();
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn movies_replay_runs_exactly() {
+        let mut chip8 = brix_in_play();
+        chip8.release_key(4).unwrap();
+        chip8.start_recording();
+        assert!(chip8.is_recording());
+        play_brix(&mut chip8, true);
+        // Keys pressed after the last frame are part of the movie too.
+        chip8.press_key(4).unwrap();
+        let movie = chip8.stop_recording().unwrap();
+        assert!(!chip8.is_recording());
+        assert_eq!(movie.frames(), 240);
+        assert_eq!(movie.random_seed(), 7);
+        assert_eq!(
+            movie.events()[..2],
+            [
+                InputEvent {
+                    frame: 0,
+                    instruction: 5,
+                    key: 6,
+                    pressed: true
+                },
+                InputEvent {
+                    frame: 0,
+                    instruction: 12,
+                    key: 0xF,
+                    pressed: true
+                },
+            ]
+        );
+        let recorded = chip8.save_state();
+
+        let mut replay = Chip8::with_seed(1);
+        replay.load_game("BRIX", None).unwrap();
+        replay.play_movie(&movie).unwrap();
+        assert!(replay.is_playing_movie());
+        // Keys the host presses during playback are ignored.
+        play_brix(&mut replay, false);
+        assert!(!replay.is_playing_movie());
+        assert_eq!(replay.save_state(), recorded);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn movies_replay_the_frame_timing() {
+        let mut chip8 = Chip8::with_seed(3);
+        chip8.load_game("PONG2", None).unwrap();
+        chip8.set_timing_mode(TimingMode::CosmacVip);
+        chip8.set_memory_policy(MemoryPolicy::Wrap);
+        for _ in 0..30 {
+            chip8.run_frame().unwrap();
+        }
+        chip8.start_recording();
+        for frame in 0..300 {
+            if frame % 40 == 0 {
+                chip8.press_key(1).unwrap();
+            } else if frame % 40 == 25 {
+                chip8.release_key(1).unwrap();
+            }
+            chip8.run_frame().unwrap();
+        }
+        let movie = chip8.stop_recording().unwrap();
+
+        let mut replay = Chip8::with_seed(3);
+        replay.load_game("PONG2", None).unwrap();
+        replay.play_movie(&movie).unwrap();
+        assert_eq!(replay.timing_mode(), TimingMode::CosmacVip);
+        assert_eq!(replay.memory_policy(), MemoryPolicy::Wrap);
+        while replay.is_playing_movie() {
+            replay.run_frame().unwrap();
+        }
+        assert_eq!(replay.save_state(), chip8.save_state());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn movie_files_round_trip_and_reject_damage() {
+        let mut chip8 = brix_in_play();
+        chip8.start_recording();
+        play_brix(&mut chip8, true);
+        let movie = chip8.stop_recording().unwrap();
+        let bytes = movie.to_bytes();
+        assert_eq!(Movie::from_bytes(&bytes), Ok(movie.clone()));
+
+        assert_eq!(Movie::from_bytes(b"C8SV"), Err(MovieError::NotAMovie));
+        let mut newer = bytes.clone();
+        newer[4] = 2;
+        assert_eq!(
+            Movie::from_bytes(&newer),
+            Err(MovieError::UnsupportedVersion(2))
+        );
+        assert_eq!(
+            Movie::from_bytes(&bytes[..bytes.len() - 20]),
+            Err(MovieError::Truncated)
+        );
+        let mut damaged = bytes.clone();
+        damaged[bytes.len() - 6] ^= 0x01;
+        assert_eq!(
+            Movie::from_bytes(&damaged),
+            Err(MovieError::ChecksumMismatch)
+        );
+
+        let mut other = Chip8::with_seed(7);
+        other.load_game("PONG", None).unwrap();
+        let state = other.save_state();
+        assert_eq!(
+            other.play_movie(&movie),
+            Err(MovieError::RomMismatch {
+                expected: chip8.rom_hash(),
+                found: other.rom_hash(),
+            })
+        );
+        assert_eq!(other.save_state(), state);
+        assert!(!other.is_playing_movie());
+    }
+
+    /// Calls a subroutine that writes V0 as BCD to 0x300, then loops forever.
+    #[cfg(feature = "std")]
+    fn subroutine_rom() -> Chip8 {
+        let mut chip8 = Chip8::with_seed(1);
+        chip8
+            .load_rom(
+                &[
+                    0x60, 0x05, // 200: V0 = 5
+                    0xA3, 0x00, // 202: I = 0x300
+                    0x22, 0x10, // 204: call 0x210
+                    0x70, 0x01, // 206: V0 += 1
+                    0x12, 0x08, // 208: jump 0x208
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // padding
+                    0x61, 0x07, // 210: V1 = 7
+                    0xF0, 0x33, // 212: BCD of V0 at I
+                    0x00, 0xEE, // 214: return
+                ],
+                DEFAULT_QUIRKS,
+            )
+            .unwrap();
+        chip8
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn debugger_stops_at_breakpoints_and_steps() {
+        let mut chip8 = subroutine_rom();
+        chip8.add_breakpoint(0x204);
+        chip8.run_frame().unwrap();
+        assert_eq!(
+            chip8.stop_reason(),
+            Some(StopReason::Breakpoint { pc: 0x204 })
+        );
+        assert_eq!(chip8.pc(), 0x204);
+        // Stopped machines don't run.
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.pc(), 0x204);
+
+        assert_eq!(chip8.debug_run(Resume::StepInto, 1), Some(StopReason::Step));
+        assert_eq!(chip8.pc(), 0x210);
+        assert_eq!(chip8.stack(), &[0x206]);
+        assert_eq!(chip8.debug_run(Resume::StepOut, 1), Some(StopReason::Step));
+        assert_eq!(chip8.pc(), 0x206);
+        assert_eq!(chip8.register(Register::V(1)), 7);
+        assert_eq!(
+            chip8.debug_run(Resume::RunTo(0x208), 1),
+            Some(StopReason::Step)
+        );
+        assert_eq!(chip8.register(Register::V(0)), 6);
+        assert_eq!(chip8.debug_run(Resume::Continue, 3), None);
+
+        let mut chip8 = subroutine_rom();
+        chip8.add_breakpoint(0x204);
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.debug_run(Resume::StepOver, 1), Some(StopReason::Step));
+        assert_eq!(chip8.pc(), 0x206);
+        assert!(chip8.stack().is_empty());
+        assert_eq!(chip8.memory()[0x300..0x303], [0, 0, 5]);
+        // Resuming at a breakpoint doesn't stop at it again before moving on.
+        chip8.add_breakpoint(0x208);
+        assert_eq!(
+            chip8.debug_run(Resume::Continue, 1),
+            Some(StopReason::Breakpoint { pc: 0x208 })
+        );
+        assert_eq!(
+            chip8.debug_run(Resume::Continue, 1),
+            Some(StopReason::Breakpoint { pc: 0x208 })
+        );
+        assert!(chip8.remove_breakpoint(0x208));
+        assert_eq!(chip8.breakpoints(), &[0x204]);
+        assert_eq!(chip8.debug_run(Resume::Continue, 3), None);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn ticking_stops_at_breakpoints_and_watches() {
+        let mut chip8 = subroutine_rom();
+        chip8.add_breakpoint(0x204);
+        chip8.watch_register(Register::V(1));
+        for _ in 0..3 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(
+            chip8.stop_reason(),
+            Some(StopReason::Breakpoint { pc: 0x204 })
+        );
+        assert_eq!(chip8.pc(), 0x204);
+
+        chip8.resume(Resume::Continue);
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+        assert_eq!(
+            chip8.stop_reason(),
+            Some(StopReason::Register {
+                pc: 0x210,
+                register: Register::V(1),
+                old: 0,
+                new: 7
+            })
+        );
+        // Stopped machines don't tick.
+        chip8.tick().unwrap();
+        assert_eq!(chip8.pc(), 0x212);
+
+        chip8.resume(Resume::Continue);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.pc(), 0x214);
+        chip8.pause();
+        chip8.tick().unwrap();
+        assert_eq!(chip8.pc(), 0x214);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn debugger_stops_on_watched_registers_and_memory() {
+        let mut chip8 = subroutine_rom();
+        chip8.watch_register(Register::V(1));
+        chip8.watch_memory(0x301..0x302, Access::Write);
+        chip8.run_frame().unwrap();
+        assert_eq!(
+            chip8.stop_reason(),
+            Some(StopReason::Register {
+                pc: 0x210,
+                register: Register::V(1),
+                old: 0,
+                new: 7
+            })
+        );
+        assert_eq!(
+            chip8.debug_run(Resume::Continue, 1),
+            Some(StopReason::Memory {
+                pc: 0x212,
+                address: 0x301,
+                access: Access::Write
+            })
+        );
+        chip8.clear_breakpoints();
+        assert_eq!(chip8.debug_run(Resume::Continue, 3), None);
+
+        // Fetching instructions doesn't read memory, drawing sprites does.
+        let mut chip8 = Chip8::with_seed(1);
+        chip8
+            .load_rom(&[0xA2, 0x06, 0xD0, 0x01, 0x12, 0x04, 0xFF], DEFAULT_QUIRKS)
+            .unwrap();
+        chip8.watch_memory(0x200..0x207, Access::Read);
+        chip8.run_frame().unwrap();
+        assert_eq!(
+            chip8.stop_reason(),
+            Some(StopReason::Memory {
+                pc: 0x202,
+                address: 0x206,
+                access: Access::Read
+            })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn stopping_in_the_middle_of_frames_keeps_their_timing() {
+        let run = |debug: bool| {
+            let mut chip8 = brix_in_play();
+            chip8.set_timing_mode(TimingMode::CosmacVip);
+            if debug {
+                chip8.add_breakpoint(chip8.pc());
+                chip8.watch_register(Register::V(0));
+            }
+            let mut frames = 0;
+            let mut stops = 0;
+            while frames < 120 {
+                chip8.run_frame().unwrap();
+                if chip8.stop_reason().is_some() {
+                    chip8.resume(Resume::Continue);
+                    stops += 1;
+                } else {
+                    frames += 1;
+                }
+            }
+            (chip8.save_state(), stops)
+        };
+        let (debugged, stops) = run(true);
+        assert!(stops > 10);
+        assert_eq!(debugged, run(false).0);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn decodes_and_writes_instructions() {
+        let cases = [
+            (0x00E0, Instruction::Clear, "clear", "CLS"),
+            (0x2ABC, Instruction::Call(0xABC), ":call 0xABC", "CALL #ABC"),
+            (
+                0x3A05,
+                Instruction::SkipIfEqualByte { x: 0xA, byte: 0x05 },
+                "if va != 0x05 then",
+                "SE VA, #05",
+            ),
+            (
+                0x8126,
+                Instruction::ShiftRight { x: 1, y: 2 },
+                "v1 >>= v2",
+                "SHR V1, V2",
+            ),
+            (
+                0xD125,
+                Instruction::Draw { x: 1, y: 2, n: 5 },
+                "sprite v1 v2 5",
+                "DRW V1, V2, 5",
+            ),
+            (0xF301, Instruction::SelectPlanes(3), "plane 3", "PLANE 3"),
+            (0xF433, Instruction::Bcd { x: 4 }, "bcd v4", "LD B, V4"),
+            (
+                0x5121,
+                Instruction::Unknown(0x5121),
+                "0x51 0x21",
+                "DW #5121",
+            ),
+        ];
+        for &(opcode, instruction, octo, cowgod) in &cases {
+            assert_eq!(Instruction::decode(opcode), instruction);
+            assert_eq!(instruction.display(Syntax::Octo).to_string(), octo);
+            assert_eq!(instruction.display(Syntax::Cowgod).to_string(), cowgod);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn disassembles_roms_with_labels() {
+        let code = [
+            0x22, 0x08, // call sub_208
+            0xF0, 0x00, 0x12, 0x34, // i := long 0x1234
+            0x12, 0x02, // jump label_202
+            0x00, 0xEE, // return
+            0xAB, // a byte left over
+        ];
+        let disassembly = disassemble(&code, 0x200);
+        assert_eq!(disassembly.lines().len(), 5);
+        assert_eq!(disassembly.lines()[1].item, Item::LongLoad(0x1234));
+        assert_eq!(disassembly.lines()[4].item, Item::Byte(0xAB));
+        assert_eq!(disassembly.label(0x208), Some("sub_208"));
+        assert_eq!(disassembly.label(0x202), Some("label_202"));
+        assert_eq!(disassembly.label(0x200), None);
+        assert_eq!(
+            disassembly.display(Syntax::Octo).to_string(),
+            "  sub_208                     # 200
+: label_202
+  i := long 0x1234            # 202
+  jump label_202              # 206
+: sub_208
+  return                      # 208
+  0xAB                        # 20A
+"
+        );
+        assert_eq!(
+            disassembly.display(Syntax::Cowgod).to_string(),
+            "  CALL sub_208                ; 200
+label_202:
+  LD I, LONG #1234            ; 202
+  JP label_202                ; 206
+sub_208:
+  RET                         ; 208
+  DB #AB                      ; 20A
+"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn assembles_octo_source() {
+        let program = assemble(
+            "
+            :const SPEED 3
+            :alias counter v3
+            # Right to left, as in Octo: 3 * (2 + 1).
+            :calc NINE { SPEED * 2 + 1 }
+            :macro add-to register amount { register += amount }
+
+            : main
+              counter := 0
+              loop
+                add-to counter SPEED
+                if counter == NINE then jump done
+                while counter != 30
+              again
+            : done
+              v4 := NINE
+              if v4 == 9 begin
+                v5 := 1
+              else
+                v5 := 2
+              end
+              i := sprite-data
+              sprite v0 v0 2
+              i := long sprite-data
+              subroutine
+            : halt
+              jump halt
+            : subroutine
+              return
+            : sprite-data
+              0b11110000 0x90
+              :byte { NINE - 1 }
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            program.code(),
+            &[
+                0x63, 0x00, 0x73, 0x03, 0x43, 0x09, 0x12, 0x0E, 0x43, 0x1E, 0x12, 0x0E, 0x12, 0x02,
+                0x64, 0x09, 0x34, 0x09, 0x12, 0x18, 0x65, 0x01, 0x12, 0x1A, 0x65, 0x02, 0xA2, 0x28,
+                0xD0, 0x02, 0xF0, 0x00, 0x02, 0x28, 0x22, 0x26, 0x12, 0x24, 0x00, 0xEE, 0xF0, 0x90,
+                0x08,
+            ][..]
+        );
+        assert_eq!(program.label("main"), Some(0x200));
+        assert_eq!(program.label("sprite-data"), Some(0x228));
+
+        let mut chip8 = Chip8::with_seed(1);
+        chip8.load_program(&program, DEFAULT_QUIRKS).unwrap();
+        for _ in 0..5 {
+            chip8.run_frame().unwrap();
+        }
+        assert_eq!(chip8.register(Register::V(3)), 9);
+        assert_eq!(chip8.register(Register::V(5)), 1);
+        assert_eq!(chip8.register(Register::I), 0x228);
+        assert_eq!(chip8.pc(), 0x224);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn assembled_roms_jump_to_main() {
+        let program = assemble(": helper return : main helper").unwrap();
+        assert_eq!(program.code(), &[0x12, 0x04, 0x00, 0xEE, 0x22, 0x02][..]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn reports_where_assembling_fails() {
+        let error = |source| {
+            let error = assemble(source).unwrap_err();
+            (error.line, error.column, error.message)
+        };
+        assert_eq!(
+            error("\n: main\n  v0 := 300"),
+            (3, 9, "300 doesn't fit in a byte".to_string())
+        );
+        assert_eq!(
+            error(": main jump nowhere"),
+            (1, 13, "'nowhere' is not defined".to_string())
+        );
+        assert_eq!(
+            error(": main loop"),
+            (1, 8, "'loop' is never closed".to_string())
+        );
+        assert_eq!(
+            error("v0 := 1"),
+            (1, 8, "the program has no 'main' label".to_string())
+        );
+        assert_eq!(error(": main : main").2, "'main' is already defined");
+        assert_eq!(error(": main v1 +- 1").2, "unknown register operator '+-'");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn rejects_jumps_and_calls_out_of_reach() {
+        // Puts `far` at 0x11A0, past what NNN can address.
+        let far = |code: &str| {
+            let padding = "0 ".repeat(4000);
+            assemble(&format!(": main\n{}\n: far\n{}", padding, code)).unwrap_err()
+        };
+        let error = far("loop v0 += 1 again");
+        assert_eq!((error.line, error.column), (4, 14));
+        assert_eq!(error.message, "address 0x11A0 is out of reach");
+        assert_eq!(far("far").message, "address 0x11A0 is out of reach");
+        assert_eq!(
+            far("if v0 == 0 begin v0 := 1 end").message,
+            "address 0x11A6 is out of reach"
+        );
+        assert_eq!(
+            assemble(&format!("jump main {}: main", "0 ".repeat(4000)))
+                .unwrap_err()
+                .message,
+            "address 0x11A4 is out of reach"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn disassembled_games_assemble_back() {
+        for &code in &[
+            TETRIS,
+            BRIX,
+            PONG,
+            PONG2,
+            INVADERS,
+            SCTEST,
+            BCTEST,
+            C8TEST,
+            SAMPLE,
+            OPCODE_TEST,
+        ] {
+            let source = format!(": main\n{}", disassemble(code, 0x200).display(Syntax::Octo));
+            assert_eq!(assemble(&source).unwrap().code(), code);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn caches_the_instructions_of_addressable_memory() {
+        let mut chip8 = Chip8::with_seed(1);
+        chip8
+            .load_rom(&[0x60, 0x05, 0x12, 0x00], DEFAULT_QUIRKS)
+            .unwrap();
+        assert_eq!(chip8.memory.decoded.len(), MEMORY_SIZE);
+        chip8.tick().unwrap();
+        assert_eq!(
+            chip8.memory.decoded[0x200],
+            Some((0x6005, Instruction::LoadByte { x: 0, byte: 5 }))
+        );
+        chip8.set_quirks(Quirks::xo_chip());
+        assert_eq!(chip8.memory.decoded.len(), chip8.memory().len());
+        assert_eq!(chip8.memory.decoded[0x200], None);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn executes_instructions_rewritten_after_they_ran() {
+        for store in &["save v1", "save v0 - v1"] {
+            let program = assemble(&format!(
+                "
+                : main
+                  v0 := 0x75
+                  v1 := 0x10
+                : target
+                  v5 += 1
+                  if v5 != 1 then jump halt
+                  # Rewrites the instruction above as v5 += 0x10.
+                  i := target
+                  {}
+                  jump target
+                : halt
+                  jump halt
+                ",
+                store
+            ))
+            .unwrap();
+            for &engine in &[Engine::Interpreter, Engine::Recompiler] {
+                let mut chip8 = Chip8::with_seed(1);
+                chip8.set_engine(engine);
+                chip8.load_program(&program, Quirks::xo_chip()).unwrap();
+                let start = chip8.save_state();
+                for _ in 0..3 {
+                    chip8.run_frame().unwrap();
+                }
+                assert_eq!(chip8.register(Register::V(5)), 0x11);
+
+                // Loading a state puts back the instructions it was saved with.
+                chip8.load_state(&start).unwrap();
+                for _ in 0..3 {
+                    chip8.run_frame().unwrap();
+                }
+                assert_eq!(chip8.register(Register::V(5)), 0x11);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn traces_what_instructions_do() {
+        let program = assemble(
+            "
+            : main
+              v0 := 5
+              i := 0x300
+              save v0
+              delay := v0
+              sprite v0 v0 1
+              v1 := key
+            ",
+        )
+        .unwrap();
+        let mut chip8 = Chip8::with_seed(1);
+        chip8.load_program(&program, DEFAULT_QUIRKS).unwrap();
+        let sink = RingBufferSink::new(64);
+        chip8.set_trace_sink(sink.clone());
+        chip8.set_trace_filter(TraceFilter::only(&[
+            TraceKind::RegisterWrite,
+            TraceKind::MemoryWrite,
+            TraceKind::Draw,
+            TraceKind::KeyWait,
+            TraceKind::TimerSet,
+        ]));
+        let start = chip8.save_state();
+        chip8.run_frame().unwrap();
+        let trace = |pc, event| Trace { pc, event };
+        assert_eq!(
+            sink.traces(),
+            [
+                trace(
+                    0x200,
+                    TraceEvent::RegisterWrite {
+                        register: Register::V(0),
+                        value: 5
+                    }
+                ),
+                trace(
+                    0x202,
+                    TraceEvent::RegisterWrite {
+                        register: Register::I,
+                        value: 0x300
+                    }
+                ),
+                trace(
+                    0x204,
+                    TraceEvent::MemoryWrite {
+                        address: 0x300,
+                        value: 5
+                    }
+                ),
+                trace(
+                    0x206,
+                    TraceEvent::TimerSet {
+                        timer: Register::Delay,
+                        value: 5
+                    }
+                ),
+                trace(
+                    0x208,
+                    TraceEvent::Draw {
+                        x: 5,
+                        y: 5,
+                        rows: 1,
+                        collision: false
+                    }
+                ),
+                trace(0x20A, TraceEvent::KeyWait { x: 1 }),
+            ]
+        );
+        assert_eq!(sink.traces()[4].to_string(), "0208 draw 1 rows at 5, 5");
+
+        // Every event is traced by default, and the key wait goes on for the rest
+        // of the frame.
+        sink.clear();
+        chip8.load_state(&start).unwrap();
+        chip8.set_trace_filter(TraceFilter::default());
+        chip8.run_frame().unwrap();
+        let traces = sink.traces();
+        assert_eq!(
+            traces[..2],
+            [
+                trace(0x200, TraceEvent::Fetch { opcode: 0x6005 }),
+                trace(
+                    0x200,
+                    TraceEvent::Execute(Instruction::LoadByte { x: 0, byte: 5 })
+                ),
+            ]
+        );
+        assert_eq!(traces.len(), 26);
+        assert_eq!(
+            traces[25],
+            trace(0x20A, TraceEvent::Execute(Instruction::WaitKey { x: 1 }))
+        );
+
+        // The ring buffer keeps the latest events.
+        let latest = RingBufferSink::new(2);
+        chip8.set_trace_sink(latest.clone());
+        chip8.run_frame().unwrap();
+        assert_eq!(
+            latest.traces(),
+            [
+                trace(0x20A, TraceEvent::Fetch { opcode: 0xF10A }),
+                trace(0x20A, TraceEvent::Execute(Instruction::WaitKey { x: 1 })),
+            ]
+        );
+        chip8.set_trace_sink(sink.clone());
+
+        // Events are filtered by the pc, and memory writes by the address written.
+        sink.clear();
+        chip8.load_state(&start).unwrap();
+        let mut filter = TraceFilter::only(&[TraceKind::MemoryWrite, TraceKind::TimerSet]);
+        filter.addresses = 0x204..0x208;
+        chip8.set_trace_filter(filter);
+        chip8.run_frame().unwrap();
+        assert_eq!(
+            sink.traces(),
+            [trace(
+                0x206,
+                TraceEvent::TimerSet {
+                    timer: Register::Delay,
+                    value: 5
+                }
+            )]
+        );
+
+        assert!(chip8.take_trace_sink().is_some());
+        sink.clear();
+        chip8.load_state(&start).unwrap();
+        chip8.run_frame().unwrap();
+        assert!(sink.traces().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn recompiler_executes_blocks_rewritten_after_they_ran() {
+        let program = assemble(
+            "
+            : main
+              v0 := 0x75
+              v1 := 0x10
+            : loop
+              v5 += 1
+              v6 += 1
+              if v6 != 3 then jump loop
+              # Rewrites the first instruction of the loop as v5 += 0x10.
+              i := loop
+              save v1
+              jump loop
+            ",
+        )
+        .unwrap();
+        let states: Vec<_> = [Engine::Interpreter, Engine::Recompiler]
+            .iter()
+            .map(|&engine| {
+                let mut chip8 = Chip8::with_seed(1);
+                chip8.set_engine(engine);
+                chip8.load_program(&program, Quirks::xo_chip()).unwrap();
+                for _ in 0..3 {
+                    chip8.run_frame().unwrap();
+                }
+                chip8.save_state()
+            })
+            .collect();
+        assert!(states[0] == states[1]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn recompiler_halts_like_the_interpreter() {
+        let program = assemble(
+            "
+            : main
+              v0 := 1
+              v1 := 2
+              return
+            ",
+        )
+        .unwrap();
+        for &engine in &[Engine::Interpreter, Engine::Recompiler] {
+            let mut chip8 = Chip8::with_seed(1);
+            chip8.set_engine(engine);
+            chip8.load_program(&program, Quirks::xo_chip()).unwrap();
+            assert_eq!(
+                chip8.run_frame(),
+                Err(Chip8Error::StackUnderflow {
+                    pc: 0x204,
+                    opcode: 0x00EE
+                })
+            );
+            assert_eq!(chip8.register(Register::V(1)), 2);
+            assert!(chip8.is_halted());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn recompiler_matches_the_interpreter() {
+        for title in &[
+            "tetris",
+            "brix",
+            "pong",
+            "pong2",
+            "invaders",
+            "sctest",
+            "bctest",
+            "c8test",
+            "sample",
+            "opcode_test",
+        ] {
+            for &speed in &[600, 20_000] {
+                let mut interpreter = Chip8::with_seed(7);
+                let mut recompiler = Chip8::with_seed(7);
+                recompiler.set_engine(Engine::Recompiler);
+                for chip8 in &mut [&mut interpreter, &mut recompiler] {
+                    chip8.load_game(title, None).unwrap();
+                    chip8.set_cpu_speed(speed);
+                }
+                for frame in 0..200 {
+                    // Hold a different key every so often, so games get past their
+                    // title screens and move around.
+                    let key = frame / 20 % 16;
+                    let results: Vec<_> = [&mut interpreter, &mut recompiler]
+                        .iter_mut()
+                        .map(|chip8| {
+                            if frame % 20 < 10 {
+                                chip8.press_key(key).unwrap();
+                            } else {
+                                chip8.release_key(key).unwrap();
+                            }
+                            chip8.run_frame()
+                        })
+                        .collect();
+                    assert_eq!(results[0], results[1]);
+                    assert!(
+                        interpreter.save_state() == recompiler.save_state(),
+                        "{} at {} instructions per second diverged at frame {}",
+                        title,
+                        speed,
+                        frame
+                    );
+                }
+            }
+        }
+    }
+}
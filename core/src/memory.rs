@@ -0,0 +1,246 @@
+#[cfg(feature = "std")]
+use core::cell::Cell;
+#[cfg(feature = "std")]
+use core::ops::Range;
+use core::ops::{Deref, DerefMut};
+
+#[cfg(feature = "std")]
+use crate::debugger::Access;
+use crate::error::Fault;
+use crate::instruction::Instruction;
+
+pub const MEMORY_SIZE: usize = 4096;
+pub const EXTENDED_MEMORY_SIZE: usize = 0x10000;
+/// The most memory a machine has: the 64 KiB XO-CHIP can address with the
+/// `xo-chip` feature, the 4 KiB of CHIP-8 and SUPER-CHIP without it.
+#[cfg(feature = "xo-chip")]
+pub const MEMORY_CAPACITY: usize = EXTENDED_MEMORY_SIZE;
+#[cfg(not(feature = "xo-chip"))]
+pub const MEMORY_CAPACITY: usize = MEMORY_SIZE;
+
+/// What the interpreter does when a ROM reaches outside of memory or the stack.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MemoryPolicy {
+    /// Wrap the address around, like the original hardware did.
+    Wrap,
+    /// Halt the machine with a `Chip8Error`.
+    #[default]
+    Fault,
+    /// Log the access through the `log` facade and carry on as if wrapping.
+    /// Without the `log` feature nothing is logged, so this acts like `Wrap`.
+    Log,
+}
+
+/// The interpreter's RAM. Every access made on behalf of a ROM goes through
+/// `read`/`write`/`address`, so out of range accesses are handled in one place
+/// according to `policy`. The raw bytes are still reachable through `Deref`
+/// for the interpreter's own bookkeeping (fonts and loading ROMs).
+///
+/// There is always room for `MEMORY_CAPACITY` bytes, but only the first `size`
+/// are accessible to ROMs.
+///
+/// With `std`, the instructions fetched are decoded once and kept alongside the
+/// bytes with their opcodes, so fetching them again reads neither. That takes 6
+/// bytes of heap for every byte ROMs can address: 24 KiB for 4 KiB of memory, and
+/// 384 KiB once the extended_memory quirk gives them 64 KiB. Every write clears
+/// what it changes, so ROMs that modify themselves still execute what they wrote.
+/// Writes to the instructions the recompiler compiled are also noted for it.
+pub(crate) struct Memory {
+    bytes: [u8; MEMORY_CAPACITY],
+    // Changed with `set_size`, which keeps the decoded instructions right.
+    pub(crate) size: usize,
+    pub(crate) policy: MemoryPolicy,
+    // The addresses the debugger watches, and the first watched access made since
+    // it last looked.
+    #[cfg(feature = "std")]
+    pub(crate) watches: Vec<(Range<usize>, Access)>,
+    #[cfg(feature = "std")]
+    pub(crate) watch_hit: Cell<Option<(usize, Access)>>,
+    // The opcode and instruction starting at each address below `size`, once it
+    // has been fetched.
+    #[cfg(feature = "std")]
+    pub(crate) decoded: Vec<Option<(u16, Instruction)>>,
+    // The bytes of the instructions the recompiler compiled, empty unless it is on.
+    #[cfg(feature = "std")]
+    pub(crate) compiled: Vec<bool>,
+    // Writes to compiled bytes, and whether all of memory was replaced, since the
+    // recompiler last looked.
+    #[cfg(feature = "std")]
+    pub(crate) code_writes: Vec<usize>,
+    #[cfg(feature = "std")]
+    pub(crate) code_replaced: bool,
+    // The writes made by the instruction being executed, while they are traced.
+    #[cfg(feature = "std")]
+    pub(crate) traced_writes: Option<Vec<(usize, u8)>>,
+}
+
+impl Memory {
+    pub(crate) fn new() -> Self {
+        Memory {
+            bytes: [0; MEMORY_CAPACITY],
+            size: MEMORY_SIZE,
+            policy: MemoryPolicy::default(),
+            #[cfg(feature = "std")]
+            watches: Vec::new(),
+            #[cfg(feature = "std")]
+            watch_hit: Cell::new(None),
+            #[cfg(feature = "std")]
+            decoded: vec![None; MEMORY_SIZE],
+            #[cfg(feature = "std")]
+            compiled: Vec::new(),
+            #[cfg(feature = "std")]
+            code_writes: Vec::new(),
+            #[cfg(feature = "std")]
+            code_replaced: false,
+            #[cfg(feature = "std")]
+            traced_writes: None,
+        }
+    }
+
+    pub(crate) fn set_size(&mut self, size: usize) {
+        if size != self.size {
+            self.size = size;
+            // The instruction at the end of memory wraps around to different bytes.
+            #[cfg(feature = "std")]
+            {
+                self.decoded = vec![None; size];
+                self.code_replaced = true;
+            }
+        }
+    }
+
+    /// Reports `fault` according to the policy. Returns `Ok` when execution should
+    /// carry on with a wrapped value.
+    pub(crate) fn violation(&self, fault: Fault) -> Result<(), Fault> {
+        match self.policy {
+            MemoryPolicy::Wrap => Ok(()),
+            MemoryPolicy::Fault => Err(fault),
+            MemoryPolicy::Log => {
+                log!("Ignoring memory violation: {:?}", fault);
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks that `address` is inside of memory, wrapping it if the policy allows it.
+    pub(crate) fn address(&self, address: usize) -> Result<usize, Fault> {
+        if address < self.size {
+            return Ok(address);
+        }
+        self.violation(Fault::MemoryOutOfBounds(address))?;
+        Ok(address % self.size)
+    }
+
+    pub(crate) fn read(&self, address: usize) -> Result<u8, Fault> {
+        let address = self.address(address)?;
+        #[cfg(feature = "std")]
+        self.watch(address, Access::Read);
+        Ok(self.bytes[address])
+    }
+
+    /// Reads a byte of an instruction, which doesn't count as reading memory for
+    /// watchpoints.
+    pub(crate) fn read_code(&self, address: usize) -> Result<u8, Fault> {
+        Ok(self.bytes[self.address(address)?])
+    }
+
+    pub(crate) fn write(&mut self, address: usize, value: u8) -> Result<(), Fault> {
+        let address = self.address(address)?;
+        #[cfg(feature = "std")]
+        {
+            self.watch(address, Access::Write);
+            // The instructions starting at the byte and at the one before it.
+            self.decoded[address] = None;
+            if address > 0 {
+                self.decoded[address - 1] = None;
+            }
+            if self.compiled.get(address) == Some(&true) {
+                self.code_writes.push(address);
+            }
+            if let Some(writes) = &mut self.traced_writes {
+                writes.push((address, value));
+            }
+        }
+        self.bytes[address] = value;
+        Ok(())
+    }
+
+    /// Copies `bytes` to `start` on the interpreter's behalf, forgetting only the
+    /// instructions it changes, unlike writes through `DerefMut`.
+    pub(crate) fn copy_in(&mut self, start: usize, bytes: &[u8]) {
+        for (address, &value) in (start..).zip(bytes) {
+            if self.bytes[address] == value {
+                continue;
+            }
+            #[cfg(feature = "std")]
+            {
+                self.decoded[address] = None;
+                if address > 0 {
+                    self.decoded[address - 1] = None;
+                }
+                if self.compiled.get(address) == Some(&true) {
+                    self.code_writes.push(address);
+                }
+            }
+            self.bytes[address] = value;
+        }
+    }
+
+    /// Fetches the opcode at `address`, which is inside memory, and decodes it. With
+    /// `std`, instructions fetched before are neither read nor decoded again.
+    pub(crate) fn fetch(&mut self, address: usize) -> Result<(u16, Instruction), Fault> {
+        #[cfg(feature = "std")]
+        if let Some(decoded) = self.decoded[address] {
+            return Ok(decoded);
+        }
+        let opcode = (self.read_code(address)? as u16) << 8 | self.read_code(address + 1)? as u16;
+        let instruction = Instruction::decode(opcode);
+        // The last byte's instruction wraps around, writes to the first byte
+        // wouldn't clear it.
+        #[cfg(feature = "std")]
+        if address + 1 < self.size {
+            self.decoded[address] = Some((opcode, instruction));
+        }
+        Ok((opcode, instruction))
+    }
+
+    #[cfg(feature = "std")]
+    fn forget_decoded(&mut self) {
+        for instruction in self.decoded.iter_mut() {
+            *instruction = None;
+        }
+        self.code_replaced = true;
+    }
+
+    #[cfg(feature = "std")]
+    fn watch(&self, address: usize, access: Access) {
+        if self.watches.is_empty() || self.watch_hit.get().is_some() {
+            return;
+        }
+        let watched = self
+            .watches
+            .iter()
+            .any(|(range, watched)| range.contains(&address) && watched.includes(access));
+        if watched {
+            self.watch_hit.set(Some((address, access)));
+        }
+    }
+}
+
+impl Deref for Memory {
+    type Target = [u8; MEMORY_CAPACITY];
+
+    fn deref(&self) -> &Self::Target {
+        &self.bytes
+    }
+}
+
+// The interpreter's own writes may go anywhere, so they forget every decoded
+// instruction. Those it makes while ROMs run go through `copy_in` instead.
+impl DerefMut for Memory {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        #[cfg(feature = "std")]
+        self.forget_decoded();
+        &mut self.bytes
+    }
+}
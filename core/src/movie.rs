@@ -0,0 +1,376 @@
+//! Movies: the keys pressed while a ROM ran, which play the run back exactly.
+//!
+//! A movie starts from a save state and lists every press and release with the
+//! frame and the instruction within the frame it came before. Playing it back
+//! loads the state, restores the host settings that decide how many instructions
+//! make up a frame, and applies each event at the same point of the run.
+//!
+//! The file is the `C8MV` magic, the format version, the header, the start state,
+//! the events and a CRC-32 of everything before it. Numbers are little endian.
+use crate::error::MovieError;
+use crate::state::{crc32, quirks_from_bits, quirks_to_bits, Reader};
+use crate::{Chip8, MemoryPolicy, Quirks, TimingMode};
+
+const MAGIC: [u8; 4] = *b"C8MV";
+/// The version of the format `Movie::to_bytes` writes.
+pub const MOVIE_VERSION: u16 = 1;
+const CHECKSUM_SIZE: usize = 4;
+
+/// A key pressed or released during a recording.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputEvent {
+    /// The frame, counted from the start of the recording.
+    pub frame: u32,
+    /// How many instructions of the frame had run.
+    pub instruction: u32,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+impl InputEvent {
+    fn position(&self) -> (u32, u32) {
+        (self.frame, self.instruction)
+    }
+}
+
+/// A recording made with `Chip8::start_recording`, to be played back with
+/// `Chip8::play_movie`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    rom_hash: u32,
+    // The seed and quirks are in the start state too. They are repeated here so
+    // that a movie can be described without loading it.
+    random_seed: u32,
+    quirks: Quirks,
+    cpu_speed: u32,
+    timing_mode: TimingMode,
+    memory_policy: MemoryPolicy,
+    vip_display_mirror: bool,
+    // How far the clock had got into spreading instructions over frames.
+    leftover_instructions: u32,
+    cycle_budget: i32,
+    start: Vec<u8>,
+    events: Vec<InputEvent>,
+    frames: u32,
+}
+
+impl Movie {
+    /// The CRC-32 of the ROM the movie was recorded with.
+    pub fn rom_hash(&self) -> u32 {
+        self.rom_hash
+    }
+
+    /// The seed of the random number generator when the recording started.
+    pub fn random_seed(&self) -> u32 {
+        self.random_seed
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// How many frames the movie lasts.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    pub fn events(&self) -> &[InputEvent] {
+        &self.events
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(64 + self.start.len() + 9 * self.events.len());
+        out.extend_from_slice(&MAGIC);
+        out.extend_from_slice(&MOVIE_VERSION.to_le_bytes());
+        out.extend_from_slice(&self.rom_hash.to_le_bytes());
+        out.extend_from_slice(&self.random_seed.to_le_bytes());
+        out.push(quirks_to_bits(self.quirks));
+        out.extend_from_slice(&self.cpu_speed.to_le_bytes());
+        out.push(match self.timing_mode {
+            TimingMode::Fixed => 0,
+            TimingMode::CosmacVip => 1,
+        });
+        out.push(match self.memory_policy {
+            MemoryPolicy::Wrap => 0,
+            MemoryPolicy::Fault => 1,
+            MemoryPolicy::Log => 2,
+        });
+        out.push(self.vip_display_mirror as u8);
+        out.extend_from_slice(&self.leftover_instructions.to_le_bytes());
+        out.extend_from_slice(&self.cycle_budget.to_le_bytes());
+        out.extend_from_slice(&self.frames.to_le_bytes());
+        out.extend_from_slice(&(self.start.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.start);
+        out.extend_from_slice(&(self.events.len() as u32).to_le_bytes());
+        for event in &self.events {
+            out.extend_from_slice(&event.frame.to_le_bytes());
+            out.extend_from_slice(&event.instruction.to_le_bytes());
+            out.push(event.key | (event.pressed as u8) << 7);
+        }
+        let checksum = crc32(&out);
+        out.extend_from_slice(&checksum.to_le_bytes());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Movie, MovieError> {
+        if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+            return Err(MovieError::NotAMovie);
+        }
+        let mut reader = Reader {
+            bytes: &bytes[MAGIC.len()..],
+        };
+        let version = reader.u16()?;
+        if version != MOVIE_VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        if bytes.len() < MAGIC.len() + 2 + CHECKSUM_SIZE {
+            return Err(MovieError::Truncated);
+        }
+        let (contents, checksum) = bytes.split_at(bytes.len() - CHECKSUM_SIZE);
+        reader.bytes = &contents[MAGIC.len() + 2..];
+        let movie = Movie::read_v1(&mut reader);
+        // A movie cut short fails to read before the checksum could tell.
+        if crc32(contents).to_le_bytes() != checksum {
+            return match movie {
+                Err(MovieError::Truncated) => Err(MovieError::Truncated),
+                _ => Err(MovieError::ChecksumMismatch),
+            };
+        }
+        let movie = movie?;
+        if !reader.bytes.is_empty() {
+            return Err(MovieError::InvalidValue("movie length"));
+        }
+        Ok(movie)
+    }
+
+    fn read_v1(reader: &mut Reader) -> Result<Movie, MovieError> {
+        let rom_hash = reader.u32()?;
+        let random_seed = reader.u32()?;
+        let quirks = quirks_from_bits(reader.u8()?);
+        let cpu_speed = reader.u32()?;
+        let timing_mode = match reader.u8()? {
+            0 => TimingMode::Fixed,
+            1 => TimingMode::CosmacVip,
+            _ => return Err(MovieError::InvalidValue("timing mode")),
+        };
+        let memory_policy = match reader.u8()? {
+            0 => MemoryPolicy::Wrap,
+            1 => MemoryPolicy::Fault,
+            2 => MemoryPolicy::Log,
+            _ => return Err(MovieError::InvalidValue("memory policy")),
+        };
+        let vip_display_mirror = reader.bool("display mirror flag")?;
+        let leftover_instructions = reader.u32()?;
+        let cycle_budget = reader.u32()? as i32;
+        let frames = reader.u32()?;
+        let start_size = reader.u32()? as usize;
+        let start = reader.bytes(start_size)?.to_vec();
+        let count = reader.u32()? as usize;
+        let mut events: Vec<InputEvent> = Vec::with_capacity(count.min(reader.bytes.len() / 9));
+        for _ in 0..count {
+            let frame = reader.u32()?;
+            let instruction = reader.u32()?;
+            let key = reader.u8()?;
+            let event = InputEvent {
+                frame,
+                instruction,
+                key: key & 0x7F,
+                pressed: key & 0x80 != 0,
+            };
+            if event.key >= 16 {
+                return Err(MovieError::InvalidValue("key"));
+            }
+            if event.frame > frames
+                || events
+                    .last()
+                    .is_some_and(|last| last.position() > event.position())
+            {
+                return Err(MovieError::InvalidValue("event position"));
+            }
+            events.push(event);
+        }
+        Ok(Movie {
+            rom_hash,
+            random_seed,
+            quirks,
+            cpu_speed,
+            timing_mode,
+            memory_policy,
+            vip_display_mirror,
+            leftover_instructions,
+            cycle_budget,
+            start,
+            events,
+            frames,
+        })
+    }
+}
+
+/// A movie being recorded or played back, and how far into it the machine is.
+pub(crate) struct Session {
+    movie: Movie,
+    playing: bool,
+    // The first event that hasn't been played back yet.
+    next_event: usize,
+    frame: u32,
+    instruction: u32,
+}
+
+impl<R> Chip8<R> {
+    /// Counts an instruction about to be executed, after playing back the events
+    /// that came before it.
+    pub(crate) fn movie_instruction(&mut self) {
+        self.play_due_events();
+        if let Some(session) = &mut self.movie {
+            session.instruction += 1;
+        }
+    }
+
+    /// Counts a frame that is ending, and ends playback after the movie's last one.
+    pub(crate) fn movie_frame(&mut self) {
+        self.play_due_events();
+        let finished = match &mut self.movie {
+            Some(session) => {
+                session.frame += 1;
+                session.instruction = 0;
+                session.playing && session.frame >= session.movie.frames
+            }
+            None => false,
+        };
+        if finished {
+            self.finish_playback();
+        }
+    }
+
+    /// Remembers a key press or release while recording. Returns `false` while
+    /// playing back, when input comes from the movie rather than the host.
+    pub(crate) fn movie_key(&mut self, key: usize, pressed: bool) -> bool {
+        let session = match &mut self.movie {
+            Some(session) => session,
+            None => return true,
+        };
+        if session.playing {
+            return false;
+        }
+        if self.keypad[key] != pressed {
+            session.movie.events.push(InputEvent {
+                frame: session.frame,
+                instruction: session.instruction,
+                key: key as u8,
+                pressed,
+            });
+        }
+        true
+    }
+
+    fn play_due_events(&mut self) {
+        let session = match &mut self.movie {
+            Some(session) if session.playing => session,
+            _ => return,
+        };
+        let position = (session.frame, session.instruction);
+        while let Some(event) = session.movie.events.get(session.next_event) {
+            if event.position() > position {
+                break;
+            }
+            self.keypad[event.key as usize] = event.pressed;
+            session.next_event += 1;
+        }
+    }
+
+    /// Applies the events recorded after the last frame, so the machine ends up
+    /// where the recording stopped, and hands input back to the host.
+    fn finish_playback(&mut self) {
+        if let Some(session) = self.movie.take() {
+            for event in &session.movie.events[session.next_event..] {
+                self.keypad[event.key as usize] = event.pressed;
+            }
+        }
+    }
+
+    /// Whether a movie is being recorded.
+    pub fn is_recording(&self) -> bool {
+        self.movie.as_ref().is_some_and(|session| !session.playing)
+    }
+
+    /// Whether a movie is being played back. Host input is ignored until it ends.
+    pub fn is_playing_movie(&self) -> bool {
+        self.movie.as_ref().is_some_and(|session| session.playing)
+    }
+
+    /// Ends playback where it is, handing input back to the host.
+    pub fn stop_playback(&mut self) {
+        if self.is_playing_movie() {
+            self.movie = None;
+        }
+    }
+}
+
+impl Chip8 {
+    /// Starts recording a movie of the keys pressed from now on, replacing any movie
+    /// being recorded or played back. Playback runs frames the way the recording
+    /// did: hosts that step instructions themselves need to do so again.
+    pub fn start_recording(&mut self) {
+        self.movie = Some(Session {
+            movie: Movie {
+                rom_hash: self.rom_hash,
+                random_seed: self.random_seed(),
+                quirks: self.quirks,
+                cpu_speed: self.clock.instructions_per_second,
+                timing_mode: self.clock.mode,
+                memory_policy: self.memory.policy,
+                vip_display_mirror: self.vip_display_mirror,
+                leftover_instructions: self.clock.leftover_instructions,
+                cycle_budget: self.clock.cycle_budget,
+                start: self.save_state(),
+                events: Vec::new(),
+                frames: 0,
+            },
+            playing: false,
+            next_event: 0,
+            frame: 0,
+            instruction: 0,
+        });
+    }
+
+    /// Ends the recording and returns it, `None` if nothing was being recorded.
+    pub fn stop_recording(&mut self) -> Option<Movie> {
+        if !self.is_recording() {
+            return None;
+        }
+        self.movie.take().map(|session| {
+            let mut movie = session.movie;
+            movie.frames = session.frame;
+            movie
+        })
+    }
+
+    /// Puts the machine back where `movie` started and plays it back from there. The
+    /// ROM it was recorded with has to be loaded.
+    pub fn play_movie(&mut self, movie: &Movie) -> Result<(), MovieError> {
+        if movie.rom_hash != self.rom_hash {
+            return Err(MovieError::RomMismatch {
+                expected: movie.rom_hash,
+                found: self.rom_hash,
+            });
+        }
+        self.load_state(&movie.start)?;
+        self.set_cpu_speed(movie.cpu_speed);
+        self.set_timing_mode(movie.timing_mode);
+        self.set_memory_policy(movie.memory_policy);
+        self.set_vip_display_mirror(movie.vip_display_mirror);
+        self.clock.leftover_instructions = movie.leftover_instructions;
+        self.clock.cycle_budget = movie.cycle_budget;
+        self.movie = Some(Session {
+            movie: movie.clone(),
+            playing: true,
+            next_event: 0,
+            frame: 0,
+            instruction: 0,
+        });
+        if movie.frames == 0 {
+            self.finish_playback();
+        }
+        Ok(())
+    }
+}
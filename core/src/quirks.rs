@@ -0,0 +1,122 @@
+use core::str::FromStr;
+
+use crate::error::HostError;
+
+/// The well known CHIP-8 interpreters whose behaviour ROMs tend to depend on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuirkProfile {
+    CosmacVip,
+    Chip48,
+    SuperChipLegacy,
+    SuperChipModern,
+    XoChip,
+}
+
+/// Behaviours that differ between CHIP-8 interpreters. Every opcode handler whose
+/// semantics depend on the platform consults these instead of hardcoding one of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8xy1, 8xy2 and 8xy3 reset VF to 0.
+    pub vf_reset: bool,
+    /// 8xy6 and 8xyE shift Vx in place instead of loading the shifted Vy into Vx.
+    pub shift: bool,
+    /// Fx55 and Fx65 leave I untouched instead of advancing it past the last register.
+    pub load_store: bool,
+    /// Sprites are clipped at the edges of the screen instead of wrapping around.
+    pub clip: bool,
+    /// Bnnn behaves like Bxnn: it jumps to xnn + Vx instead of nnn + V0.
+    pub jump: bool,
+    /// Dxyn waits for the display interrupt, so at most one sprite is drawn per frame.
+    pub display_wait: bool,
+    /// Fx1E sets VF when I overflows past 0xFFF, like the Amiga interpreter.
+    pub index_overflow: bool,
+    /// ROMs can address XO-CHIP's 64 KiB of memory instead of 4 KiB.
+    pub extended_memory: bool,
+}
+
+impl Quirks {
+    pub fn from_profile(profile: QuirkProfile) -> Quirks {
+        match profile {
+            QuirkProfile::CosmacVip => Quirks::cosmac_vip(),
+            QuirkProfile::Chip48 => Quirks::chip48(),
+            QuirkProfile::SuperChipLegacy => Quirks::super_chip_legacy(),
+            QuirkProfile::SuperChipModern => Quirks::super_chip_modern(),
+            QuirkProfile::XoChip => Quirks::xo_chip(),
+        }
+    }
+
+    pub const fn cosmac_vip() -> Self {
+        Quirks {
+            vf_reset: true,
+            shift: false,
+            load_store: false,
+            clip: true,
+            jump: false,
+            display_wait: true,
+            index_overflow: false,
+            extended_memory: false,
+        }
+    }
+
+    pub const fn chip48() -> Self {
+        Quirks {
+            vf_reset: false,
+            shift: true,
+            load_store: true,
+            clip: true,
+            jump: true,
+            display_wait: false,
+            index_overflow: false,
+            extended_memory: false,
+        }
+    }
+
+    /// SUPER-CHIP 1.1 as it ran on the HP48 calculators.
+    pub const fn super_chip_legacy() -> Self {
+        Quirks {
+            display_wait: true,
+            ..Quirks::chip48()
+        }
+    }
+
+    /// SUPER-CHIP as implemented by modern interpreters such as Octo.
+    pub const fn super_chip_modern() -> Self {
+        Quirks::chip48()
+    }
+
+    pub const fn xo_chip() -> Self {
+        Quirks {
+            vf_reset: false,
+            shift: false,
+            load_store: false,
+            clip: false,
+            jump: false,
+            display_wait: false,
+            index_overflow: false,
+            extended_memory: true,
+        }
+    }
+}
+
+/// Parses the names hosts take profiles by: `cosmac-vip`, `chip48`, `schip-legacy`,
+/// `schip-modern` and `xo-chip`.
+impl FromStr for QuirkProfile {
+    type Err = HostError;
+
+    fn from_str(name: &str) -> Result<Self, HostError> {
+        match name {
+            "cosmac-vip" => Ok(QuirkProfile::CosmacVip),
+            "chip48" => Ok(QuirkProfile::Chip48),
+            "schip-legacy" => Ok(QuirkProfile::SuperChipLegacy),
+            "schip-modern" => Ok(QuirkProfile::SuperChipModern),
+            "xo-chip" => Ok(QuirkProfile::XoChip),
+            _ => Err(HostError::UnknownQuirkProfile),
+        }
+    }
+}
+
+impl From<QuirkProfile> for Quirks {
+    fn from(profile: QuirkProfile) -> Self {
+        Quirks::from_profile(profile)
+    }
+}
@@ -0,0 +1,138 @@
+/// Where CXNN gets its random numbers from.
+pub trait RandomSource {
+    /// Returns the next random byte. `memory` is the interpreter's RAM, for sources
+    /// that emulate an interpreter which derived its numbers from it.
+    fn next_byte(&mut self, memory: &[u8]) -> u8;
+
+    /// Called on every 60 Hz timer tick.
+    fn frame(&mut self) {}
+}
+
+/// A xorshift generator: fast, tiny and reproducible from its seed.
+pub struct SeededRandom {
+    pub(crate) seed: u32,
+    pub(crate) state: u32,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u32) -> Self {
+        // xorshift gets stuck on 0, so that seed is remapped.
+        SeededRandom {
+            seed,
+            state: if seed == 0 { 0x9E37_79B9 } else { seed },
+        }
+    }
+
+    /// The seed the generator was created from, which reproduces its numbers.
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn next_byte(&mut self, _memory: &[u8]) -> u8 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state >> 24) as u8
+    }
+}
+
+/// Modelled on the COSMAC VIP interpreter, which kept a pointer in its R9 register.
+/// The display interrupt bumps its low byte every frame. CXNN bumps it again, adds the
+/// byte it points at to its high byte and uses the result, so the numbers depend on
+/// timing and on the contents of memory.
+pub struct CosmacVipRandom {
+    pub(crate) seed: u16,
+    pub(crate) r9: u16,
+}
+
+impl CosmacVipRandom {
+    pub fn new(seed: u16) -> Self {
+        CosmacVipRandom { seed, r9: seed }
+    }
+
+    /// The value R9 started out with.
+    pub fn seed(&self) -> u16 {
+        self.seed
+    }
+
+    fn increment_low_byte(&mut self) {
+        self.r9 = (self.r9 & 0xFF00) | (self.r9 as u8).wrapping_add(1) as u16;
+    }
+}
+
+impl RandomSource for CosmacVipRandom {
+    fn next_byte(&mut self, memory: &[u8]) -> u8 {
+        self.increment_low_byte();
+        let byte = memory[self.r9 as usize % memory.len()];
+        let random = ((self.r9 >> 8) as u8).wrapping_add(byte);
+        self.r9 = (random as u16) << 8 | (self.r9 & 0x00FF);
+        random
+    }
+
+    fn frame(&mut self) {
+        self.increment_low_byte();
+    }
+}
+
+/// Plays back a fixed sequence of numbers, starting over once it runs out.
+/// Meant for tests that need to know what CXNN will produce.
+pub struct ScriptedRandom<'a> {
+    values: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ScriptedRandom<'a> {
+    pub fn new(values: &'a [u8]) -> Self {
+        assert!(
+            !values.is_empty(),
+            "a scripted sequence needs at least one value"
+        );
+        ScriptedRandom {
+            values,
+            position: 0,
+        }
+    }
+}
+
+impl RandomSource for ScriptedRandom<'_> {
+    fn next_byte(&mut self, _memory: &[u8]) -> u8 {
+        let value = self.values[self.position];
+        self.position = (self.position + 1) % self.values.len();
+        value
+    }
+}
+
+/// The generators `Chip8` can switch between without knowing about the type of
+/// its random source, which is what it uses unless it is given another one.
+pub enum BuiltinRandom {
+    Seeded(SeededRandom),
+    CosmacVip(CosmacVipRandom),
+}
+
+impl BuiltinRandom {
+    /// The seed the current generator was created from.
+    pub fn seed(&self) -> u32 {
+        match self {
+            BuiltinRandom::Seeded(random) => random.seed(),
+            BuiltinRandom::CosmacVip(random) => random.seed() as u32,
+        }
+    }
+}
+
+impl RandomSource for BuiltinRandom {
+    fn next_byte(&mut self, memory: &[u8]) -> u8 {
+        match self {
+            BuiltinRandom::Seeded(random) => random.next_byte(memory),
+            BuiltinRandom::CosmacVip(random) => random.next_byte(memory),
+        }
+    }
+
+    fn frame(&mut self) {
+        match self {
+            BuiltinRandom::Seeded(random) => random.frame(),
+            BuiltinRandom::CosmacVip(random) => random.frame(),
+        }
+    }
+}
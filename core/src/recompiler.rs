@@ -0,0 +1,243 @@
+//! The recompiler: an execution engine for running ROMs far faster than real time,
+//! e.g. to fast forward or to train agents on them.
+//!
+//! Starting from the pc, it gathers the instructions up to the next jump, call or
+//! return into a block, decoded once into a compact list of operations. Blocks are
+//! kept in a table indexed by address, so each one leads straight to the next. A
+//! block runs its operations back to back with the interpreter's own handlers,
+//! without the fetching, decoding and bookkeeping the interpreter does around every
+//! instruction, and leaves early when one of them goes elsewhere than the next, such
+//! as a skip that is taken.
+//!
+//! When a ROM writes over code that was compiled, every block is thrown away and
+//! the instructions it wrote are interpreted from then on. The interpreter also
+//! takes over whenever something needs to see every instruction: COSMAC VIP timing,
+//! movies, the debugger and tracing.
+use crate::clock::TimingMode;
+use crate::memory::{Memory, MEMORY_CAPACITY};
+use crate::{Chip8, Chip8Error, Instruction, RandomSource};
+
+// Long enough for the straight runs of code ROMs have, short enough that compiling
+// code that is never reached doesn't cost much.
+const MAX_BLOCK_LENGTH: usize = 64;
+
+/// How instructions are executed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Engine {
+    /// One instruction at a time, the reference every other engine is checked
+    /// against.
+    #[default]
+    Interpreter,
+    /// Runs blocks of instructions compiled ahead of time, falling back to the
+    /// interpreter where it has to.
+    Recompiler,
+}
+
+#[derive(Clone, Copy)]
+struct Op {
+    instruction: Instruction,
+    opcode: u16,
+    // 4 for the XO-CHIP F000 NNNN, 2 otherwise.
+    size: u8,
+}
+
+pub(crate) struct Recompiler {
+    blocks: Vec<Vec<Op>>,
+    // The index in `blocks` plus one of the block starting at each address, 0 if none.
+    block_at: Vec<u32>,
+    // The instructions a ROM wrote over, which are left to the interpreter.
+    interpreted: Vec<bool>,
+}
+
+impl Recompiler {
+    fn new() -> Self {
+        Recompiler {
+            blocks: Vec::new(),
+            block_at: vec![0; MEMORY_CAPACITY],
+            interpreted: vec![false; MEMORY_CAPACITY],
+        }
+    }
+
+    fn forget_blocks(&mut self, memory: &mut Memory) {
+        self.blocks.clear();
+        for block in self.block_at.iter_mut() {
+            *block = 0;
+        }
+        for compiled in memory.compiled.iter_mut() {
+            *compiled = false;
+        }
+    }
+
+    /// Catches up with what happened to memory since the last block ran.
+    fn sync(&mut self, memory: &mut Memory) {
+        if memory.code_replaced {
+            memory.code_replaced = false;
+            memory.code_writes.clear();
+            for interpreted in self.interpreted.iter_mut() {
+                *interpreted = false;
+            }
+            self.forget_blocks(memory);
+        } else if !memory.code_writes.is_empty() {
+            for address in memory.code_writes.drain(..) {
+                // The instructions starting at the byte and at the one before it.
+                self.interpreted[address] = true;
+                if address > 0 {
+                    self.interpreted[address - 1] = true;
+                }
+            }
+            self.forget_blocks(memory);
+        }
+    }
+
+    /// The block starting at `start`, compiling it if it hasn't been. `None` if the
+    /// instruction there has to be interpreted.
+    fn block(&mut self, memory: &mut Memory, start: usize) -> Option<usize> {
+        if let Some(index) = self.block_at[start].checked_sub(1) {
+            return Some(index as usize);
+        }
+        let mut ops = Vec::new();
+        let mut address = start;
+        while ops.len() < MAX_BLOCK_LENGTH {
+            // Instructions that wrap around the end of memory are left to the
+            // interpreter, which knows what to do about them.
+            if address + 1 >= memory.size || self.interpreted[address] {
+                break;
+            }
+            let (opcode, instruction) = match memory.fetch(address) {
+                Ok(fetched) => fetched,
+                Err(_) => break,
+            };
+            let size = match instruction {
+                Instruction::Unknown(_) => break,
+                Instruction::LoadLongI if address + 3 >= memory.size => break,
+                Instruction::LoadLongI => 4,
+                _ => 2,
+            };
+            for compiled in &mut memory.compiled[address..address + size] {
+                *compiled = true;
+            }
+            ops.push(Op {
+                instruction,
+                opcode,
+                size: size as u8,
+            });
+            address += size;
+            if ends_block(instruction) {
+                break;
+            }
+        }
+        if ops.is_empty() {
+            return None;
+        }
+        self.blocks.push(ops);
+        self.block_at[start] = self.blocks.len() as u32;
+        Some(self.blocks.len() - 1)
+    }
+
+    /// Runs blocks until the frame is out of instructions, or until the pc reaches
+    /// an instruction the interpreter has to execute.
+    fn run<R: RandomSource>(&mut self, chip8: &mut Chip8<R>) -> Result<(), Chip8Error> {
+        let mut left = chip8.clock.instructions_left();
+        while left > 0 && !chip8.exited {
+            self.sync(&mut chip8.memory);
+            let start = chip8.pc;
+            if start >= chip8.memory.size {
+                break;
+            }
+            let index = match self.block(&mut chip8.memory, start) {
+                Some(index) => index,
+                None => break,
+            };
+            let mut executed = 0;
+            for op in &self.blocks[index] {
+                if executed == left {
+                    break;
+                }
+                let pc = chip8.pc;
+                chip8.pc += 2;
+                if let Err(fault) = chip8.execute(op.instruction) {
+                    let error = fault.at(pc, op.opcode);
+                    log!("Halting: {}", error);
+                    chip8.clock.executed_instructions(executed);
+                    chip8.halted = Some(error);
+                    return Err(error);
+                }
+                executed += 1;
+                // Taken skips, waits and ROMs writing over code end the block early.
+                if chip8.pc != pc + op.size as usize
+                    || chip8.exited
+                    || !chip8.memory.code_writes.is_empty()
+                {
+                    break;
+                }
+            }
+            chip8.clock.executed_instructions(executed);
+            left -= executed;
+        }
+        Ok(())
+    }
+}
+
+/// Whether the instruction always goes elsewhere than the next one.
+fn ends_block(instruction: Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Jump(_)
+            | Instruction::Call(_)
+            | Instruction::Return
+            | Instruction::JumpRelative(_)
+            | Instruction::Exit
+    )
+}
+
+impl<R: RandomSource> Chip8<R> {
+    pub fn engine(&self) -> Engine {
+        if self.recompiler.is_some() {
+            Engine::Recompiler
+        } else {
+            Engine::Interpreter
+        }
+    }
+
+    /// Switches the engine executing instructions. Whatever the engine, the machine
+    /// ends up in exactly the same state.
+    pub fn set_engine(&mut self, engine: Engine) {
+        if engine == self.engine() {
+            return;
+        }
+        match engine {
+            Engine::Interpreter => {
+                self.recompiler = None;
+                self.memory.compiled = Vec::new();
+            }
+            Engine::Recompiler => {
+                self.recompiler = Some(Recompiler::new());
+                self.memory.compiled = vec![false; MEMORY_CAPACITY];
+                self.memory.code_writes.clear();
+                self.memory.code_replaced = false;
+            }
+        }
+    }
+
+    /// Runs as much of the current frame with the recompiler as it can, if it is on
+    /// and nothing needs the interpreter.
+    pub(crate) fn run_compiled(&mut self) -> Result<(), Chip8Error> {
+        let usable = self.clock.mode == TimingMode::Fixed
+            && self.halted.is_none()
+            && self.movie.is_none()
+            && self.debugger.is_idle()
+            && self.memory.watches.is_empty()
+            && !self.is_tracing();
+        if !usable {
+            return Ok(());
+        }
+        match self.recompiler.take() {
+            Some(mut recompiler) => {
+                let result = recompiler.run(self);
+                self.recompiler = Some(recompiler);
+                result
+            }
+            None => Ok(()),
+        }
+    }
+}
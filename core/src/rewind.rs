@@ -0,0 +1,220 @@
+//! Rewinding: a ring buffer holding a save state for each of the latest frames.
+//!
+//! Storing every state in full would take over 4 KiB a frame, so only every
+//! `KEYFRAME_INTERVAL`th state is kept whole. The states in between are kept as
+//! the runs of bytes in which they differ from that keyframe, which are short as
+//! most of memory never changes while a ROM runs.
+use std::collections::VecDeque;
+
+use crate::error::StateError;
+use crate::{Chip8, MAX_STATE_SIZE};
+
+// One keyframe a second.
+const KEYFRAME_INTERVAL: usize = 60;
+
+/// A keyframe and the frames after it, encoded against it.
+struct Group {
+    keyframe: Vec<u8>,
+    deltas: Vec<Vec<u8>>,
+}
+
+impl Group {
+    fn frames(&self) -> usize {
+        1 + self.deltas.len()
+    }
+}
+
+pub(crate) struct History<R> {
+    capacity: usize,
+    groups: VecDeque<Group>,
+    frames: usize,
+    // Saves the machine's state. `Chip8` can only save states with its builtin
+    // random source, and records frames from code that is generic over it.
+    save: fn(&Chip8<R>, &mut [u8]) -> Result<usize, StateError>,
+    // Holds the latest state, so that saving doesn't allocate every frame.
+    buffer: Vec<u8>,
+}
+
+impl<R> History<R> {
+    pub(crate) fn new(
+        capacity: usize,
+        save: fn(&Chip8<R>, &mut [u8]) -> Result<usize, StateError>,
+    ) -> Self {
+        History {
+            capacity,
+            groups: VecDeque::new(),
+            frames: 0,
+            save,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// How many frames are held.
+    pub(crate) fn len(&self) -> usize {
+        self.frames
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.groups.clear();
+        self.frames = 0;
+    }
+
+    /// Adds the state `chip8` is in as the newest frame.
+    pub(crate) fn record(&mut self, chip8: &Chip8<R>) {
+        self.buffer.resize(MAX_STATE_SIZE, 0);
+        let state = match (self.save)(chip8, &mut self.buffer) {
+            Ok(size) => &self.buffer[..size],
+            Err(_) => return,
+        };
+        match self.groups.back_mut() {
+            Some(group)
+                if group.frames() < KEYFRAME_INTERVAL && group.keyframe.len() == state.len() =>
+            {
+                group.deltas.push(encode_delta(&group.keyframe, state))
+            }
+            _ => self.groups.push_back(Group {
+                keyframe: state.to_vec(),
+                deltas: Vec::new(),
+            }),
+        }
+        self.frames += 1;
+        self.trim();
+    }
+
+    /// Drops the oldest frames beyond the capacity. Whole groups go at once, as their
+    /// frames can't be decoded without the keyframe, so a few more may be kept.
+    fn trim(&mut self) {
+        while let Some(oldest) = self.groups.front() {
+            if self.frames - oldest.frames() < self.capacity {
+                break;
+            }
+            self.frames -= oldest.frames();
+            self.groups.pop_front();
+        }
+    }
+
+    /// Drops the newest `frames` frames and returns the state of the frame that is
+    /// then the newest, if any is left.
+    pub(crate) fn rewind(&mut self, frames: usize) -> Option<Vec<u8>> {
+        for _ in 0..frames {
+            let group = self.groups.back_mut()?;
+            if group.deltas.pop().is_none() {
+                self.groups.pop_back();
+            }
+            self.frames -= 1;
+        }
+        let group = self.groups.back()?;
+        Some(match group.deltas.last() {
+            Some(delta) => decode_delta(&group.keyframe, delta),
+            None => group.keyframe.clone(),
+        })
+    }
+
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.trim();
+    }
+
+    /// The memory taken by the encoded frames.
+    #[cfg(test)]
+    pub(crate) fn size(&self) -> usize {
+        self.groups
+            .iter()
+            .map(|group| group.keyframe.len() + group.deltas.iter().map(Vec::len).sum::<usize>())
+            .sum()
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(bytes: &mut &[u8]) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    while let Some((&byte, rest)) = bytes.split_first() {
+        *bytes = rest;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    value
+}
+
+/// Encodes `state` as runs of bytes that differ from `keyframe`, each one the
+/// number of equal bytes to skip, the length of the run and the bytes of `state`.
+/// Both are the same size.
+fn encode_delta(keyframe: &[u8], state: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    let mut position = 0;
+    while position < state.len() {
+        let start = match (position..state.len()).find(|&i| state[i] != keyframe[i]) {
+            Some(start) => start,
+            None => break,
+        };
+        let end = (start..state.len())
+            .find(|&i| state[i] == keyframe[i])
+            .unwrap_or(state.len());
+        write_varint(&mut delta, start - position);
+        write_varint(&mut delta, end - start);
+        delta.extend_from_slice(&state[start..end]);
+        position = end;
+    }
+    delta
+}
+
+fn decode_delta(keyframe: &[u8], mut delta: &[u8]) -> Vec<u8> {
+    let mut state = keyframe.to_vec();
+    let mut position = 0;
+    while !delta.is_empty() {
+        position += read_varint(&mut delta);
+        let length = read_varint(&mut delta);
+        state[position..position + length].copy_from_slice(&delta[..length]);
+        delta = &delta[length..];
+        position += length;
+    }
+    state
+}
+
+impl Chip8 {
+    /// Keeps the states of at least the last `frames` frames, so that `rewind` can
+    /// go back to any of them. 0, the default, turns rewinding off.
+    pub fn set_rewind_history(&mut self, frames: usize) {
+        match (&mut self.history, frames) {
+            (_, 0) => self.history = None,
+            (Some(history), _) => history.set_capacity(frames),
+            (None, _) => self.history = Some(History::new(frames, Chip8::save_state_into)),
+        }
+    }
+
+    /// How many frames `rewind` can go back right now.
+    pub fn rewind_available(&self) -> usize {
+        self.history
+            .as_ref()
+            .map_or(0, |history| history.len().saturating_sub(1))
+    }
+
+    /// Goes back `frames` frames, or as far as the history reaches, and returns how
+    /// many frames it went back. The frames rewound over are forgotten.
+    pub fn rewind(&mut self, frames: usize) -> usize {
+        let frames = frames.min(self.rewind_available());
+        if frames == 0 {
+            return 0;
+        }
+        let state = self
+            .history
+            .as_mut()
+            .and_then(|history| history.rewind(frames));
+        if let Some(state) = state {
+            // The history only holds states this machine saved.
+            let _ = self.load_state(&state);
+        }
+        frames
+    }
+}
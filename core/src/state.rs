@@ -0,0 +1,468 @@
+//! Save states: everything a running ROM can observe, in a versioned binary format.
+//!
+//! A state is a header (the `C8SV` magic, the format version and the length of the
+//! payload), the payload, and a CRC-32 of both. Numbers are little endian.
+//! Settings chosen by the host rather than the ROM, like the CPU speed, timing
+//! mode, memory policy and flicker filter, aren't saved.
+use crate::display::{Resolution, MAX_FRAME_SIZE, PLANES};
+use crate::error::StateError;
+use crate::memory::{EXTENDED_MEMORY_SIZE, MEMORY_CAPACITY, MEMORY_SIZE};
+use crate::{
+    BuiltinRandom, Chip8, Chip8Error, CosmacVipRandom, KeyWait, Quirks, SeededRandom, PATTERN_SIZE,
+    STACK_DEPTH,
+};
+
+const MAGIC: [u8; 4] = *b"C8SV";
+/// The version of the format `save_state` writes.
+pub const STATE_VERSION: u16 = 1;
+const HEADER_SIZE: usize = 4 + 2 + 4;
+const CHECKSUM_SIZE: usize = 4;
+/// The size of the largest state, that of an XO-CHIP ROM with 64 KiB of memory, or
+/// of a ROM with 4 KiB without the `xo-chip` feature.
+pub const MAX_STATE_SIZE: usize =
+    HEADER_SIZE + payload_size(MEMORY_CAPACITY, MAX_FRAME_SIZE) + CHECKSUM_SIZE;
+
+const fn payload_size(memory_size: usize, frame_size: usize) -> usize {
+    4 // ROM hash
+        + 1 // quirks
+        + memory_size
+        + 16 + 4 + 1 + 1 + 16 // Vx, I, delay, sound, RPL flags
+        + 4 + 4 * STACK_DEPTH + 1 // pc, stack, sp
+        + 2 // keypad
+        + 2 // key wait
+        + 2 // vblank, exited
+        + 1 + 4 + 2 + 4 // halted: kind, pc, opcode, address or key
+        + 1 + 4 + 4 // random: kind, seed, state
+        + PATTERN_SIZE + 1 + 1 // audio pattern, whether it is loaded, pitch
+        + 1 + 1 + PLANES * frame_size // resolution, selected planes, frames
+}
+
+/// The CRC-32 used by zlib and PNG. Identifies ROMs and checks states.
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+struct Writer<'a> {
+    out: &'a mut [u8],
+    position: usize,
+}
+
+impl Writer<'_> {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.out[self.position..self.position + bytes.len()].copy_from_slice(bytes);
+        self.position += bytes.len();
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.bytes(&[value]);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn address(&mut self, address: usize) {
+        self.u32(address as u32);
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    pub(crate) bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn bytes(&mut self, count: usize) -> Result<&'a [u8], StateError> {
+        if self.bytes.len() < count {
+            return Err(StateError::Truncated);
+        }
+        let (bytes, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self, field: &'static str) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::InvalidValue(field)),
+        }
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn address(&mut self) -> Result<usize, StateError> {
+        Ok(self.u32()? as usize)
+    }
+}
+
+pub(crate) fn quirks_to_bits(quirks: Quirks) -> u8 {
+    [
+        quirks.vf_reset,
+        quirks.shift,
+        quirks.load_store,
+        quirks.clip,
+        quirks.jump,
+        quirks.display_wait,
+        quirks.index_overflow,
+        quirks.extended_memory,
+    ]
+    .iter()
+    .enumerate()
+    .fold(0, |bits, (bit, &on)| bits | (on as u8) << bit)
+}
+
+pub(crate) fn quirks_from_bits(bits: u8) -> Quirks {
+    let on = |bit: u32| bits & 1 << bit != 0;
+    Quirks {
+        vf_reset: on(0),
+        shift: on(1),
+        load_store: on(2),
+        clip: on(3),
+        jump: on(4),
+        display_wait: on(5),
+        index_overflow: on(6),
+        extended_memory: on(7),
+    }
+}
+
+fn write_halted(writer: &mut Writer, halted: Option<Chip8Error>) {
+    let (kind, detail) = match halted {
+        None => (0, 0),
+        Some(Chip8Error::UnknownOpcode { .. }) => (1, 0),
+        Some(Chip8Error::StackOverflow { .. }) => (2, 0),
+        Some(Chip8Error::StackUnderflow { .. }) => (3, 0),
+        Some(Chip8Error::MemoryOutOfBounds { address, .. }) => (4, address as u32),
+        Some(Chip8Error::InvalidKey { key, .. }) => (5, key as u32),
+    };
+    writer.u8(kind);
+    writer.address(halted.map_or(0, |error| error.pc()));
+    writer.u16(halted.map_or(0, |error| error.opcode()));
+    writer.u32(detail);
+}
+
+fn read_halted(reader: &mut Reader) -> Result<Option<Chip8Error>, StateError> {
+    let kind = reader.u8()?;
+    let pc = reader.address()?;
+    let opcode = reader.u16()?;
+    let detail = reader.u32()?;
+    Ok(match kind {
+        0 => None,
+        1 => Some(Chip8Error::UnknownOpcode { pc, opcode }),
+        2 => Some(Chip8Error::StackOverflow { pc, opcode }),
+        3 => Some(Chip8Error::StackUnderflow { pc, opcode }),
+        4 => Some(Chip8Error::MemoryOutOfBounds {
+            pc,
+            opcode,
+            address: detail as usize,
+        }),
+        5 => Some(Chip8Error::InvalidKey {
+            pc,
+            opcode,
+            key: detail as u8,
+        }),
+        _ => return Err(StateError::InvalidValue("fault")),
+    })
+}
+
+/// A state read and checked in full before any of it is applied, so that loading
+/// a bad state leaves the machine untouched.
+struct SavedState<'a> {
+    rom_hash: u32,
+    quirks: Quirks,
+    memory: &'a [u8],
+    Vx: [u8; 16],
+    I: usize,
+    delay: u8,
+    sound: u8,
+    rpl: [u8; 16],
+    pc: usize,
+    stack: [usize; STACK_DEPTH],
+    sp: usize,
+    keypad: [bool; 16],
+    key_wait: Option<KeyWait>,
+    vblank: bool,
+    exited: bool,
+    halted: Option<Chip8Error>,
+    random: BuiltinRandom,
+    pattern: [u8; PATTERN_SIZE],
+    pattern_loaded: bool,
+    pitch: u8,
+    resolution: Resolution,
+    selected_planes: u8,
+    frames: [&'a [u8]; PLANES],
+}
+
+impl<'a> SavedState<'a> {
+    /// Reads a version 1 payload. Later versions of the format add their own
+    /// readers, and turn older states into theirs.
+    fn read_v1(reader: &mut Reader<'a>) -> Result<Self, StateError> {
+        let rom_hash = reader.u32()?;
+        let quirks = quirks_from_bits(reader.u8()?);
+        let memory_size = if quirks.extended_memory {
+            EXTENDED_MEMORY_SIZE
+        } else {
+            MEMORY_SIZE
+        };
+        if memory_size > MEMORY_CAPACITY {
+            return Err(StateError::InvalidValue("memory size"));
+        }
+        let memory = reader.bytes(memory_size)?;
+        let Vx = reader.array()?;
+        let I = reader.address()?;
+        let delay = reader.u8()?;
+        let sound = reader.u8()?;
+        let rpl = reader.array()?;
+        let pc = reader.address()?;
+        let mut stack = [0; STACK_DEPTH];
+        for address in stack.iter_mut() {
+            *address = reader.address()?;
+        }
+        let sp = reader.u8()? as usize;
+        if sp > STACK_DEPTH {
+            return Err(StateError::InvalidValue("stack pointer"));
+        }
+        let keys = reader.u16()?;
+        let mut keypad = [false; 16];
+        for (key, pressed) in keypad.iter_mut().enumerate() {
+            *pressed = keys & 1 << key != 0;
+        }
+        let key_wait = match (reader.u8()?, reader.u8()?) {
+            (0, _) => None,
+            (1, _) => Some(KeyWait::Press),
+            (2, key) if key < 16 => Some(KeyWait::Release(key)),
+            _ => return Err(StateError::InvalidValue("key wait")),
+        };
+        let vblank = reader.bool("vblank flag")?;
+        let exited = reader.bool("exit flag")?;
+        let halted = read_halted(reader)?;
+        let random = match (reader.u8()?, reader.u32()?, reader.u32()?) {
+            (0, seed, state) if state != 0 => BuiltinRandom::Seeded(SeededRandom { seed, state }),
+            (1, seed, r9) if seed <= 0xFFFF && r9 <= 0xFFFF => {
+                BuiltinRandom::CosmacVip(CosmacVipRandom {
+                    seed: seed as u16,
+                    r9: r9 as u16,
+                })
+            }
+            _ => return Err(StateError::InvalidValue("random generator")),
+        };
+        let pattern = reader.array()?;
+        let pattern_loaded = reader.bool("audio pattern flag")?;
+        let pitch = reader.u8()?;
+        let resolution = match reader.u8()? {
+            0 => Resolution::Low,
+            1 => Resolution::Tall,
+            2 => Resolution::High,
+            _ => return Err(StateError::InvalidValue("resolution")),
+        };
+        let selected_planes = reader.u8()?;
+        if selected_planes > 0b11 {
+            return Err(StateError::InvalidValue("plane selection"));
+        }
+        let frame_size = resolution.width() * resolution.height() / 8;
+        let frames = [reader.bytes(frame_size)?, reader.bytes(frame_size)?];
+        Ok(SavedState {
+            rom_hash,
+            quirks,
+            memory,
+            Vx,
+            I,
+            delay,
+            sound,
+            rpl,
+            pc,
+            stack,
+            sp,
+            keypad,
+            key_wait,
+            vblank,
+            exited,
+            halted,
+            random,
+            pattern,
+            pattern_loaded,
+            pitch,
+            resolution,
+            selected_planes,
+            frames,
+        })
+    }
+}
+
+impl Chip8 {
+    /// The size in bytes of the state `save_state` would produce right now.
+    pub fn state_size(&self) -> usize {
+        HEADER_SIZE + payload_size(self.memory.size, self.framebuffer.size()) + CHECKSUM_SIZE
+    }
+
+    /// Writes a save state into `out`, which needs room for `state_size()` bytes
+    /// (`MAX_STATE_SIZE` always suffices), and returns its size.
+    pub fn save_state_into(&self, out: &mut [u8]) -> Result<usize, StateError> {
+        let size = self.state_size();
+        if out.len() < size {
+            return Err(StateError::BufferTooSmall {
+                size: out.len(),
+                needed: size,
+            });
+        }
+        let mut writer = Writer { out, position: 0 };
+        writer.bytes(&MAGIC);
+        writer.u16(STATE_VERSION);
+        writer.u32((size - HEADER_SIZE - CHECKSUM_SIZE) as u32);
+
+        writer.u32(self.rom_hash);
+        // The extended_memory bit says how much memory follows, which is 4 KiB
+        // whatever the quirk without the `xo-chip` feature.
+        let quirks = Quirks {
+            extended_memory: self.memory.size == EXTENDED_MEMORY_SIZE,
+            ..self.quirks
+        };
+        writer.u8(quirks_to_bits(quirks));
+        writer.bytes(self.memory());
+        let registers = &self.registers;
+        writer.bytes(&registers.Vx);
+        writer.address(registers.I);
+        writer.u8(registers.delay);
+        writer.u8(registers.sound);
+        writer.bytes(&registers.rpl);
+        writer.address(self.pc);
+        for &address in self.stack.iter() {
+            writer.address(address);
+        }
+        writer.u8(self.sp as u8);
+        let keys = self
+            .keypad
+            .iter()
+            .enumerate()
+            .fold(0, |keys, (key, &pressed)| keys | (pressed as u16) << key);
+        writer.u16(keys);
+        let (wait, key) = match self.key_wait {
+            None => (0, 0),
+            Some(KeyWait::Press) => (1, 0),
+            Some(KeyWait::Release(key)) => (2, key),
+        };
+        writer.u8(wait);
+        writer.u8(key);
+        writer.bool(self.vblank);
+        writer.bool(self.exited);
+        write_halted(&mut writer, self.halted);
+        match &self.random {
+            BuiltinRandom::Seeded(random) => {
+                writer.u8(0);
+                writer.u32(random.seed);
+                writer.u32(random.state);
+            }
+            BuiltinRandom::CosmacVip(random) => {
+                writer.u8(1);
+                writer.u32(random.seed as u32);
+                writer.u32(random.r9 as u32);
+            }
+        }
+        writer.bytes(&self.audio.pattern);
+        writer.bool(self.audio.pattern_loaded);
+        writer.u8(self.audio.pitch);
+        writer.u8(match self.framebuffer.resolution() {
+            Resolution::Low => 0,
+            Resolution::Tall => 1,
+            Resolution::High => 2,
+        });
+        writer.u8(self.framebuffer.selected_plane_mask());
+        for plane in 0..PLANES {
+            writer.bytes(self.framebuffer.current(plane));
+        }
+
+        let checksum = crc32(&writer.out[..writer.position]);
+        writer.u32(checksum);
+        debug_assert_eq!(writer.position, size);
+        Ok(size)
+    }
+
+    /// Saves the state of the machine, to be restored with `load_state`.
+    #[cfg(feature = "std")]
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = vec![0; self.state_size()];
+        // The buffer is exactly as large as the state.
+        let _ = self.save_state_into(&mut state);
+        state
+    }
+
+    /// Restores a state made by `save_state`, including the ROM that was loaded. The
+    /// machine is left as it was if the state can't be loaded.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        if state.len() < MAGIC.len() || state[..MAGIC.len()] != MAGIC {
+            return Err(StateError::NotASaveState);
+        }
+        let mut reader = Reader { bytes: state };
+        reader.bytes(MAGIC.len())?;
+        let version = reader.u16()?;
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let length = reader.u32()? as usize;
+        let payload = reader.bytes(length)?;
+        let checksum = reader.u32()?;
+        if crc32(&state[..HEADER_SIZE + length]) != checksum {
+            return Err(StateError::ChecksumMismatch);
+        }
+        let mut reader = Reader { bytes: payload };
+        let saved = SavedState::read_v1(&mut reader)?;
+        if !reader.bytes.is_empty() {
+            return Err(StateError::InvalidValue("payload length"));
+        }
+        self.apply(saved);
+        Ok(())
+    }
+
+    fn apply(&mut self, saved: SavedState) {
+        self.rom_hash = saved.rom_hash;
+        self.set_quirks(saved.quirks);
+        self.memory[..saved.memory.len()].copy_from_slice(saved.memory);
+        let registers = &mut self.registers;
+        registers.Vx = saved.Vx;
+        registers.I = saved.I;
+        registers.delay = saved.delay;
+        registers.sound = saved.sound;
+        registers.rpl = saved.rpl;
+        self.pc = saved.pc;
+        self.stack = saved.stack;
+        self.sp = saved.sp;
+        self.keypad = saved.keypad;
+        self.key_wait = saved.key_wait;
+        self.vblank = saved.vblank;
+        self.exited = saved.exited;
+        self.halted = saved.halted;
+        self.random = saved.random;
+        self.audio.pattern = saved.pattern;
+        self.audio.pattern_loaded = saved.pattern_loaded;
+        self.audio.pitch = saved.pitch;
+        self.framebuffer
+            .restore(saved.resolution, saved.selected_planes, saved.frames);
+    }
+}
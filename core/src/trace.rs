@@ -0,0 +1,415 @@
+//! Tracing: what the machine does, instruction by instruction, as structured events.
+//!
+//! A host hands the machine a `TraceSink` and a `TraceFilter` picking the events it
+//! wants. Events nobody asked for are never built, and without a sink the machine
+//! runs as fast as it does without tracing at all.
+use core::fmt;
+use core::ops::Range;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs::File;
+use std::io;
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::{BufWriter, Write};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::{Chip8, Instruction, RandomSource, Register, RegisterBank, Syntax};
+
+/// Something the machine did while executing the instruction at `pc`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Trace {
+    pub pc: usize,
+    pub event: TraceEvent,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceEvent {
+    /// The instruction was read from memory.
+    Fetch { opcode: u16 },
+    /// The instruction, decoded, is executed. Comes before everything it does.
+    Execute(Instruction),
+    /// The instruction changed one of V0 to VF or I.
+    RegisterWrite { register: Register, value: usize },
+    /// The instruction wrote `value` to `address`.
+    MemoryWrite { address: usize, value: u8 },
+    /// A sprite of `rows` rows, 0 for a 16x16 one, was drawn at `x`, `y`.
+    Draw {
+        x: u8,
+        y: u8,
+        rows: u8,
+        collision: bool,
+    },
+    /// An Fx0A started waiting for a key to put in V`x`.
+    KeyWait { x: u8 },
+    /// `Register::Delay` or `Register::Sound` was set to `value`.
+    TimerSet { timer: Register, value: u8 },
+}
+
+/// The kinds of `TraceEvent`, to filter them by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceKind {
+    Fetch,
+    Execute,
+    RegisterWrite,
+    MemoryWrite,
+    Draw,
+    KeyWait,
+    TimerSet,
+}
+
+impl TraceEvent {
+    pub fn kind(&self) -> TraceKind {
+        match self {
+            TraceEvent::Fetch { .. } => TraceKind::Fetch,
+            TraceEvent::Execute(_) => TraceKind::Execute,
+            TraceEvent::RegisterWrite { .. } => TraceKind::RegisterWrite,
+            TraceEvent::MemoryWrite { .. } => TraceKind::MemoryWrite,
+            TraceEvent::Draw { .. } => TraceKind::Draw,
+            TraceEvent::KeyWait { .. } => TraceKind::KeyWait,
+            TraceEvent::TimerSet { .. } => TraceKind::TimerSet,
+        }
+    }
+}
+
+// One line per event, e.g. `0204 memory 0300 = 5A`.
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04X} ", self.pc)?;
+        match self.event {
+            TraceEvent::Fetch { opcode } => write!(f, "fetch {:04X}", opcode),
+            TraceEvent::Execute(instruction) => {
+                write!(f, "execute {}", instruction.display(Syntax::Octo))
+            }
+            TraceEvent::RegisterWrite { register, value } => {
+                write!(f, "register {} = {:02X}", RegisterName(register), value)
+            }
+            TraceEvent::MemoryWrite { address, value } => {
+                write!(f, "memory {:04X} = {:02X}", address, value)
+            }
+            TraceEvent::Draw {
+                x,
+                y,
+                rows,
+                collision,
+            } => write!(
+                f,
+                "draw {} rows at {}, {}{}",
+                rows,
+                x,
+                y,
+                if collision { ", collided" } else { "" }
+            ),
+            TraceEvent::KeyWait { x } => write!(f, "key wait into v{:x}", x),
+            TraceEvent::TimerSet { timer, value } => {
+                write!(f, "timer {} = {:02X}", RegisterName(timer), value)
+            }
+        }
+    }
+}
+
+// Registers as the debugger names them.
+struct RegisterName(Register);
+
+impl fmt::Display for RegisterName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Register::V(x) => write!(f, "v{:x}", x),
+            Register::I => write!(f, "i"),
+            Register::Delay => write!(f, "delay"),
+            Register::Sound => write!(f, "sound"),
+        }
+    }
+}
+
+/// Which events reach the sink: those of the kinds included, at a pc in
+/// `addresses`. Memory writes are matched by the address written instead.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceFilter {
+    // One bit per TraceKind.
+    kinds: u8,
+    pub addresses: Range<usize>,
+}
+
+impl TraceFilter {
+    /// Every event, anywhere in memory.
+    pub fn all() -> Self {
+        TraceFilter {
+            kinds: u8::MAX,
+            addresses: 0..usize::MAX,
+        }
+    }
+
+    /// Only the events of `kinds`, anywhere in memory.
+    pub fn only(kinds: &[TraceKind]) -> Self {
+        let mut filter = TraceFilter {
+            kinds: 0,
+            ..TraceFilter::all()
+        };
+        for &kind in kinds {
+            filter.set_kind(kind, true);
+        }
+        filter
+    }
+
+    pub fn includes(&self, kind: TraceKind) -> bool {
+        self.kinds & 1 << kind as u8 != 0
+    }
+
+    pub fn set_kind(&mut self, kind: TraceKind, included: bool) {
+        if included {
+            self.kinds |= 1 << kind as u8;
+        } else {
+            self.kinds &= !(1 << kind as u8);
+        }
+    }
+}
+
+impl Default for TraceFilter {
+    fn default() -> Self {
+        TraceFilter::all()
+    }
+}
+
+/// Receives the events the machine traces.
+pub trait TraceSink {
+    fn record(&mut self, trace: Trace);
+
+    /// Hands over what is buffered, and reports whether anything went wrong since
+    /// the last flush.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Discards every event.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NullSink;
+
+impl TraceSink for NullSink {
+    fn record(&mut self, _trace: Trace) {}
+}
+
+/// Prints every event to stderr, one per line.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ConsoleSink;
+
+impl TraceSink for ConsoleSink {
+    fn record(&mut self, trace: Trace) {
+        eprintln!("{}", trace);
+    }
+}
+
+/// Keeps the latest `capacity` events in memory. Clones share their events, so a
+/// host can hand one to the machine and read what it traced from another.
+#[derive(Clone, Debug)]
+pub struct RingBufferSink {
+    capacity: usize,
+    traces: Rc<RefCell<VecDeque<Trace>>>,
+}
+
+impl RingBufferSink {
+    pub fn new(capacity: usize) -> Self {
+        RingBufferSink {
+            capacity,
+            traces: Rc::new(RefCell::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    /// The events kept, oldest first.
+    pub fn traces(&self) -> Vec<Trace> {
+        self.traces.borrow().iter().copied().collect()
+    }
+
+    pub fn clear(&self) {
+        self.traces.borrow_mut().clear();
+    }
+}
+
+impl TraceSink for RingBufferSink {
+    fn record(&mut self, trace: Trace) {
+        let mut traces = self.traces.borrow_mut();
+        if traces.len() == self.capacity {
+            traces.pop_front();
+        }
+        if self.capacity > 0 {
+            traces.push_back(trace);
+        }
+    }
+}
+
+/// Writes every event to a file, or any other writer, one per line.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileSink<W: Write = BufWriter<File>> {
+    writer: W,
+    // The first write that failed, reported by the next flush. Nothing is written
+    // after it.
+    error: Option<io::Error>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileSink {
+    /// Creates the file at `path`, or truncates it if it exists.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(FileSink::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<W: Write> FileSink<W> {
+    pub fn new(writer: W) -> Self {
+        FileSink {
+            writer,
+            error: None,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<W: Write> TraceSink for FileSink<W> {
+    fn record(&mut self, trace: Trace) {
+        if self.error.is_none() {
+            if let Err(error) = writeln!(self.writer, "{}", trace) {
+                self.error = Some(error);
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+pub(crate) struct Tracer {
+    sink: Option<Box<dyn TraceSink>>,
+    filter: TraceFilter,
+    // The instruction being executed, which the events are attributed to.
+    pc: usize,
+}
+
+impl Tracer {
+    pub(crate) fn new() -> Self {
+        Tracer {
+            sink: None,
+            filter: TraceFilter::all(),
+            pc: 0,
+        }
+    }
+
+    /// Whether a sink takes events of `kind`.
+    pub(crate) fn wants(&self, kind: TraceKind) -> bool {
+        self.sink.is_some() && self.filter.includes(kind)
+    }
+
+    pub(crate) fn is_on(&self) -> bool {
+        self.sink.is_some()
+    }
+}
+
+impl<R: RandomSource> Chip8<R> {
+    /// Sends what the machine does to `sink`, replacing the sink set before.
+    ///
+    /// The recompiler doesn't trace, the interpreter executes every instruction
+    /// while a sink is set.
+    pub fn set_trace_sink(&mut self, sink: impl TraceSink + 'static) {
+        self.tracer.sink = Some(Box::new(sink));
+        self.update_traced_writes();
+    }
+
+    /// Stops tracing, and returns the sink the events went to.
+    pub fn take_trace_sink(&mut self) -> Option<Box<dyn TraceSink>> {
+        let sink = self.tracer.sink.take();
+        self.update_traced_writes();
+        sink
+    }
+
+    pub fn trace_filter(&self) -> &TraceFilter {
+        &self.tracer.filter
+    }
+
+    pub fn set_trace_filter(&mut self, filter: TraceFilter) {
+        self.tracer.filter = filter;
+        self.update_traced_writes();
+    }
+
+    // Memory only collects writes while they are traced.
+    fn update_traced_writes(&mut self) {
+        self.memory.traced_writes = if self.tracer.wants(TraceKind::MemoryWrite) {
+            Some(Vec::new())
+        } else {
+            None
+        };
+    }
+
+    pub(crate) fn is_tracing(&self) -> bool {
+        self.tracer.is_on()
+    }
+
+    /// Sends `event` of the instruction being executed to the sink, if it wants it.
+    pub(crate) fn trace(&mut self, event: TraceEvent) {
+        let tracer = &mut self.tracer;
+        if let Some(sink) = &mut tracer.sink {
+            let address = match event {
+                TraceEvent::MemoryWrite { address, .. } => address,
+                _ => tracer.pc,
+            };
+            if tracer.filter.includes(event.kind()) && tracer.filter.addresses.contains(&address) {
+                sink.record(Trace {
+                    pc: tracer.pc,
+                    event,
+                });
+            }
+        }
+    }
+
+    /// Traces the fetch of the instruction at `pc`. Returns the registers as they
+    /// are before it, if register writes are traced.
+    pub(crate) fn trace_fetch(
+        &mut self,
+        pc: usize,
+        opcode: u16,
+        instruction: Instruction,
+    ) -> Option<RegisterBank> {
+        self.tracer.pc = pc;
+        self.trace(TraceEvent::Fetch { opcode });
+        self.trace(TraceEvent::Execute(instruction));
+        if self.tracer.wants(TraceKind::RegisterWrite) {
+            Some(self.registers.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Traces the registers and memory the instruction changed. `before` are the
+    /// registers before it.
+    pub(crate) fn trace_writes(&mut self, before: Option<RegisterBank>) {
+        if let Some(before) = before {
+            for x in 0..16 {
+                if before.Vx[x] != self.registers.Vx[x] {
+                    self.trace(TraceEvent::RegisterWrite {
+                        register: Register::V(x as u8),
+                        value: self.registers.Vx[x] as usize,
+                    });
+                }
+            }
+            if before.I != self.registers.I {
+                self.trace(TraceEvent::RegisterWrite {
+                    register: Register::I,
+                    value: self.registers.I,
+                });
+            }
+        }
+        if let Some(mut writes) = self.memory.traced_writes.take() {
+            for &(address, value) in &writes {
+                self.trace(TraceEvent::MemoryWrite { address, value });
+            }
+            writes.clear();
+            self.memory.traced_writes = Some(writes);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use chip8_core::{AssembleError, Chip8Error, HostError, MovieError, StateError};
+use js_sys::{Error, Reflect};
+use wasm_bindgen::prelude::JsValue;
+
+/// Builds a js `Error` named `Chip8Error` with `code`, `pc` and `opcode` properties
+/// (plus `address` or `key` when relevant), so js callers can switch on `error.code`.
+pub fn chip8_error(error: Chip8Error) -> JsValue {
+    let js_error = Error::new(&error.to_string());
+    js_error.set_name("Chip8Error");
+    let set = |key: &str, value: JsValue| {
+        // Setting a property on a freshly created Error object can't fail.
+        let _ = Reflect::set(&js_error, &JsValue::from_str(key), &value);
+    };
+    set("code", JsValue::from_str(error.code()));
+    set("pc", JsValue::from(error.pc() as u32));
+    set("opcode", JsValue::from(error.opcode()));
+    match error {
+        Chip8Error::MemoryOutOfBounds { address, .. } => {
+            set("address", JsValue::from(address as u32))
+        }
+        Chip8Error::InvalidKey { key, .. } => set("key", JsValue::from(key)),
+        _ => {}
+    }
+    js_error.into()
+}
+
+pub fn host_error(error: HostError) -> JsValue {
+    Error::new(&error.to_string()).into()
+}
+
+pub fn state_error(error: StateError) -> JsValue {
+    Error::new(&error.to_string()).into()
+}
+
+pub fn movie_error(error: MovieError) -> JsValue {
+    Error::new(&error.to_string()).into()
+}
+
+/// Builds a js `Error` named `AssembleError` with the `line` and `column` the
+/// source went wrong at.
+pub fn assemble_error(error: AssembleError) -> JsValue {
+    let js_error = Error::new(&error.to_string());
+    js_error.set_name("AssembleError");
+    let set = |key: &str, value: usize| {
+        let _ = Reflect::set(
+            &js_error,
+            &JsValue::from_str(key),
+            &JsValue::from(value as u32),
+        );
+    };
+    set("line", error.line);
+    set("column", error.column);
+    js_error.into()
+}
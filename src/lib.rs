@@ -1,23 +1,20 @@
-#![feature(slice_fill)]
-
-#![allow(non_snake_case)]
-pub mod games;
+//! The js bindings of the interpreter: a thin layer over `chip8_core` that
+//! converts between js values and the core's types.
+mod error;
+mod types;
 mod utils;
 
-use games::Game;
-use js_sys::Error;
-use rand::{thread_rng, Rng};
+pub use chip8_core::games;
+use chip8_core::{HostError, Resume};
+use error::{assemble_error, chip8_error, host_error, movie_error, state_error};
+use js_sys::{Error, Object, Reflect};
+use types::{parse_register, stop_reason};
+pub use types::{
+    Access, Engine, FlickerFilter, MemoryPolicy, QuirkProfile, Quirks, Resolution, Syntax,
+    TimingMode, TraceKind,
+};
+use utils::ConsoleSink;
 use wasm_bindgen::prelude::*;
-extern crate web_sys;
-
-// A macro to provide `println!(..)`-style syntax for `console.log` logging.
-macro_rules! log {
-    ( $( $t:tt )* ) => {
-        web_sys::console::log_1(&format!( $( $t )* ).into());
-    }
-}
-
-// mod memory;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
@@ -25,69 +22,15 @@ macro_rules! log {
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
-const FONT_LOCATION: usize = 0x50;
-const MEMORY_SIZE: usize = 4096;
-const DISPLAY_BUFFER_SIZE: usize = 512;
-const NEW_FRAME_START: usize = MEMORY_SIZE - DISPLAY_BUFFER_SIZE / 2;
-const STACK_START: usize = MEMORY_SIZE - DISPLAY_BUFFER_SIZE;
-
 #[wasm_bindgen]
 pub struct Chip8 {
-    memory: [u8; MEMORY_SIZE],
-    registers: RegisterBank,
-    pc: usize,
-    sp: usize,
-    keypad: [bool; 16],
-    game: Game,
-}
-
-struct RegisterBank {
-    Vx: [u8; 16],
-    I: usize,
-    delay: u8,
-    sound: u8,
-}
-
-impl Default for RegisterBank {
-    fn default() -> Self {
-        RegisterBank {
-            Vx: [0; 16],
-            I: 0,
-            delay: 0,
-            sound: 0,
-        }
-    }
+    inner: chip8_core::Chip8,
 }
 
 impl Default for Chip8 {
     fn default() -> Self {
-        let mut memory = [0; MEMORY_SIZE];
-        let fonts = [
-            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-            0x20, 0x60, 0x20, 0x20, 0x70, // 1
-            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-            0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-        ];
-        memory[FONT_LOCATION..(FONT_LOCATION + fonts.len())].copy_from_slice(&fonts);
         Chip8 {
-            memory,
-            registers: RegisterBank::default(),
-            pc: 0x200,
-            sp: STACK_START,
-            keypad: [false; 16],
-            game: Game::default(),
+            inner: chip8_core::Chip8::new(),
         }
     }
 }
@@ -96,531 +39,441 @@ impl Default for Chip8 {
 impl Chip8 {
     pub fn new() -> Self {
         utils::set_panic_hook();
+        utils::set_logger();
         Chip8::default()
     }
 
     pub fn memory_ptr(&self) -> *const u8 {
-        self.memory.as_ptr()
+        self.inner.memory().as_ptr()
     }
 
+    pub fn memory_policy(&self) -> MemoryPolicy {
+        self.inner.memory_policy().into()
+    }
+
+    /// Chooses how out of range memory, stack and `I` accesses are handled.
+    /// `MemoryPolicy::Log` only reaches the console with the `console_log`
+    /// feature; otherwise it wraps silently.
+    pub fn set_memory_policy(&mut self, policy: MemoryPolicy) {
+        self.inner.set_memory_policy(policy.into());
+    }
+
+    /// The framebuffer's first plane, packed 8 pixels to a byte, row by row.
     pub fn display_buffer_ptr(&self) -> *const u8 {
-        // log!("{:?}", &self.memory[STACK_START..NEW_FRAME_START]);
-        self.memory[STACK_START..NEW_FRAME_START].as_ptr()
+        self.first_display_plane().as_ptr()
+    }
+
+    /// Display buffer of one of the two XO-CHIP bitplanes. Together, the bits of both
+    /// planes pick one of four colours for each pixel. Throws for other planes.
+    pub fn display_plane_ptr(&self, plane: usize) -> Result<*const u8, JsValue> {
+        self.inner
+            .display_plane(plane)
+            .map(<[u8]>::as_ptr)
+            .ok_or_else(|| host_error(HostError::InvalidPlane(plane)))
     }
 
+    /// Size in bytes of each plane's buffer at the current resolution.
     pub fn display_buffer_size(&self) -> usize {
-        // The display buffer js should use is only half the size of the real display buffer.
-        // The bottom half of the display buffer is what should be shown on screen,
-        // the top half is what the game state is actually like. We use two representations to
-        // reduce the flicking on the screen by ORing the previous frame with the current frame
-        DISPLAY_BUFFER_SIZE / 2
+        self.first_display_plane().len()
+    }
+
+    pub fn display_width(&self) -> usize {
+        self.inner.display_width()
+    }
+
+    pub fn display_height(&self) -> usize {
+        self.inner.display_height()
+    }
+
+    /// The filtered display of one plane with one byte per pixel, row by row, from 0
+    /// for off to 255 for fully lit. Updated on every timer tick. Throws for planes
+    /// other than 0 and 1.
+    pub fn intensity_buffer_ptr(&self, plane: usize) -> Result<*const u8, JsValue> {
+        self.inner
+            .intensity_plane(plane)
+            .map(<[u8]>::as_ptr)
+            .ok_or_else(|| host_error(HostError::InvalidPlane(plane)))
+    }
+
+    pub fn intensity_buffer_size(&self) -> usize {
+        // Plane 0 always exists.
+        self.inner.intensity_plane(0).unwrap_or_default().len()
+    }
+
+    pub fn flicker_filter(&self) -> FlickerFilter {
+        self.inner.flicker_filter().into()
+    }
+
+    pub fn set_flicker_filter(&mut self, filter: FlickerFilter) {
+        self.inner.set_flicker_filter(filter.into());
+    }
+
+    pub fn set_persistence_frames(&mut self, frames: u8) {
+        self.inner.set_persistence_frames(frames);
+    }
+
+    pub fn set_phosphor_decay(&mut self, decay: f32) {
+        self.inner.set_phosphor_decay(decay);
+    }
+
+    pub fn resolution(&self) -> Resolution {
+        self.inner.resolution().into()
+    }
+
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.inner.set_resolution(resolution.into());
+    }
+
+    pub fn set_vip_display_mirror(&mut self, enabled: bool) {
+        self.inner.set_vip_display_mirror(enabled);
     }
 
     pub fn press_key(&mut self, key: JsValue) -> Result<(), JsValue> {
-        let idx = match key.as_f64() {
-            Some(idx) => idx.round() as usize,
-            None => return Err(Error::new("Could not parse key as f64").into()),
-        };
-        self.keypad[idx as usize] = true;
-        Ok(())
+        let key = parse_key(&key)?;
+        self.inner.press_key(key).map_err(host_error)
     }
 
     pub fn release_key(&mut self, key: JsValue) -> Result<(), JsValue> {
-        // this and the press_key function could be one function with 2 modes of operation,
-        // but this feels like a better API.
-        let idx = match key.as_f64() {
-            Some(idx) => idx.round() as usize,
-            None => return Err(Error::new("Could not parse key as f64").into()),
-        };
-        self.keypad[idx as usize] = false;
-        Ok(())
+        let key = parse_key(&key)?;
+        self.inner.release_key(key).map_err(host_error)
+    }
+
+    /// Puts the machine back the way it was switched on, keeping its settings. Also
+    /// clears a fault it halted on. Loading a ROM resets it too.
+    pub fn reset(&mut self) {
+        self.inner.reset();
     }
 
-    pub fn load_rom(&mut self, title: JsValue) -> Result<(), JsValue> {
+    /// Loads one of the bundled games. Unless a quirk `profile` is given, the game runs
+    /// with the quirks it is known to need.
+    pub fn load_rom(
+        &mut self,
+        title: JsValue,
+        profile: Option<QuirkProfile>,
+    ) -> Result<(), JsValue> {
         match title.as_string() {
             None => Err(Error::new("Could not parse title as string").into()),
-            Some(title) => {
-                let game = games::Game::new(&title)?;
-
-                for (mem, bytes) in self.memory.iter_mut().skip(0x200).zip(game.code) {
-                    *mem = *bytes;
-                }
-                self.game = game;
-                Ok(())
-            }
+            Some(title) => self
+                .inner
+                .load_game(&title, profile.map(Into::into))
+                .map_err(host_error),
         }
     }
 
-    pub fn tick(&mut self) {
-        let machine_code = self.fetch();
-        self.decode_and_execute(machine_code)
+    /// Assembles Octo `source` and loads the ROM, with the quirks of `profile` or
+    /// those the bundled games use. Returns the address of every label by name, and
+    /// throws an `AssembleError` with the `line` and `column` of a mistake.
+    pub fn load_source(
+        &mut self,
+        source: &str,
+        profile: Option<QuirkProfile>,
+    ) -> Result<JsValue, JsValue> {
+        let program = chip8_core::assemble(source).map_err(assemble_error)?;
+        let quirks = profile.map_or(games::DEFAULT_QUIRKS, |profile| {
+            chip8_core::QuirkProfile::from(profile).into()
+        });
+        self.inner
+            .load_program(&program, quirks)
+            .map_err(host_error)?;
+        let labels = Object::new();
+        for (name, &address) in program.labels() {
+            let _ = Reflect::set(&labels, &name.into(), &JsValue::from(address));
+        }
+        Ok(labels.into())
     }
 
-    pub fn decrement_timers(&mut self) {
-        self.registers.delay = self.registers.delay.saturating_sub(1);
-        self.registers.sound = self.registers.sound.saturating_sub(1);
-    }
-
-    fn fetch(&mut self) -> u16 {
-        let machine_code = (self.memory[self.pc] as u16) << 8 | self.memory[self.pc + 1] as u16;
-        self.pc += 2;
-        machine_code
-    }
-
-    fn decode_and_execute(&mut self, instruction_code: u16) {
-        log!("instruction_code: {:04X?}", instruction_code);
-        let nibbles = (
-            (instruction_code >> 12 & 0x000F),
-            (instruction_code >> 8 & 0x000F) as usize,
-            (instruction_code >> 4 & 0x000F) as usize,
-            (instruction_code & 0x000F) as u8,
-        );
-        let vx = self.registers.Vx[nibbles.1 as usize];
-        let vy = self.registers.Vx[nibbles.2 as usize];
-        let byte = (instruction_code & 0x00FF) as u8;
-        let triple = (instruction_code & 0x0FFF) as usize;
-        match nibbles {
-            (0x0, 0x0, 0xE, 0x0) => self.clear_display(),
-            (0x0, 0x0, 0xE, 0xE) => self.return_from_subroutine(),
-            (0x1, _, _, _) => self.jump(triple),
-            (0x2, _, _, _) => self.call_subroutine(triple),
-            (0x3, _, _, _) => self.skip_next_if_equal_to_byte(vx, byte),
-            (0x4, _, _, _) => self.skip_next_if_not_equal_to_byte(vx, byte),
-            (0x5, _, _, 0) => self.skip_next_if_equal_to_register(vx, vy),
-            (0x6, x, _, _) => self.load_from_byte(x, byte),
-            (0x7, x, _, _) => self.add_byte(x, byte),
-            (0x8, x, _, 0) => self.load_from_register(x, vy),
-            (0x8, x, _, 1) => self.or(x, vy),
-            (0x8, x, _, 2) => self.and(x, vy),
-            (0x8, x, _, 3) => self.xor(x, vy),
-            (0x8, x, _, 4) => self.add_registers(x, vy),
-            (0x8, x, _, 5) => self.sub_vy_from_vx(x, vy),
-            (0x8, x, y, 6) => self.shift_right(x, y), // logical right shift, not arithmetic
-            (0x8, x, _, 7) => self.sub_vx_from_vy(x, vy),
-            (0x8, x, y, 0xE) => self.shift_left(x, y),
-            (0x9, _, _, 0) => self.skip_next_if_not_equal_to_register(vx, vy),
-            (0xA, _, _, _) => self.set_I(triple),
-            (0xB, _, _, _) => self.jump_relative(triple),
-            (0xC, x, _, _) => self.set_random_number(x, byte),
-            (0xD, _, _, n) => self.draw(vx, vy, n),
-            (0xE, _, 9, 0xE) => self.skip_if_key_is_pressed(vx),
-            (0xE, _, 0xA, 1) => self.skip_if_key_is_not_pressed(vx),
-            (0xF, x, 0, 7) => self.load_from_delay_timer(x),
-            (0xF, _, 0, 0xA) => self.block_until_key_is_pressed(vx),
-            (0xF, _, 1, 5) => self.set_delay_timer(vx),
-            (0xF, _, 1, 8) => self.set_sound_timer(vx),
-            (0xF, _, 1, 0xE) => self.increment_i(vx),
-            (0xF, _, 2, 9) => self.load_font_location_in_I(vx),
-            (0xF, _, 3, 3) => self.store_bcd(vx),
-            (0xF, x, 5, 5) => self.bulk_store(x),
-            (0xF, x, 6, 5) => self.bulk_load(x),
-            (0xF, _, 7, 5) => self.nop(),
-            (0xF, _, 8, 5) => self.nop(),
-            _ => panic!("Unknowin instruction encountered!"),
-            // _ => Err(Error::new("Unimplemented instruction encountered!").into()),
-        }
+    /// CRC-32 of the loaded ROM, which identifies the game a save state belongs to.
+    pub fn rom_hash(&self) -> u32 {
+        self.inner.rom_hash()
     }
 
-    fn set_sp(&mut self, offset: isize) {
-        log!("stack pointer: {}", self.sp);
-        log!("offset: {}", offset);
-        self.sp = (self.sp as isize + offset) as usize;
-        log!("set stack pointer to: {}", self.sp);
-        // if STACK_START - 12 * 2 >= self.sp {
-        //     return Err(Error::new("StackOverflow: Stack exceeded maximum size").into());
-        // }
-        // if self.sp >= STACK_START {
-        //     return Err(Error::new("StackUnderflow: Stack is invading frame buffer").into());
-        // }
+    /// The whole machine as a `Uint8Array`, to be restored with `load_state`.
+    pub fn save_state(&self) -> Vec<u8> {
+        self.inner.save_state()
     }
 
-    fn clear_display(&mut self) {
-        log!("Clearing display");
-        let (old_frame, current_frame) = self.memory.split_at_mut(NEW_FRAME_START);
-        current_frame.fill(0);
-        old_frame[STACK_START..NEW_FRAME_START].copy_from_slice(current_frame);
+    /// Restores a state made by `save_state`. Throws, leaving the machine as it was,
+    /// if the state is damaged or was saved by an unsupported version.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), JsValue> {
+        self.inner.load_state(state).map_err(state_error)
     }
 
-    fn return_from_subroutine(&mut self) {
-        self.pc = ((self.memory[self.sp] as usize) << 8) | (self.memory[self.sp + 1] as usize);
-        log!("Returning from subroutine to: {}", self.pc);
-        self.set_sp(2);
+    /// Keeps at least the last `frames` frames for `rewind`, 0 turns rewinding off.
+    pub fn set_rewind_history(&mut self, frames: usize) {
+        self.inner.set_rewind_history(frames);
     }
 
-    fn jump(&mut self, address: usize) {
-        // log!("Jumping to: {:04X}", address);
-        self.pc = address;
+    pub fn rewind_available(&self) -> usize {
+        self.inner.rewind_available()
     }
 
-    fn call_subroutine(&mut self, address: usize) {
-        log!("Calling subroutine: {:04X}", address);
-        self.set_sp(-2);
-        self.memory[self.sp] = ((self.pc & 0xFF00) >> 8) as u8;
-        self.memory[self.sp + 1] = (self.pc & 0x00FF) as u8;
-        self.pc = address;
+    /// Goes back up to `frames` frames and returns how many it went back.
+    pub fn rewind(&mut self, frames: usize) -> usize {
+        self.inner.rewind(frames)
     }
 
-    fn skip_next_if_equal_to_byte(&mut self, vx: u8, byte: u8) {
-        log!("Skip next if vx == byte: ({}, {})", vx, byte);
-        if vx == byte {
-            self.pc += 2;
-        }
+    /// Starts recording the keys pressed from now on, for `stop_recording`.
+    pub fn start_recording(&mut self) {
+        self.inner.start_recording();
     }
 
-    fn skip_next_if_not_equal_to_byte(&mut self, vx: u8, byte: u8) {
-        log!("Skip next if vx != byte: ({}, {})", vx, byte);
-        if vx != byte {
-            self.pc += 2;
-        }
+    /// Ends the recording and returns the movie as a `Uint8Array`, `undefined` if
+    /// nothing was being recorded.
+    pub fn stop_recording(&mut self) -> Option<Vec<u8>> {
+        self.inner.stop_recording().map(|movie| movie.to_bytes())
     }
 
-    fn skip_next_if_equal_to_register(&mut self, vx: u8, vy: u8) {
-        log!("Skip next if vx == vy: ({}, {})", vx, vy);
-        if vx == vy {
-            self.pc += 2;
-        }
+    /// Plays back a movie made by `stop_recording`. Throws if it is damaged or was
+    /// recorded with another ROM than the one loaded.
+    pub fn play_movie(&mut self, movie: &[u8]) -> Result<(), JsValue> {
+        let movie = chip8_core::Movie::from_bytes(movie).map_err(movie_error)?;
+        self.inner.play_movie(&movie).map_err(movie_error)
     }
 
-    fn load_from_byte(&mut self, x: usize, byte: u8) {
-        log!("Loading V{:X} with {}", x, byte);
-        self.registers.Vx[x] = byte;
+    pub fn is_playing_movie(&self) -> bool {
+        self.inner.is_playing_movie()
     }
 
-    fn add_byte(&mut self, x: usize, byte: u8) {
-        log!("Adding V{:X} with {}", x, byte);
-        self.registers.Vx[x] = self.registers.Vx[x].wrapping_add(byte);
+    /// Executes a single instruction, unless the debugger stops before it.
+    ///
+    /// If the instruction faults the machine halts: the error is thrown, and every
+    /// following call throws it again without executing anything.
+    pub fn tick(&mut self) -> Result<(), JsValue> {
+        self.inner.tick().map_err(chip8_error)
     }
 
-    fn load_from_register(&mut self, x: usize, vy: u8) {
-        log!("Load V{:X} with {}", x, vy);
-        self.registers.Vx[x] = vy;
+    pub fn is_halted(&self) -> bool {
+        self.inner.is_halted()
     }
 
-    fn or(&mut self, x: usize, vy: u8) {
-        log!("ORing V{:X} with {}", x, vy);
-        self.registers.Vx[x] |= vy;
+    /// Whether the ROM is blocked on Fx0A, waiting for a key to be pressed and released.
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.inner.is_waiting_for_key()
     }
 
-    fn and(&mut self, x: usize, vy: u8) {
-        log!("ANDing V{:X} with {}", x, vy);
-        self.registers.Vx[x] &= vy;
+    pub fn has_exited(&self) -> bool {
+        self.inner.has_exited()
     }
 
-    fn xor(&mut self, x: usize, vy: u8) {
-        log!("XORing V{:X} with {}", x, vy);
-        self.registers.Vx[x] ^= vy;
+    pub fn quirks(&self) -> Quirks {
+        self.inner.quirks().into()
     }
 
-    fn add_registers(&mut self, x: usize, vy: u8) {
-        log!("Adding V{:X} with {}", x, vy);
-        let (result, overflow) = self.registers.Vx[x].overflowing_add(vy);
-        self.registers.Vx[0xF] = if overflow {1} else {0};
-        self.registers.Vx[x] = result;
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.inner.set_quirks(quirks.into());
     }
 
-    fn sub_vy_from_vx(&mut self, x: usize, vy: u8) {
-        log!("Subtracting {} from V{:X}", vy, x);
-        let (result, overflow) = self.registers.Vx[x].overflowing_sub(vy);
-        self.registers.Vx[0xF] = if overflow { 1 } else { 0 };
-        self.registers.Vx[x] = result;
+    pub fn set_audio_sample_rate(&mut self, sample_rate: f32) {
+        self.inner.set_audio_sample_rate(sample_rate);
     }
 
-    fn shift_right(&mut self, x: usize, y: usize) {
-        log!("Right shifting V{:X}", x);
-        if self.game.shift_quirk {
-            self.registers.Vx[0xF] = self.registers.Vx[x] & 1;
-            self.registers.Vx[x] = self.registers.Vx[x] >> 1;
-        } else {
-            self.registers.Vx[0xF] = self.registers.Vx[y] & 1;
-            self.registers.Vx[x] = self.registers.Vx[y] >> 1;
-        }
+    pub fn sound_active(&self) -> bool {
+        self.inner.sound_active()
     }
 
-    fn sub_vx_from_vy(&mut self, x: usize, vy: u8) {
-        log!("Subbing V{:X} from {}", x, vy);
-        let (result, overflow) = vy.overflowing_sub(self.registers.Vx[x]);
-        self.registers.Vx[0xF] = if overflow { 1 } else { 0 };
-        self.registers.Vx[x] = result;
+    /// Fills `out` with the next PCM samples. Call this once per frame with enough
+    /// room for a frame's worth of samples at the audio sample rate.
+    pub fn render_audio(&mut self, out: &mut [f32]) {
+        self.inner.render_audio(out);
     }
 
-    fn shift_left(&mut self, x: usize, y: usize) {
-        log!("Left shifting V{:X}", x);
-        if self.game.shift_quirk {
-            self.registers.Vx[0xF] = (self.registers.Vx[x] & 0b10000000) >> 7;
-            self.registers.Vx[x] = self.registers.Vx[x] << 1;
-        } else {
-            self.registers.Vx[0xF] = (self.registers.Vx[y] & 0b10000000) >> 7;
-            self.registers.Vx[x] = self.registers.Vx[y] << 1;
-        }
+    pub fn random_seed(&self) -> u32 {
+        self.inner.random_seed()
     }
 
-    fn skip_next_if_not_equal_to_register(&mut self, vx: u8, vy: u8) {
-        // log!("Skip next if vx != vy ({}, {})", vx, vy);
-        if vx != vy {
-            self.pc += 2;
-        }
+    pub fn set_random_seed(&mut self, seed: u32) {
+        self.inner.set_random_seed(seed);
     }
 
-    fn set_I(&mut self, address: usize) {
-        self.registers.I = address;
-        log!("Setting I: {:04X}", self.registers.I);
-    }
-
-    fn jump_relative(&mut self, address: usize) {
-        self.pc = address + (self.registers.Vx[0] as usize);
-        log!("Jumping relative to: {:04X}", self.pc);
-    }
-
-    fn set_random_number(&mut self, x: usize, byte: u8) {
-        let mut rng = thread_rng();
-        let random_num: u8 = rng.gen();
-        log!("chose random num: {}", random_num);
-        self.registers.Vx[x] = random_num & byte;
-        log!("Setting random number: {:02X}", self.registers.Vx[x]);
-    }
-
-    fn draw(&mut self, vx: u8, vy: u8, n: u8) {
-        log!("Draw args: vx = {}, vy = {}, n = {}", vx, vy, n);
-        let (rest, current_frame) = self.memory.split_at_mut(NEW_FRAME_START);
-        let (rest, smoothed_frame) = rest.split_at_mut(STACK_START);
-        smoothed_frame.copy_from_slice(current_frame);
-        self.registers.Vx[0xF] = 0;
-
-        let sprite = &rest[self.registers.I..(self.registers.I + n as usize)];
-        // log!("sprite: {:?}", sprite);
-        for y in 0..n {
-            let sprite_byte = sprite[y as usize];
-            for x in 0..8 {
-                let row = ((vx + x) % 64) as usize;
-                let col = ((vy + y) % 32) as usize;
-                let byte = (col * 64 + row) / 8;
-                // log!("sprite_byte: {:08b}", sprite_byte);
-                // log!("screen_byte: {:08b}", current_frame[byte]);
-                let screen_mask: u8 = 0b1000_0000 >> (col * 64 + row) % 8;
-                let sprite_bit = sprite_byte & (0b1000_0000 >> x);
-                // log!("screen mask: {:08b}", screen_mask);
-
-                // log!("sprite bit: {:08b}", sprite_bit);
-
-                // log!("current_frame_byte: {:08b}", current_frame[byte]);
-                if sprite_bit != 0 {
-                    if current_frame[byte] & screen_mask != 0 {
-                        self.registers.Vx[0xF] = 1;
-                    }
-                    current_frame[byte] ^= screen_mask
-                }
-
-                // current_frame[byte] ^=
-                //     (current_frame[byte] & screen_mask) & sprite_byte & (0b1000_0000 >> x);
-                // log!("result: {:08b}", current_frame[byte]);
-            }
-        }
+    pub fn use_cosmac_vip_random(&mut self, seed: u16) {
+        self.inner.use_cosmac_vip_random(seed);
+    }
 
-        for (smooth, raw) in smoothed_frame.iter_mut().zip(current_frame.iter()) {
-            *smooth |= raw;
-        }
+    pub fn cpu_speed(&self) -> u32 {
+        self.inner.cpu_speed()
     }
 
-    fn skip_if_key_is_pressed(&mut self, vx: u8) {
-        log!("Skip next if key is pressed: {:X}", vx);
-        if self.keypad[vx as usize] {
-            self.pc += 2;
-        }
+    pub fn set_cpu_speed(&mut self, instructions_per_second: u32) {
+        self.inner.set_cpu_speed(instructions_per_second);
     }
 
-    fn skip_if_key_is_not_pressed(&mut self, vx: u8) {
-        log!("Skip next if key is not pressed: {:X}", vx);
-        if !self.keypad[vx as usize] {
-            self.pc += 2;
-        }
+    pub fn timing_mode(&self) -> TimingMode {
+        self.inner.timing_mode().into()
     }
 
-    fn load_from_delay_timer(&mut self, x: usize) {
-        log!(
-            "Loading V{:X} with delay timer: {}",
-            x,
-            self.registers.delay
-        );
-        self.registers.Vx[x] = self.registers.delay;
+    pub fn set_timing_mode(&mut self, mode: TimingMode) {
+        self.inner.set_timing_mode(mode.into());
     }
 
-    fn block_until_key_is_pressed(&mut self, vx: u8) {
-        log!("Block until key is pressed: {}", vx);
-        if !self.keypad[vx as usize] {
-            self.pc -= 2;
-        }
+    pub fn engine(&self) -> Engine {
+        self.inner.engine().into()
     }
 
-    fn set_delay_timer(&mut self, vx: u8) {
-        log!("Set delay timer: {}", vx);
-        self.registers.delay = vx;
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.inner.set_engine(engine.into());
     }
 
-    fn set_sound_timer(&mut self, vx: u8) {
-        log!("Set sound timer: {}", vx);
-        self.registers.sound = vx;
+    pub fn run_frame(&mut self) -> Result<(), JsValue> {
+        self.inner.run_frame().map_err(chip8_error)
     }
 
-    fn increment_i(&mut self, vx: u8) {
-        log!("Increment I: {}", vx);
-        self.registers.I += vx as usize;
+    /// Advances the machine by `elapsed_ms` and returns how many frames were completed.
+    pub fn run_for(&mut self, elapsed_ms: f64) -> Result<u32, JsValue> {
+        self.inner.run_for(elapsed_ms).map_err(chip8_error)
     }
 
-    fn load_font_location_in_I(&mut self, vx: u8) {
-        self.registers.I = FONT_LOCATION + (vx as usize) * 5;
-        log!("Set I to font location of {}: {}", vx, self.registers.I);
+    pub fn decrement_timers(&mut self) {
+        self.inner.decrement_timers();
     }
 
-    fn store_bcd(&mut self, vx: u8) {
-        log!("Store BCD: {}", vx);
-        let hundreds = vx / 100;
-        let tens = (vx % 100) / 10;
-        let ones = vx % 10;
-        self.memory[self.registers.I] = hundreds;
-        self.memory[self.registers.I + 1] = tens;
-        self.memory[self.registers.I + 2] = ones;
+    /// Prints what every instruction does to the console, as filtered by
+    /// `set_trace_kind` and `set_trace_addresses`. Slows the interpreter down a lot.
+    pub fn trace_to_console(&mut self) {
+        self.inner.set_trace_sink(ConsoleSink);
     }
 
-    fn bulk_store(&mut self, x: usize) {
-        log!("Bulk store from V0 to V{:X}", x);
-        self.memory[self.registers.I..self.registers.I + x + 1]
-            .copy_from_slice(&self.registers.Vx[0..x + 1]);
-        if !self.game.load_store_quirk {
-            self.registers.I = self.registers.I + x + 1;
-        }
+    pub fn stop_tracing(&mut self) {
+        self.inner.take_trace_sink();
     }
 
-    fn bulk_load(&mut self, x: usize) {
-        log!("Bulk load into V0 to V{:X}", x);
-        self.registers.Vx[0..x + 1]
-            .copy_from_slice(&self.memory[self.registers.I..self.registers.I + x + 1]);
-        log!("registers: {:?}", self.registers.Vx);
-        log!(
-            "memory region: {:?}",
-            &self.memory[self.registers.I..self.registers.I + x + 1]
-        );
-        if !self.game.load_store_quirk {
-            self.registers.I = self.registers.I + x + 1;
-        }
+    /// Whether events of `kind` are traced, which they all are at first.
+    pub fn set_trace_kind(&mut self, kind: TraceKind, traced: bool) {
+        let mut filter = self.inner.trace_filter().clone();
+        filter.set_kind(kind.into(), traced);
+        self.inner.set_trace_filter(filter);
     }
 
-    fn nop(&mut self) {
-        self.pc += 54;
+    /// Only traces the instructions from `start` up to, but not including, `end`,
+    /// and the memory writes to those addresses.
+    pub fn set_trace_addresses(&mut self, start: usize, end: usize) {
+        let mut filter = self.inner.trace_filter().clone();
+        filter.addresses = start..end;
+        self.inner.set_trace_filter(filter);
     }
-}
 
-#[cfg(wasm_bindgen_test)]
-mod tests {
-    use super::*;
+    /// Stops before the instruction at `address` whenever it is reached.
+    pub fn add_breakpoint(&mut self, address: usize) {
+        self.inner.add_breakpoint(address);
+    }
 
-}
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        self.inner.remove_breakpoint(address)
+    }
+
+    /// Stops after instructions that make the `access` to an address from `start`
+    /// up to, but not including, `end`.
+    pub fn watch_memory(&mut self, start: usize, end: usize, access: Access) {
+        self.inner.watch_memory(start..end, access.into());
+    }
+
+    pub fn unwatch_memory(&mut self, start: usize, end: usize) -> bool {
+        self.inner.unwatch_memory(start..end)
+    }
+
+    /// Stops after instructions that change the register named `register`: `v0` to
+    /// `vf`, `i`, `delay` or `sound`.
+    pub fn watch_register(&mut self, register: &str) -> Result<(), JsValue> {
+        self.inner.watch_register(register_named(register)?);
+        Ok(())
+    }
+
+    pub fn unwatch_register(&mut self, register: &str) -> Result<bool, JsValue> {
+        Ok(self.inner.unwatch_register(register_named(register)?))
+    }
 
-mod idk {
+    pub fn clear_breakpoints(&mut self) {
+        self.inner.clear_breakpoints();
+    }
 
-    #![cfg(target_arch = "wasm32")]
+    /// Why execution stopped, as an object with a `reason` property, or `undefined`
+    /// while it is running. `run_frame` and `run_for` do nothing while it is stopped.
+    pub fn stop_reason(&self) -> JsValue {
+        self.inner
+            .stop_reason()
+            .map_or(JsValue::UNDEFINED, stop_reason)
+    }
 
-    use wasm_bindgen_test;
-    use wasm_bindgen::prelude::JsValue;
-    use wasm_bindgen_test::*;
-    use super::*;
-    use super::games::*;
+    pub fn pause(&mut self) {
+        self.inner.pause();
+    }
 
-    wasm_bindgen_test_configure!(run_in_browser);
+    /// Lets `run_frame` and `run_for` carry on after execution stopped.
+    pub fn resume(&mut self) {
+        self.inner.resume(Resume::Continue);
+    }
 
-    #[wasm_bindgen_test]
-    fn loads_games() {
-        let mut chip8 = Chip8::new();
-        chip8.load_rom(JsValue::from_str("TETRIS")).unwrap();
-        let slice = unsafe { std::slice::from_raw_parts(chip8.memory_ptr(), 4096) };
-        assert_eq!(&slice[0x200..(0x200 + TETRIS.len())], TETRIS);
+    /// Executes a single instruction and returns why execution stopped.
+    pub fn step_into(&mut self) -> JsValue {
+        self.debug_run(Resume::StepInto)
+    }
 
-        chip8.load_rom(JsValue::from_str("brix")).unwrap();
-        let slice = unsafe { std::slice::from_raw_parts(chip8.memory_ptr(), 4096) };
-        assert_eq!(&slice[0x200..(0x200 + BRIX.len())], BRIX);
+    /// Like `step_into`, but runs 2NNN calls until they return.
+    pub fn step_over(&mut self) -> JsValue {
+        self.debug_run(Resume::StepOver)
     }
 
-    #[wasm_bindgen_test]
-    fn add_register_overflows_and_sets_flag() {
-        let mut chip8 = Chip8::new();
-        chip8.load_from_byte(0, 0xF0);
-        chip8.add_registers(0, 0x0F);
-        assert_eq!(chip8.registers.Vx[0], 0xFF);
-        assert_eq!(chip8.registers.Vx[0xF], 0);
+    /// Runs until the current subroutine returns and returns why execution stopped.
+    pub fn step_out(&mut self) -> JsValue {
+        self.debug_run(Resume::StepOut)
+    }
 
-        chip8.add_registers(0, 0x0F);
+    /// Runs until the instruction at `address` is reached and returns why execution
+    /// stopped.
+    pub fn run_to(&mut self, address: usize) -> JsValue {
+        self.debug_run(Resume::RunTo(address))
+    }
 
-        assert_eq!(chip8.registers.Vx[0], 0x0E);
-        assert_eq!(chip8.registers.Vx[0xF], 1);
+    pub fn pc(&self) -> usize {
+        self.inner.pc()
     }
 
-    #[wasm_bindgen_test]
-    fn draws_correctly() {
-        let mut chip8 = Chip8::new();
-        chip8.load_rom(JsValue::from_str("bctest"));
-        chip8.set_I(000);
-        let e: [u8; 5] = [0xF0, 0x80, 0xF0, 0x80, 0xF0]; // E
-        chip8.memory[0..5].copy_from_slice(&e);
-        chip8.draw(0, 0, 5);
-        // log!("{:X?}", &chip8.memory[NEW_FRAME_START..]);
-        for i in 0..5 {
-            assert_eq!(chip8.memory[NEW_FRAME_START + 8 *i], e[i]);
-        }
-        assert_eq!(chip8.registers.Vx[0xF], 0);
+    pub fn register(&self, register: &str) -> Result<usize, JsValue> {
+        Ok(self.inner.register(register_named(register)?))
+    }
 
-        chip8.draw(0, 0, 5);
-        for i in 0..5 {
-            assert_eq!(chip8.memory[NEW_FRAME_START + 8 * i], 0);
-        }
-        assert_eq!(chip8.registers.Vx[0xF], 1);
-    }
-    #[wasm_bindgen_test]
-    fn return_reverts_call() {
-        let mut chip8 = Chip8::new();
-        chip8.memory[0x200] = 0x24;
-        chip8.memory[0x201] = 0x00;
-        chip8.tick();
-        chip8.memory[0x400] = 0x00;
-        chip8.memory[0x401] = 0xEE;
-        chip8.tick();
-        assert_eq!(chip8.pc, 0x202);
-        assert_eq!(chip8.sp, STACK_START);
-    }
-
-    #[wasm_bindgen_test]
-    fn check_sub_vy_from_vx() {
-        let mut chip8 = Chip8::new();
-        chip8.registers.Vx[0] = 2;
-        chip8.registers.Vx[1] = 1;
-        chip8.memory[0x200] = 0x80;
-        chip8.memory[0x201] = 0x15;
-        chip8.tick();
-        assert_eq!(chip8.registers.Vx[0], 1);
-        assert_eq!(chip8.registers.Vx[0xF], 0);
-        chip8.pc = 0x200;
-        chip8.tick();
-        assert_eq!(chip8.registers.Vx[0], 0);
-        assert_eq!(chip8.registers.Vx[0xF], 0);
-        chip8.pc = 0x200;
-        chip8.tick();
-        assert_eq!(chip8.registers.Vx[0], 0xFF);
-        assert_eq!(chip8.registers.Vx[0xF], 1);
-    }
-
-    #[wasm_bindgen_test]
-    fn check_sub_vx_from_vy() {
-        let mut chip8 = Chip8::new();
-        chip8.registers.Vx[0] = 1;
-        chip8.registers.Vx[1] = 2;
-        chip8.memory[0x200] = 0x80;
-        chip8.memory[0x201] = 0x17;
-        chip8.tick();
-        assert_eq!(chip8.registers.Vx[0], 1);
-        assert_eq!(chip8.registers.Vx[0xF], 0);
-        chip8.registers.Vx[0] = 3;
-        chip8.pc = 0x200;
-        chip8.tick();
-        assert_eq!(chip8.registers.Vx[0], 0xFF);
-        assert_eq!(chip8.registers.Vx[0xF], 1);
+    /// The return addresses of the subroutines being executed, innermost last.
+    pub fn stack(&self) -> Vec<u32> {
+        self.inner
+            .stack()
+            .iter()
+            .map(|&address| address as u32)
+            .collect()
     }
+}
 
+/// Disassembles the ROM `code`, loaded at `load_address` (0x200 for most ROMs), with
+/// labels for the addresses it jumps to and calls.
+#[wasm_bindgen]
+pub fn disassemble(code: &[u8], load_address: u16, syntax: Syntax) -> String {
+    chip8_core::disassemble(code, load_address)
+        .display(syntax.into())
+        .to_string()
+}
+
+// Steps run for at most this many frames at once, so a step that never ends doesn't
+// hang the page. The step carries on as frames are run.
+const MAX_STEP_FRAMES: u32 = 600;
+
+impl Chip8 {
+    fn debug_run(&mut self, how: Resume) -> JsValue {
+        self.inner
+            .debug_run(how, MAX_STEP_FRAMES)
+            .map_or(JsValue::UNDEFINED, stop_reason)
+    }
+
+    // Plane 0 always exists.
+    fn first_display_plane(&self) -> &[u8] {
+        self.inner.display_plane(0).unwrap_or_default()
+    }
+}
+
+fn register_named(name: &str) -> Result<chip8_core::Register, JsValue> {
+    parse_register(name).ok_or_else(|| Error::new(&format!("Unknown register '{}'", name)).into())
+}
+
+fn parse_key(key: &JsValue) -> Result<usize, JsValue> {
+    match key.as_f64() {
+        Some(key) => Ok(key.round() as usize),
+        None => Err(Error::new("Could not parse key as f64").into()),
+    }
 }
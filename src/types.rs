@@ -0,0 +1,254 @@
+//! js facing copies of the core's settings and results, which can't carry
+//! `#[wasm_bindgen]` themselves as the core doesn't depend on it.
+use chip8_core::{Register, StopReason};
+use js_sys::{Object, Reflect};
+use wasm_bindgen::prelude::*;
+
+use crate::error::chip8_error;
+
+// Declares a fieldless enum for js along with conversions to and from the core
+// enum of the same name.
+macro_rules! mirror_enum {
+    ($(#[$meta:meta])* $name:ident { $($(#[$variant_meta:meta])* $variant:ident,)* }) => {
+        $(#[$meta])*
+        #[wasm_bindgen]
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)*
+        }
+
+        impl From<$name> for chip8_core::$name {
+            fn from(value: $name) -> Self {
+                match value {
+                    $($name::$variant => chip8_core::$name::$variant,)*
+                }
+            }
+        }
+
+        impl From<chip8_core::$name> for $name {
+            fn from(value: chip8_core::$name) -> Self {
+                match value {
+                    $(chip8_core::$name::$variant => $name::$variant,)*
+                }
+            }
+        }
+    };
+}
+
+mirror_enum! {
+    /// What the interpreter does when a ROM reaches outside of memory or the stack.
+    MemoryPolicy {
+        /// Wrap the address around, like the original hardware did.
+        Wrap,
+        /// Halt the machine with a `Chip8Error`.
+        Fault,
+        /// Log the access to the console and carry on as if wrapping.
+        Log,
+    }
+}
+
+mirror_enum! {
+    /// The well known CHIP-8 interpreters whose behaviour ROMs tend to depend on.
+    QuirkProfile {
+        CosmacVip,
+        Chip48,
+        SuperChipLegacy,
+        SuperChipModern,
+        XoChip,
+    }
+}
+
+mirror_enum! {
+    /// How the emulator decides how many instructions make up a frame.
+    TimingMode {
+        /// Every instruction takes the same time, set with `set_cpu_speed`.
+        Fixed,
+        /// Instructions take as many machine cycles as on the COSMAC VIP.
+        CosmacVip,
+    }
+}
+
+mirror_enum! {
+    /// How instructions are executed.
+    Engine {
+        /// One instruction at a time.
+        Interpreter,
+        /// Blocks of instructions compiled ahead of time, faster at high speeds.
+        Recompiler,
+    }
+}
+
+mirror_enum! {
+    /// How a frame is turned into what is shown, to hide flicker.
+    FlickerFilter {
+        Off,
+        OrPrevious,
+        Persistence,
+        PhosphorDecay,
+    }
+}
+
+mirror_enum! {
+    /// The sizes the display can take: 64x32, 64x64 and 128x64.
+    Resolution {
+        Low,
+        Tall,
+        High,
+    }
+}
+
+mirror_enum! {
+    /// The kind of memory access a watchpoint stops on.
+    Access {
+        Read,
+        Write,
+        ReadWrite,
+    }
+}
+
+mirror_enum! {
+    /// The kinds of events the interpreter traces.
+    TraceKind {
+        Fetch,
+        Execute,
+        RegisterWrite,
+        MemoryWrite,
+        Draw,
+        KeyWait,
+        TimerSet,
+    }
+}
+
+mirror_enum! {
+    /// The assembly language `disassemble` writes.
+    Syntax {
+        /// The language of the Octo assembler.
+        Octo,
+        /// The mnemonics of Cowgod's CHIP-8 technical reference.
+        Cowgod,
+    }
+}
+
+/// Parses the name of a register the debugger can watch: `v0` to `vf`, `i`,
+/// `delay` or `sound`, in any case.
+pub fn parse_register(name: &str) -> Option<Register> {
+    let name = name.to_ascii_lowercase();
+    match name.as_str() {
+        "i" => Some(Register::I),
+        "delay" => Some(Register::Delay),
+        "sound" => Some(Register::Sound),
+        _ if name.len() == 2 && name.starts_with('v') => {
+            u8::from_str_radix(&name[1..], 16).ok().map(Register::V)
+        }
+        _ => None,
+    }
+}
+
+fn register_name(register: Register) -> String {
+    match register {
+        Register::V(x) => format!("v{:x}", x),
+        Register::I => "i".to_string(),
+        Register::Delay => "delay".to_string(),
+        Register::Sound => "sound".to_string(),
+    }
+}
+
+/// Turns a stop reason into an object with a `reason` property, one of
+/// `breakpoint`, `memory`, `register`, `step`, `paused`, `halted` or `exited`,
+/// and the details of that reason: `pc`, `address` and `access`, `register`, `old`
+/// and `new`, or the `error` the machine halted on.
+pub fn stop_reason(reason: StopReason) -> JsValue {
+    let object = Object::new();
+    let set = |key: &str, value: JsValue| {
+        // Setting a property on a freshly created object can't fail.
+        let _ = Reflect::set(&object, &JsValue::from_str(key), &value);
+    };
+    let name = match reason {
+        StopReason::Breakpoint { pc } => {
+            set("pc", JsValue::from(pc as u32));
+            "breakpoint"
+        }
+        StopReason::Memory {
+            pc,
+            address,
+            access,
+        } => {
+            set("pc", JsValue::from(pc as u32));
+            set("address", JsValue::from(address as u32));
+            set("access", JsValue::from(Access::from(access) as u32));
+            "memory"
+        }
+        StopReason::Register {
+            pc,
+            register,
+            old,
+            new,
+        } => {
+            set("pc", JsValue::from(pc as u32));
+            set("register", JsValue::from_str(&register_name(register)));
+            set("old", JsValue::from(old as u32));
+            set("new", JsValue::from(new as u32));
+            "register"
+        }
+        StopReason::Step => "step",
+        StopReason::Paused => "paused",
+        StopReason::Halted(error) => {
+            set("error", chip8_error(error));
+            "halted"
+        }
+        StopReason::Exited => "exited",
+    };
+    set("reason", JsValue::from_str(name));
+    object.into()
+}
+
+/// Behaviours that differ between CHIP-8 interpreters, see `chip8_core::Quirks`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    pub vf_reset: bool,
+    pub shift: bool,
+    pub load_store: bool,
+    pub clip: bool,
+    pub jump: bool,
+    pub display_wait: bool,
+    pub index_overflow: bool,
+    pub extended_memory: bool,
+}
+
+#[wasm_bindgen]
+impl Quirks {
+    pub fn from_profile(profile: QuirkProfile) -> Quirks {
+        chip8_core::Quirks::from_profile(profile.into()).into()
+    }
+}
+
+impl From<Quirks> for chip8_core::Quirks {
+    fn from(quirks: Quirks) -> Self {
+        chip8_core::Quirks {
+            vf_reset: quirks.vf_reset,
+            shift: quirks.shift,
+            load_store: quirks.load_store,
+            clip: quirks.clip,
+            jump: quirks.jump,
+            display_wait: quirks.display_wait,
+            index_overflow: quirks.index_overflow,
+            extended_memory: quirks.extended_memory,
+        }
+    }
+}
+
+impl From<chip8_core::Quirks> for Quirks {
+    fn from(quirks: chip8_core::Quirks) -> Self {
+        Quirks {
+            vf_reset: quirks.vf_reset,
+            shift: quirks.shift,
+            load_store: quirks.load_store,
+            clip: quirks.clip,
+            jump: quirks.jump,
+            display_wait: quirks.display_wait,
+            index_overflow: quirks.index_overflow,
+            extended_memory: quirks.extended_memory,
+        }
+    }
+}
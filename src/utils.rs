@@ -1,3 +1,5 @@
+use chip8_core::{Trace, TraceSink};
+
 pub fn set_panic_hook() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
     // `set_panic_hook` function at least once during initialization, and then
@@ -8,3 +10,37 @@ pub fn set_panic_hook() {
     #[cfg(feature = "console_error_panic_hook")]
     console_error_panic_hook::set_once();
 }
+
+/// Prints the events the interpreter traces to the browser console, one per line.
+pub struct ConsoleSink;
+
+impl TraceSink for ConsoleSink {
+    fn record(&mut self, trace: Trace) {
+        web_sys::console::log_1(&trace.to_string().into());
+    }
+}
+
+/// Sends the interpreter's log messages, about faults and ignored memory
+/// violations, to the browser console.
+struct ConsoleLogger;
+
+impl log::Log for ConsoleLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        web_sys::console::log_1(&record.args().to_string().into());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: ConsoleLogger = ConsoleLogger;
+
+pub fn set_logger() {
+    // Only the first call installs the logger, later ones fail and can be ignored.
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(log::LevelFilter::Debug);
+    }
+}
@@ -0,0 +1,177 @@
+use std::time::{Duration, Instant};
+
+use chip8_core::{Chip8, HostError};
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+
+/// The keypad key a keyboard key stands for, in the same QWERTY layout as the web
+/// frontend:
+///
+/// ```text
+/// 1 2 3 4      1 2 3 C
+/// Q W E R  ->  4 5 6 D
+/// A S D F      7 8 9 E
+/// Z X C V      A 0 B F
+/// ```
+pub fn translate_key(code: KeyCode) -> Option<usize> {
+    let key = match code {
+        KeyCode::Char(key) => key.to_ascii_lowercase(),
+        _ => return None,
+    };
+    match key {
+        '1' => Some(0x1),
+        '2' => Some(0x2),
+        '3' => Some(0x3),
+        '4' => Some(0xC),
+        'q' => Some(0x4),
+        'w' => Some(0x5),
+        'e' => Some(0x6),
+        'r' => Some(0xD),
+        'a' => Some(0x7),
+        's' => Some(0x8),
+        'd' => Some(0x9),
+        'f' => Some(0xE),
+        'z' => Some(0xA),
+        'x' => Some(0x0),
+        'c' => Some(0xB),
+        'v' => Some(0xF),
+        _ => None,
+    }
+}
+
+/// Turns key events into presses and releases of the keypad.
+///
+/// Most terminals only report that a key went down, and then repeat it while it is
+/// held. Unless the terminal reports releases, a key is released once it hasn't been
+/// repeated for `hold`.
+pub struct Keypad {
+    reports_releases: bool,
+    hold: Duration,
+    // When each held key is due to be released, if the terminal doesn't say.
+    release_at: [Option<Instant>; 16],
+}
+
+impl Keypad {
+    pub fn new(reports_releases: bool, hold: Duration) -> Self {
+        Keypad {
+            reports_releases,
+            hold,
+            release_at: [None; 16],
+        }
+    }
+
+    /// Presses or releases the key for `event`, returning whether it was on the keypad.
+    pub fn handle(
+        &mut self,
+        chip8: &mut Chip8,
+        event: KeyEvent,
+        now: Instant,
+    ) -> Result<bool, HostError> {
+        let key = match translate_key(event.code) {
+            Some(key) => key,
+            None => return Ok(false),
+        };
+        match event.kind {
+            KeyEventKind::Release => {
+                self.release_at[key] = None;
+                chip8.release_key(key)?;
+            }
+            KeyEventKind::Press | KeyEventKind::Repeat => {
+                if !self.reports_releases {
+                    self.release_at[key] = Some(now + self.hold);
+                }
+                chip8.press_key(key)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Releases the keys that haven't been repeated for long enough.
+    pub fn release_expired(&mut self, chip8: &mut Chip8, now: Instant) -> Result<(), HostError> {
+        for (key, release_at) in self.release_at.iter_mut().enumerate() {
+            if release_at.is_some_and(|time| time <= now) {
+                *release_at = None;
+                chip8.release_key(key)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyEventState, KeyModifiers};
+
+    fn event(key: char, kind: KeyEventKind) -> KeyEvent {
+        KeyEvent {
+            code: KeyCode::Char(key),
+            modifiers: KeyModifiers::NONE,
+            kind,
+            state: KeyEventState::NONE,
+        }
+    }
+
+    #[test]
+    fn maps_qwerty_onto_the_keypad() {
+        let layout: Vec<Option<usize>> = "1234qwerasdfzxcv"
+            .chars()
+            .map(|key| translate_key(KeyCode::Char(key)))
+            .collect();
+        let keypad = [
+            0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+        ];
+        assert_eq!(
+            layout,
+            keypad.iter().map(|&key| Some(key)).collect::<Vec<_>>()
+        );
+        assert_eq!(translate_key(KeyCode::Char('W')), Some(0x5));
+        assert_eq!(translate_key(KeyCode::Char('p')), None);
+        assert_eq!(translate_key(KeyCode::Esc), None);
+    }
+
+    #[test]
+    fn releases_keys_that_stop_repeating() {
+        let mut chip8 = Chip8::with_seed(1);
+        let mut keypad = Keypad::new(false, Duration::from_millis(100));
+        let start = Instant::now();
+
+        assert!(keypad
+            .handle(&mut chip8, event('w', KeyEventKind::Press), start)
+            .unwrap());
+        assert!(chip8.is_key_pressed(0x5));
+        keypad
+            .handle(
+                &mut chip8,
+                event('w', KeyEventKind::Repeat),
+                start + Duration::from_millis(80),
+            )
+            .unwrap();
+        keypad
+            .release_expired(&mut chip8, start + Duration::from_millis(150))
+            .unwrap();
+        assert!(chip8.is_key_pressed(0x5));
+        keypad
+            .release_expired(&mut chip8, start + Duration::from_millis(180))
+            .unwrap();
+        assert!(!chip8.is_key_pressed(0x5));
+    }
+
+    #[test]
+    fn waits_for_releases_the_terminal_reports() {
+        let mut chip8 = Chip8::with_seed(1);
+        let mut keypad = Keypad::new(true, Duration::from_millis(100));
+        let start = Instant::now();
+
+        keypad
+            .handle(&mut chip8, event('x', KeyEventKind::Press), start)
+            .unwrap();
+        keypad
+            .release_expired(&mut chip8, start + Duration::from_secs(60))
+            .unwrap();
+        assert!(chip8.is_key_pressed(0x0));
+        keypad
+            .handle(&mut chip8, event('x', KeyEventKind::Release), start)
+            .unwrap();
+        assert!(!chip8.is_key_pressed(0x0));
+    }
+}
@@ -0,0 +1,261 @@
+//! Plays ROMs in a terminal, drawing the display with half blocks or braille so
+//! that it works over SSH.
+mod keypad;
+mod render;
+
+use std::error::Error;
+use std::fs;
+use std::io::{self, Stdout, Write};
+use std::process;
+use std::time::{Duration, Instant};
+
+use chip8_core::{Chip8, Movie, QuirkProfile, SystemClock, TimingMode};
+use crossterm::cursor::{Hide, Show};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::execute;
+use crossterm::terminal::{
+    self, disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use keypad::Keypad;
+use render::{Frame, Screen, Style};
+
+const USAGE: &str = "\
+Usage: chip8-tui [OPTIONS] <ROM>
+
+Plays <ROM>, a ROM file or the title of a bundled game, in the terminal.
+
+Keys:
+  1 2 3 4               1 2 3 C
+  Q W E R    are the    4 5 6 D
+  A S D F    keypad's   7 8 9 E
+  Z X C V               A 0 B F
+  Esc quits.
+
+Options:
+  --braille             Draw with braille, 2x4 pixels to a character, instead of
+                        half blocks
+  --hold <MS>           How long a key stays down after the terminal last
+                        reported it, unless it reports releases [default: 150]
+  --movie <FILE>        Play back the movie in FILE, recorded with the same ROM
+  --record <FILE>       Record the keys pressed into a movie in FILE, written
+                        on quitting
+  --quirks <PROFILE>    cosmac-vip, chip48, schip-legacy, schip-modern or xo-chip
+  --speed <N>           Instructions per second [default: 600]
+  --vip-timing          Time instructions like the COSMAC VIP instead
+  -h, --help            Print this message";
+
+// How long to wait for input before running the frames that came due.
+const FRAME: Duration = Duration::from_micros(1_000_000 / 60);
+
+#[derive(Debug)]
+struct Options {
+    rom: String,
+    style: Style,
+    hold: Duration,
+    movie: Option<String>,
+    record: Option<String>,
+    profile: Option<QuirkProfile>,
+    speed: Option<u32>,
+    vip_timing: bool,
+}
+
+impl Options {
+    /// Returns `Ok(None)` when help was asked for.
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+        let mut rom = None;
+        let mut options = Options {
+            rom: String::new(),
+            style: Style::HalfBlocks,
+            hold: Duration::from_millis(150),
+            movie: None,
+            record: None,
+            profile: None,
+            speed: None,
+            vip_timing: false,
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "--braille" => options.style = Style::Braille,
+                "--hold" => options.hold = Duration::from_millis(parse_number(&value()?)?.into()),
+                "--movie" => options.movie = Some(value()?),
+                "--record" => options.record = Some(value()?),
+                "--quirks" => {
+                    let name = value()?;
+                    let profile = name
+                        .parse()
+                        .map_err(|error| format!("{} '{}'", error, name))?;
+                    options.profile = Some(profile);
+                }
+                "--speed" => options.speed = Some(parse_number(&value()?)?),
+                "--vip-timing" => options.vip_timing = true,
+                _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+                _ if rom.is_none() => rom = Some(arg),
+                _ => return Err(format!("unexpected argument '{}'", arg)),
+            }
+        }
+        options.rom = rom.ok_or("missing <ROM>")?;
+        if options.movie.is_some() && options.record.is_some() {
+            return Err("--movie can't be combined with --record".to_string());
+        }
+        Ok(Some(options))
+    }
+}
+
+fn parse_number(value: &str) -> Result<u32, String> {
+    value
+        .parse()
+        .map_err(|_| format!("'{}' is not a number", value))
+}
+
+/// Raw mode on the alternate screen for as long as it lives, so that the terminal is
+/// restored however the game ends.
+struct Terminal {
+    out: Stdout,
+    reports_releases: bool,
+}
+
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        let mut out = io::stdout();
+        enable_raw_mode()?;
+        execute!(out, EnterAlternateScreen, Hide)?;
+        let reports_releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if reports_releases {
+            execute!(
+                out,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        Ok(Terminal {
+            out,
+            reports_releases,
+        })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if self.reports_releases {
+            let _ = execute!(self.out, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(self.out, LeaveAlternateScreen, Show);
+        let _ = disable_raw_mode();
+    }
+}
+
+fn is_quit(event: &KeyEvent) -> bool {
+    let ctrl_c =
+        event.code == KeyCode::Char('c') && event.modifiers.contains(KeyModifiers::CONTROL);
+    event.kind == KeyEventKind::Press && (event.code == KeyCode::Esc || ctrl_c)
+}
+
+fn play(chip8: &mut Chip8, options: &Options) -> Result<(), Box<dyn Error>> {
+    let mut terminal = Terminal::enter()?;
+    let mut keypad = Keypad::new(terminal.reports_releases, options.hold);
+    let mut screen = Screen::default();
+    let mut clock = SystemClock::default();
+    // Why the ROM stopped running, once it has.
+    let mut stopped = None;
+    loop {
+        if event::poll(FRAME)? {
+            loop {
+                match event::read()? {
+                    Event::Key(key) if is_quit(&key) => return Ok(()),
+                    // Ctrl-C is the only control key that means anything.
+                    Event::Key(key) if key.modifiers.contains(KeyModifiers::CONTROL) => {}
+                    Event::Key(key) => {
+                        keypad.handle(chip8, key, Instant::now())?;
+                    }
+                    Event::Resize(..) => screen.invalidate(),
+                    _ => {}
+                }
+                if !event::poll(Duration::ZERO)? {
+                    break;
+                }
+            }
+        }
+        keypad.release_expired(chip8, Instant::now())?;
+
+        if stopped.is_none() {
+            if let Err(error) = chip8.run(&mut clock) {
+                stopped = Some(format!("Halted: {}.", error));
+            } else if chip8.has_exited() {
+                stopped = Some("The ROM exited.".to_string());
+            }
+        }
+        let bell = stopped.is_none() && chip8.sound_active();
+        let status = match &stopped {
+            Some(reason) => format!("{} Esc quits.", reason),
+            None => format!(
+                "{}{}  {}  Esc quits.",
+                options.rom,
+                if chip8.is_recording() {
+                    "  REC"
+                } else if chip8.is_playing_movie() {
+                    "  PLAYING MOVIE"
+                } else {
+                    ""
+                },
+                if bell { '♪' } else { ' ' }
+            ),
+        };
+        screen.draw(
+            &mut terminal.out,
+            Frame::new(chip8, options.style),
+            bell,
+            &status,
+        )?;
+    }
+}
+
+fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    let mut chip8 = Chip8::new();
+    chip8
+        .load_rom_or_game(&options.rom, options.profile)
+        .map_err(|error| format!("{}: {}", options.rom, error))?;
+    if let Some(speed) = options.speed {
+        chip8.set_cpu_speed(speed);
+    }
+    if options.vip_timing {
+        chip8.set_timing_mode(TimingMode::CosmacVip);
+    }
+    if let Some(path) = &options.movie {
+        chip8.play_movie(&Movie::from_bytes(&fs::read(path)?)?)?;
+    }
+    if options.record.is_some() {
+        chip8.start_recording();
+    }
+    let played = play(&mut chip8, options);
+    // Keep the recording even if the game ended in an error.
+    if let Some(path) = &options.record {
+        if let Some(movie) = chip8.stop_recording() {
+            fs::write(path, movie.to_bytes())?;
+        }
+    }
+    played?;
+    io::stdout().flush()?;
+    Ok(())
+}
+
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(error) => {
+            eprintln!("chip8-tui: {}\n\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(error) = run(&options) {
+        eprintln!("chip8-tui: {}", error);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,260 @@
+use std::io::{self, Write};
+
+use chip8_core::Chip8;
+use crossterm::cursor::MoveTo;
+use crossterm::style::{Color, Print, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{Clear, ClearType};
+use crossterm::QueueableCommand;
+
+// Indexed by the bits of the two XO-CHIP planes, like the palette of the web frontend.
+const PALETTE: [Color; 4] = [Color::Black, Color::White, Color::Grey, Color::DarkGrey];
+
+// Intensities from this level up are drawn as lit, so the flicker filters still
+// apply but a fading phosphor doesn't smear across the whole screen.
+const LIT: u8 = 128;
+
+/// How pixels are packed into character cells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+    /// `▀` with the top pixel in the foreground and the bottom one in the
+    /// background: 1x2 pixels per cell, in all four colours.
+    HalfBlocks,
+    /// Braille patterns: 2x4 pixels per cell, lit in any plane or not at all.
+    Braille,
+}
+
+impl Style {
+    /// The number of pixels across and down in each cell.
+    fn cell_size(self) -> (usize, usize) {
+        match self {
+            Style::HalfBlocks => (1, 2),
+            Style::Braille => (2, 4),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub symbol: char,
+    pub foreground: u8,
+    pub background: u8,
+}
+
+/// The display as character cells, row by row.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Frame {
+    pub width: usize,
+    pub rows: Vec<Vec<Cell>>,
+}
+
+impl Frame {
+    pub fn new(chip8: &Chip8, style: Style) -> Self {
+        let (width, height) = (chip8.display_width(), chip8.display_height());
+        // Both planes always exist.
+        let planes = [0, 1].map(|plane| chip8.intensity_plane(plane).unwrap_or_default());
+        let pixel = |x: usize, y: usize| {
+            planes
+                .iter()
+                .enumerate()
+                .filter(|(_, plane)| plane[y * width + x] >= LIT)
+                .fold(0, |pixel, (index, _)| pixel | 1 << index)
+        };
+        let (cell_width, cell_height) = style.cell_size();
+        let rows = (0..height / cell_height)
+            .map(|row| {
+                let y = row * cell_height;
+                (0..width / cell_width)
+                    .map(|column| match style {
+                        Style::HalfBlocks => Cell {
+                            symbol: '▀',
+                            foreground: pixel(column, y),
+                            background: pixel(column, y + 1),
+                        },
+                        Style::Braille => braille(|dx, dy| pixel(column * 2 + dx, y + dy) != 0),
+                    })
+                    .collect()
+            })
+            .collect();
+        Frame {
+            width: width / cell_width,
+            rows,
+        }
+    }
+}
+
+// Braille dots are numbered down the left column then down the right one, except
+// for the bottom row which was added later and comes last.
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+fn braille(lit: impl Fn(usize, usize) -> bool) -> Cell {
+    let mut dots = 0;
+    for (dy, row) in BRAILLE_DOTS.iter().enumerate() {
+        for (dx, dot) in row.iter().enumerate() {
+            if lit(dx, dy) {
+                dots |= dot;
+            }
+        }
+    }
+    Cell {
+        symbol: core::char::from_u32(0x2800 + dots).unwrap(),
+        foreground: 1,
+        background: 0,
+    }
+}
+
+/// Draws frames inside a border, rewriting only the rows that changed since the
+/// last one so that playing over a slow connection stays smooth.
+#[derive(Default)]
+pub struct Screen {
+    shown: Frame,
+    bell: bool,
+    status: String,
+}
+
+impl Screen {
+    /// Forgets what is on the terminal, so the next draw repaints everything.
+    pub fn invalidate(&mut self) {
+        self.shown = Frame::default();
+    }
+
+    /// Draws `frame`, with the border lit up if `bell` is ringing and `status` below.
+    pub fn draw(
+        &mut self,
+        out: &mut impl Write,
+        frame: Frame,
+        bell: bool,
+        status: &str,
+    ) -> io::Result<()> {
+        let repaint = frame.width != self.shown.width || frame.rows.len() != self.shown.rows.len();
+        if repaint {
+            out.queue(SetBackgroundColor(Color::Reset))?
+                .queue(Clear(ClearType::All))?;
+        }
+        let height = frame.rows.len() as u16;
+        if repaint || bell != self.bell {
+            self.draw_border(out, frame.width as u16, height, bell)?;
+        }
+        for (y, row) in frame.rows.iter().enumerate() {
+            if !repaint && self.shown.rows[y] == *row {
+                continue;
+            }
+            out.queue(MoveTo(1, y as u16 + 1))?;
+            let mut colours = None;
+            for cell in row {
+                if colours != Some((cell.foreground, cell.background)) {
+                    colours = Some((cell.foreground, cell.background));
+                    out.queue(SetForegroundColor(PALETTE[cell.foreground as usize]))?
+                        .queue(SetBackgroundColor(PALETTE[cell.background as usize]))?;
+                }
+                out.queue(Print(cell.symbol))?;
+            }
+        }
+        if repaint || status != self.status {
+            out.queue(MoveTo(0, height + 2))?
+                .queue(SetForegroundColor(Color::Reset))?
+                .queue(SetBackgroundColor(Color::Reset))?
+                .queue(Clear(ClearType::CurrentLine))?
+                .queue(Print(status))?;
+            self.status = status.to_string();
+        }
+        self.shown = frame;
+        self.bell = bell;
+        out.flush()
+    }
+
+    fn draw_border(
+        &self,
+        out: &mut impl Write,
+        width: u16,
+        height: u16,
+        bell: bool,
+    ) -> io::Result<()> {
+        let colour = if bell { Color::Yellow } else { Color::DarkGrey };
+        let line = "─".repeat(width as usize);
+        out.queue(SetForegroundColor(colour))?
+            .queue(SetBackgroundColor(Color::Reset))?
+            .queue(MoveTo(0, 0))?
+            .queue(Print(format!("┌{}┐", line)))?;
+        for y in 1..=height {
+            out.queue(MoveTo(0, y))?
+                .queue(Print('│'))?
+                .queue(MoveTo(width + 1, y))?
+                .queue(Print('│'))?;
+        }
+        out.queue(MoveTo(0, height + 1))?
+            .queue(Print(format!("└{}┘", line)))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::games::DEFAULT_QUIRKS;
+    use chip8_core::FlickerFilter;
+
+    // Draws the 0 font glyph at the top left corner, then loops.
+    fn draw_zero() -> Chip8 {
+        let mut chip8 = Chip8::with_seed(1);
+        chip8
+            .load_rom(
+                &[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06],
+                DEFAULT_QUIRKS,
+            )
+            .unwrap();
+        chip8.set_flicker_filter(FlickerFilter::Off);
+        chip8.run_frame().unwrap();
+        chip8
+    }
+
+    #[test]
+    fn packs_two_pixels_into_half_blocks() {
+        let frame = Frame::new(&draw_zero(), Style::HalfBlocks);
+        assert_eq!(frame.width, 64);
+        assert_eq!(frame.rows.len(), 16);
+        // The glyph's rows are 1111, 1001, 1001, 1001 and 1111.
+        let colours = |row: &[Cell]| -> Vec<(u8, u8)> {
+            row[..4]
+                .iter()
+                .map(|cell| (cell.foreground, cell.background))
+                .collect()
+        };
+        assert_eq!(colours(&frame.rows[0]), [(1, 1), (1, 0), (1, 0), (1, 1)]);
+        assert_eq!(colours(&frame.rows[1]), [(1, 1), (0, 0), (0, 0), (1, 1)]);
+        assert_eq!(colours(&frame.rows[2]), [(1, 0), (1, 0), (1, 0), (1, 0)]);
+        assert_eq!(frame.rows[3][0].foreground, 0);
+    }
+
+    #[test]
+    fn packs_eight_pixels_into_braille() {
+        let frame = Frame::new(&draw_zero(), Style::Braille);
+        assert_eq!(frame.width, 32);
+        assert_eq!(frame.rows.len(), 8);
+        let symbols: String = frame.rows[0][..3].iter().map(|cell| cell.symbol).collect();
+        let second: String = frame.rows[1][..2].iter().map(|cell| cell.symbol).collect();
+        assert_eq!(symbols, "⡏⢹⠀");
+        assert_eq!(second, "⠉⠉");
+    }
+
+    #[test]
+    fn redraws_only_changed_rows() {
+        let chip8 = draw_zero();
+        let mut screen = Screen::default();
+        let mut out = Vec::new();
+        screen
+            .draw(&mut out, Frame::new(&chip8, Style::HalfBlocks), false, "")
+            .unwrap();
+        assert!(!out.is_empty());
+
+        out.clear();
+        screen
+            .draw(&mut out, Frame::new(&chip8, Style::HalfBlocks), false, "")
+            .unwrap();
+        assert!(out.is_empty());
+
+        screen
+            .draw(&mut out, Frame::new(&chip8, Style::HalfBlocks), true, "")
+            .unwrap();
+        assert!(String::from_utf8(out).unwrap().contains('┌'));
+    }
+}
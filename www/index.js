@@ -1,58 +1,124 @@
-import { Chip8 } from "wasm-chip8";
+import { Chip8, FlickerFilter } from "wasm-chip8";
 import { memory } from "wasm-chip8/wasm_chip8_bg";
 
-const WHITE = "#FFFFFF";
-const BLACK = "#000000";
+// Indexed by the bits of the two XO-CHIP planes: plane 1 is the low bit, plane 2 the high bit.
+const PALETTE = ["#000000", "#FFFFFF", "#AAAAAA", "#555555"];
 const PIXEL_SIZE = 10;
+// Size of the low resolution display, high resolution pixels are drawn at half the size.
 const width = 64;
 const height = 32;
 
 let chip8 = Chip8.new();
 chip8.load_rom("tetris");
+// Ten seconds of frames to rewind through while Backspace is held.
+chip8.set_rewind_history(600);
+let rewinding = false;
 
 const canvas = document.getElementById("screen");
 canvas.width = width * (PIXEL_SIZE + 10);
 canvas.height = height * (PIXEL_SIZE + 10);
 
 const ctx = canvas.getContext("2d");
+const prompt = document.getElementById("prompt");
 
-function renderLoop() {
-  for (let i = 0; i < 10; i++) {
-    //     debugger;
-    chip8.tick();
-    // debugger;
+const flickerFilter = document.getElementById("flicker-filter");
+flickerFilter.addEventListener("change", () => {
+  chip8.set_flicker_filter(FlickerFilter[flickerFilter.value]);
+});
+
+// Browsers only allow audio to start after user interaction, so the context is
+// created on the first key press.
+const audio = {
+  context: null,
+  // When the last queued buffer finishes playing.
+  queuedUntil: 0,
+
+  start() {
+    if (this.context === null) {
+      this.context = new AudioContext();
+      chip8.set_audio_sample_rate(this.context.sampleRate);
+    }
+  },
+
+  renderFrame() {
+    if (this.context === null) {
+      return;
+    }
+    const samples = new Float32Array(Math.round(this.context.sampleRate / 60));
+    chip8.render_audio(samples);
+    const buffer = this.context.createBuffer(1, samples.length, this.context.sampleRate);
+    buffer.copyToChannel(samples, 0);
+    const source = this.context.createBufferSource();
+    source.buffer = buffer;
+    source.connect(this.context.destination);
+    this.queuedUntil = Math.max(this.queuedUntil, this.context.currentTime);
+    source.start(this.queuedUntil);
+    this.queuedUntil += buffer.duration;
+  },
+};
+
+// Timestamp of the previous animation frame, the emulator runs for the time in between.
+let lastTimestamp = null;
+
+function renderLoop(timestamp) {
+  const elapsed = lastTimestamp === null ? 0 : timestamp - lastTimestamp;
+  lastTimestamp = timestamp;
+  if (rewinding) {
+    // Back one frame per animation frame, so the game plays backwards at about its own pace.
+    chip8.rewind(1);
+    drawPixels();
+    requestAnimationFrame(renderLoop);
+    return;
+  }
+  let frames;
+  try {
+    frames = chip8.run_for(elapsed);
+  } catch (error) {
+    // The machine halts on the first fault; keep the last frame on screen.
+    const pc = error.pc.toString(16).padStart(4, "0");
+    const opcode = error.opcode.toString(16).padStart(4, "0");
+    console.error(`${error.code} at ${pc} (opcode ${opcode}): ${error.message}`);
+    drawPixels();
+    return;
   }
-  chip8.decrement_timers();
+  for (let i = 0; i < frames; i++) {
+    audio.renderFrame();
+  }
+  prompt.hidden = !chip8.is_waiting_for_key();
   drawPixels();
   fps.render();
   requestAnimationFrame(renderLoop);
 }
 
 function drawPixels() {
-  const displayPtr = chip8.display_buffer_ptr();
-  const display_size = chip8.display_buffer_size();
-  const pixels = new Uint8Array(memory.buffer, displayPtr, display_size);
-
-  ctx.beginPath();
-  for (let row = 0; row < height; row++) {
-    for (let col = 0; col < width; col++) {
-      const idx = getIndex(row, col);
-
-      ctx.fillStyle = pixelIsSet(idx, pixels) ? WHITE : BLACK;
-      ctx.fillRect(col * PIXEL_SIZE, row * PIXEL_SIZE, PIXEL_SIZE, PIXEL_SIZE);
+  const size = chip8.intensity_buffer_size();
+  const firstPlane = new Uint8Array(memory.buffer, chip8.intensity_buffer_ptr(0), size);
+  const secondPlane = new Uint8Array(memory.buffer, chip8.intensity_buffer_ptr(1), size);
+  const displayWidth = chip8.display_width();
+  const displayHeight = chip8.display_height();
+  const pixelSize = (PIXEL_SIZE * width) / displayWidth;
+
+  ctx.fillStyle = PALETTE[0];
+  ctx.fillRect(0, 0, canvas.width, canvas.height);
+  for (let row = 0; row < displayHeight; row++) {
+    for (let col = 0; col < displayWidth; col++) {
+      const idx = getIndex(row, col, displayWidth);
+      const first = firstPlane[idx];
+      const second = secondPlane[idx];
+      if (first === 0 && second === 0) {
+        continue;
+      }
+      // Planes fading out with phosphor decay are drawn translucent.
+      ctx.globalAlpha = Math.max(first, second) / 255;
+      ctx.fillStyle = PALETTE[(first > 0) | ((second > 0) << 1)];
+      ctx.fillRect(col * pixelSize, row * pixelSize, pixelSize, pixelSize);
     }
   }
-  ctx.stroke();
+  ctx.globalAlpha = 1;
 }
 
-function getIndex(row, col) {
-  return row * width + col;
-}
-
-function pixelIsSet(idx, pixels) {
-  let byte = Math.floor(idx / 8);
-  let mask = 0b10000000 >> idx % 8;
-  return (pixels[byte] & mask) === mask;
+function getIndex(row, col, displayWidth) {
+  return row * displayWidth + col;
 }
 
 function translate_key(keycode) {
@@ -78,6 +144,12 @@ function translate_key(keycode) {
 }
 
 window.addEventListener("keydown", function (event) {
+    audio.start();
+    if (event.code === "Backspace") {
+        rewinding = true;
+        event.preventDefault();
+        return;
+    }
     let key = translate_key(event.code);
     if (typeof key !== 'undefined') {
         chip8.press_key(translate_key(event.code));
@@ -85,6 +157,10 @@ window.addEventListener("keydown", function (event) {
 });
 
 window.addEventListener("keyup", function (event) {
+    if (event.code === "Backspace") {
+        rewinding = false;
+        return;
+    }
     let key = translate_key(event.code);
     if (typeof key !== 'undefined') {
         chip8.release_key(translate_key(event.code));
@@ -134,4 +210,4 @@ max of last 100 = ${Math.round(max)}
   }
 };
 
-renderLoop();
+requestAnimationFrame(renderLoop);